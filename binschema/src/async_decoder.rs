@@ -0,0 +1,472 @@
+
+use crate::{
+    error::{
+        Result,
+        Error,
+        ErrorKind,
+        error,
+        bail,
+    },
+    coder::coder::CoderState,
+    var_len::{
+        read_var_len_uint,
+        read_var_len_sint,
+        read_ord,
+        ord_byte_len,
+    },
+    schema::Schema,
+    limits::{
+        DecodeLimits,
+        LimitExceeded,
+    },
+};
+use std::{
+    io,
+    mem::size_of,
+};
+use tokio::io::{
+    AsyncRead,
+    AsyncReadExt,
+};
+
+
+/// Max number of bytes in an encoded var len int.
+const MAX_VAR_LEN_BYTES: usize = 19;
+
+/// Decodes a value from a `tokio::io::AsyncRead` comforming to a schema.
+/// Mirrors `Decoder`, but methods which read are async. Resolving and
+/// skipping are not supported.
+///
+/// Reads are small and frequent, so `R` should generally be buffered.
+pub struct AsyncDecoder<'a, 'b, R> {
+    state: &'b mut CoderState<'a>,
+    read: &'b mut R,
+    pos: u64,
+    limits: DecodeLimits,
+}
+
+impl<'a, 'b, R> AsyncDecoder<'a, 'b, R> {
+    pub fn new(state: &'b mut CoderState<'a>, read: &'b mut R) -> Self {
+        AsyncDecoder {
+            state,
+            read,
+            pos: 0,
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// See `Decoder::set_limits`.
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// See `Decoder::position`.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// See `Decoder::need`.
+    pub fn need(&self) -> Result<&'a Schema> {
+        self.state.need()
+    }
+
+    pub fn coder_state(&self) -> &CoderState<'a> {
+        &*self.state
+    }
+
+    /// Mark broken and construct an `ErrorKind::LimitExceeded` error.
+    fn limit_exceeded(&mut self, limit: LimitExceeded) -> Error {
+        self.state.mark_broken();
+        Error::new(ErrorKind::LimitExceeded, limit, Some(self.coder_state()))
+    }
+
+    /// Fail if reading `n` more bytes would exceed the total bytes limit.
+    fn check_budget(&mut self, n: usize) -> Result<()> {
+        match self.limits.max_total_bytes {
+            Some(max) if self.pos.saturating_add(n as u64) > max => Err(
+                self.limit_exceeded(LimitExceeded::TotalBytes(max))
+            ),
+            _ => Ok(()),
+        }
+    }
+
+    fn check_depth(&mut self) -> Result<()> {
+        self.limits
+            .check_depth(self.state.depth())
+            .map_err(|limit| self.limit_exceeded(limit))
+    }
+}
+
+macro_rules! decode_le_bytes {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            let mut buf = [0; size_of::<$t>()];
+            self.read_exact(&mut buf).await?;
+            Ok($t::from_le_bytes(buf))
+        }
+    )*};
+}
+
+macro_rules! decode_var_len_uint {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            let n = self.read_var_len_uint().await?;
+            $t::try_from(n)
+                .map_err(|_| error!(
+                    MalformedData,
+                    Some(self.coder_state()),
+                    concat!(
+                        "{} out of range for a ",
+                        stringify!($t),
+                    ),
+                    n,
+                ))
+                .map_err(|e| self.state.broken(e))
+        }
+    )*};
+}
+
+macro_rules! decode_var_len_sint {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            let buf = self.read_var_len_bytes().await?;
+            let n = read_var_len_sint(&mut &buf[..])
+                .map_err(|e| self.state.broken(e))?;
+            $t::try_from(n)
+                .map_err(|_| error!(
+                    MalformedData,
+                    Some(self.coder_state()),
+                    concat!(
+                        "{} out of range for a ",
+                        stringify!($t),
+                    ),
+                    n,
+                ))
+                .map_err(|e| self.state.broken(e))
+        }
+    )*};
+}
+
+impl<'a, 'b, R: AsyncRead + Unpin> AsyncDecoder<'a, 'b, R> {
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.check_budget(buf.len())?;
+        self.read
+            .read_exact(buf)
+            .await
+            .map_err(|e| self.state.broken(e))?;
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+
+    /// Read the bytes of a var len int, up to and including the byte without
+    /// the more bit, so they can be parsed with the sync var len functions.
+    async fn read_var_len_bytes(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        while buf.len() < MAX_VAR_LEN_BYTES {
+            let mut byte = [0];
+            self.read_exact(&mut byte).await?;
+            buf.push(byte[0]);
+            if byte[0] & 0b10000000 == 0 {
+                break;
+            }
+        }
+        Ok(buf)
+    }
+
+    async fn read_var_len_uint(&mut self) -> Result<u128> {
+        let buf = self.read_var_len_bytes().await?;
+        read_var_len_uint(&mut &buf[..])
+            .map_err(|e| self.state.broken(e))
+    }
+
+    /// Read a varlen-encoded usize.
+    async fn read_len(&mut self) -> Result<usize> {
+        let n = self.read_var_len_uint().await?;
+        usize::try_from(n)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(self.coder_state()),
+                "{} out of range for a usize",
+                n,
+            ))
+            .map_err(|e| self.state.broken(e))
+    }
+
+    /// Read a str or bytes len, then that many bytes into `buf`.
+    async fn read_str_or_bytes(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        let len = self.read_len().await?;
+        if let Err(limit) = self.limits.check_str_bytes_len(len) {
+            return Err(self.limit_exceeded(limit));
+        }
+        self.check_budget(len)?;
+        // grow buf only as bytes actually arrive
+        let n = (&mut *self.read)
+            .take(len as u64)
+            .read_to_end(buf)
+            .await
+            .map_err(|e| self.state.broken(e))?;
+        self.pos += n as u64;
+        if n < len {
+            let e = io::Error::from(io::ErrorKind::UnexpectedEof);
+            return Err(self.state.broken(e));
+        }
+        Ok(())
+    }
+
+    decode_le_bytes!(
+        decode_u8(u8) code_u8,
+        decode_u16(u16) code_u16,
+        decode_i8(i8) code_i8,
+        decode_i16(i16) code_i16,
+        decode_f32(f32) code_f32,
+        decode_f64(f64) code_f64,
+    );
+
+    decode_var_len_uint!(
+        decode_u32(u32) code_u32,
+        decode_u64(u64) code_u64,
+        decode_u128(u128) code_u128,
+    );
+
+    decode_var_len_sint!(
+        decode_i32(i32) code_i32,
+        decode_i64(i64) code_i64,
+        decode_i128(i128) code_i128,
+    );
+
+    pub async fn decode_char(&mut self) -> Result<char> {
+        self.state.set_offset(self.position());
+        self.state.code_char()?;
+        let n = self.read_var_len_uint().await?;
+        let n = u32::try_from(n)
+            .map_err(|_| error!(
+                MalformedData,
+                Some(self.coder_state()),
+                "{} out of range for a char",
+                n,
+            ))
+            .map_err(|e| self.state.broken(e))?;
+        char::from_u32(n)
+            .ok_or_else(|| error!(
+                MalformedData,
+                Some(self.coder_state()),
+                "{} is not a valid char",
+                n
+            ))
+    }
+
+    pub async fn decode_bool(&mut self) -> Result<bool> {
+        self.state.set_offset(self.position());
+        self.state.code_bool()?;
+        let mut buf = [0];
+        self.read_exact(&mut buf).await?;
+        match buf[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            n => Err(error!(
+                MalformedData,
+                Some(self.coder_state()),
+                "{} is not a valid bool",
+                n,
+            )),
+        }
+    }
+
+    pub fn decode_unit(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_unit()?;
+        Ok(())
+    }
+
+    /// Clear `buf` and decode a str into it.
+    pub async fn decode_str_into(&mut self, buf: &mut String) -> Result<()> {
+        self.state.set_offset(self.position());
+        buf.clear();
+        self.state.code_str()?;
+        let mut bbuf = std::mem::take(buf).into_bytes();
+        let result = self.read_str_or_bytes(&mut bbuf).await;
+        if let Err(e) = result {
+            bbuf.clear();
+            *buf = String::from_utf8(bbuf).unwrap();
+            return Err(e);
+        }
+        match String::from_utf8(bbuf) {
+            Ok(s) => {
+                *buf = s;
+                Ok(())
+            }
+            Err(e) => {
+                self.state.mark_broken();
+                let mut bbuf = e.into_bytes();
+                bbuf.clear();
+                *buf = String::from_utf8(bbuf).unwrap();
+                Err(error!(
+                    MalformedData, Some(self.coder_state()), "non UTF8 str bytes",
+                ))
+            }
+        }
+    }
+
+    /// Decode a str into a new alloc.
+    pub async fn decode_str(&mut self) -> Result<String> {
+        let mut buf = String::new();
+        self.decode_str_into(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// Clear `buf` and decode a bytes into it.
+    pub async fn decode_bytes_into(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        self.state.set_offset(self.position());
+        buf.clear();
+        self.state.code_bytes()?;
+        let result = self.read_str_or_bytes(buf).await;
+        if result.is_err() {
+            buf.clear();
+        }
+        result
+    }
+
+    /// Decode a bytes into a new alloc.
+    pub async fn decode_bytes(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.decode_bytes_into(&mut buf).await?;
+        Ok(buf)
+    }
+
+    /// See `Decoder::begin_option`.
+    pub async fn begin_option(&mut self) -> Result<bool> {
+        self.state.set_offset(self.position());
+        self.state.begin_option()?;
+        let mut buf = [0];
+        self.read_exact(&mut buf).await?;
+        let is_some =
+            match buf[0] {
+                0 => false,
+                1 => true,
+                n => bail!(
+                    MalformedData,
+                    Some(self.coder_state()),
+                    "{} is not a valid option someness",
+                    n,
+                ),
+            };
+        if is_some {
+            self.state.set_option_some()?;
+            self.check_depth()?;
+        } else {
+            self.state.set_option_none();
+        }
+        Ok(is_some)
+    }
+
+    /// See `Decoder::begin_fixed_len_seq`.
+    pub fn begin_fixed_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_fixed_len_seq(len)?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_var_len_seq`.
+    pub async fn begin_var_len_seq(&mut self) -> Result<usize> {
+        self.state.set_offset(self.position());
+        self.state.begin_var_len_seq()?;
+        let len = self.read_len().await?;
+        if let Err(limit) = self.limits.check_seq_len(len) {
+            return Err(self.limit_exceeded(limit));
+        }
+        self.state.set_var_len_seq_len(len);
+        Ok(len)
+    }
+
+    /// See `Decoder::begin_seq_elem`.
+    pub fn begin_seq_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_seq_elem()?;
+        self.check_depth()
+    }
+
+    /// See `Decoder::finish_seq`.
+    pub fn finish_seq(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_seq()?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_tuple`.
+    pub fn begin_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_tuple()?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_tuple_elem`.
+    pub fn begin_tuple_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_tuple_elem()?;
+        self.check_depth()
+    }
+
+    /// See `Decoder::finish_tuple`.
+    pub fn finish_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_tuple()?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_struct`.
+    pub fn begin_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_struct()?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_struct_field`.
+    pub fn begin_struct_field(&mut self, name: &str) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_struct_field(name)?;
+        self.check_depth()
+    }
+
+    /// See `Decoder::finish_struct`.
+    pub fn finish_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_struct()?;
+        Ok(())
+    }
+
+    /// See `Decoder::begin_enum`.
+    pub async fn begin_enum(&mut self) -> Result<usize> {
+        self.state.set_offset(self.position());
+        let num_variants = self.state.begin_enum()?;
+        let mut buf = [0; 8];
+        let byte_len = if num_variants > 0 {
+            ord_byte_len(num_variants - 1)
+        } else {
+            0
+        };
+        self.read_exact(&mut buf[..byte_len]).await?;
+        let variant_ord = read_ord(&mut &buf[..byte_len], num_variants)
+            .map_err(|e| self.state.broken(e))?;
+        self.state
+            .begin_enum_variant_ord(variant_ord)
+            .map_err(|e| self.state.broken(e))?;
+        Ok(variant_ord)
+    }
+
+    /// See `Decoder::begin_enum_variant`.
+    pub fn begin_enum_variant(&mut self, name: &str) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_enum_variant_name(name)?;
+        self.check_depth()
+    }
+}
@@ -0,0 +1,266 @@
+
+use crate::{
+    error::Result,
+    do_if_err::DoIfErr,
+    coder::coder::CoderState,
+    var_len::{
+        write_var_len_uint,
+        write_var_len_sint,
+        write_ord,
+    },
+    schema::Schema,
+};
+use tokio::io::{
+    AsyncWrite,
+    AsyncWriteExt,
+};
+
+
+/// Encodes a value to a `tokio::io::AsyncWrite` comforming to a schema.
+/// Mirrors `Encoder`, but methods which write are async.
+///
+/// Writes are small and frequent, so `W` should generally be buffered.
+pub struct AsyncEncoder<'a, 'b, W> {
+    state: &'b mut CoderState<'a>,
+    write: &'b mut W,
+    pos: u64,
+}
+
+impl<'a, 'b, W> AsyncEncoder<'a, 'b, W> {
+    pub fn new(state: &'b mut CoderState<'a>, write: &'b mut W) -> Self {
+        AsyncEncoder {
+            state,
+            write,
+            pos: 0,
+        }
+    }
+
+    /// See `Encoder::position`.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// See `Encoder::need`.
+    pub fn need(&self) -> Result<&'a Schema> {
+        self.state.need()
+    }
+
+    pub fn coder_state(&self) -> &CoderState<'a> {
+        &*self.state
+    }
+}
+
+macro_rules! encode_le_bytes {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            self.write(&n.to_le_bytes()).await?;
+            Ok(())
+        }
+    )*};
+}
+
+macro_rules! encode_var_len_uint {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            self.write_var_len_uint(n as u128).await?;
+            Ok(())
+        }
+    )*};
+}
+
+macro_rules! encode_var_len_sint {
+    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+        pub async fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
+            self.state.$c()?;
+            let mut buf = Vec::new();
+            write_var_len_sint(&mut buf, n as i128).unwrap();
+            self.write(&buf).await?;
+            Ok(())
+        }
+    )*};
+}
+
+impl<'a, 'b, W: AsyncWrite + Unpin> AsyncEncoder<'a, 'b, W> {
+    async fn write(&mut self, b: &[u8]) -> Result<()> {
+        self.write
+            .write_all(b)
+            .await
+            .map_err(|e| self.state.broken(e))?;
+        self.pos += b.len() as u64;
+        Ok(())
+    }
+
+    async fn write_var_len_uint(&mut self, n: u128) -> Result<()> {
+        let mut buf = Vec::new();
+        write_var_len_uint(&mut buf, n).unwrap();
+        self.write(&buf).await
+    }
+
+    encode_le_bytes!(
+        encode_u8(u8) code_u8,
+        encode_u16(u16) code_u16,
+        encode_i8(i8) code_i8,
+        encode_i16(i16) code_i16,
+        encode_f32(f32) code_f32,
+        encode_f64(f64) code_f64,
+    );
+
+    encode_var_len_uint!(
+        encode_u32(u32) code_u32,
+        encode_u64(u64) code_u64,
+        encode_u128(u128) code_u128,
+        encode_char(char) code_char,
+    );
+
+    encode_var_len_sint!(
+        encode_i32(i32) code_i32,
+        encode_i64(i64) code_i64,
+        encode_i128(i128) code_i128,
+    );
+
+    pub async fn encode_bool(&mut self, b: bool) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_bool()?;
+        self.write(&[b as u8]).await?;
+        Ok(())
+    }
+
+    pub fn encode_unit(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_unit()?;
+        Ok(())
+    }
+
+    pub async fn encode_str(&mut self, s: &str) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_str()?;
+        self.write_var_len_uint(s.len() as u128).await?;
+        self.write(s.as_bytes()).await?;
+        Ok(())
+    }
+
+    pub async fn encode_bytes(&mut self, s: &[u8]) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_bytes()?;
+        self.write_var_len_uint(s.len() as u128).await?;
+        self.write(s).await?;
+        Ok(())
+    }
+
+    /// Completely encode an option none value.
+    pub async fn encode_none(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_option()?;
+        self.state.set_option_none();
+        self.write(&[0]).await?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_some`.
+    pub async fn begin_some(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_option()?;
+        self.state.set_option_some()?;
+        self.write(&[1]).await?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_fixed_len_seq`.
+    pub fn begin_fixed_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_fixed_len_seq(len)?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_var_len_seq`.
+    pub async fn begin_var_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_var_len_seq()?;
+        self.state.set_var_len_seq_len(len);
+        self.write_var_len_uint(len as u128).await?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_seq_elem`.
+    pub fn begin_seq_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_seq_elem()?;
+        Ok(())
+    }
+
+    /// See `Encoder::finish_seq`.
+    pub fn finish_seq(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_seq()?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_tuple`.
+    pub fn begin_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_tuple()?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_tuple_elem`.
+    pub fn begin_tuple_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_tuple_elem()?;
+        Ok(())
+    }
+
+    /// See `Encoder::finish_tuple`.
+    pub fn finish_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_tuple()?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_struct`.
+    pub fn begin_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_struct()?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_struct_field`.
+    pub fn begin_struct_field(&mut self, name: &str) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.begin_struct_field(name)?;
+        Ok(())
+    }
+
+    /// See `Encoder::finish_struct`.
+    pub fn finish_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.finish_struct()?;
+        Ok(())
+    }
+
+    /// See `Encoder::begin_enum`.
+    pub async fn begin_enum(
+        &mut self,
+        variant_ord: usize,
+        variant_name: &str,
+    ) -> Result<()> {
+        self.state.set_offset(self.position());
+        // same all-or-nothing state change as the sync encoder
+        let num_variants = self.state.begin_enum()?;
+        self.state
+            .begin_enum_variant_ord(variant_ord)
+            .do_if_err(|| self.state.cancel_enum())?;
+        self.state
+            .begin_enum_variant_name(variant_name)
+            .do_if_err(|| self.state.cancel_enum())?;
+
+        let mut buf = Vec::new();
+        write_ord(&mut buf, variant_ord, num_variants).unwrap();
+        self.write(&buf).await?;
+        Ok(())
+    }
+}
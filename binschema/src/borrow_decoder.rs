@@ -0,0 +1,145 @@
+use crate::{
+    error::{
+        Result,
+        Error,
+        error,
+    },
+    coder::coder::CoderState,
+    schema::Schema,
+    Decoder,
+};
+use std::{
+    borrow::Cow,
+    io,
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    str::from_utf8,
+};
+
+
+/// Decodes a value from a byte slice comforming to a schema. Derefs to a
+/// `Decoder`, but as a `serde::Deserializer` visits strs and bytes with
+/// `visit_borrowed_str` and `visit_borrowed_bytes`, such that
+/// `#[serde(borrow)]` fields can be decoded without allocating.
+pub struct BorrowDecoder<'a, 'b, 'de> {
+    decoder: Decoder<'a, 'b, &'de [u8]>,
+}
+
+impl<'a, 'b, 'de> BorrowDecoder<'a, 'b, 'de> {
+    pub fn new(state: &'b mut CoderState<'a>, read: &'b mut &'de [u8]) -> Self {
+        BorrowDecoder {
+            decoder: Decoder::new(state, read),
+        }
+    }
+
+    /// See `Decoder::new_resolving`.
+    pub fn new_resolving(
+        state: &'b mut CoderState<'a>,
+        writer: &'a Schema,
+        read: &'b mut &'de [u8],
+    ) -> Self {
+        BorrowDecoder {
+            decoder: Decoder::new_resolving(state, writer, read),
+        }
+    }
+
+    pub fn into_inner(self) -> Decoder<'a, 'b, &'de [u8]> {
+        self.decoder
+    }
+}
+
+impl<'a, 'b, 'de> Deref for BorrowDecoder<'a, 'b, 'de> {
+    type Target = Decoder<'a, 'b, &'de [u8]>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.decoder
+    }
+}
+
+impl<'a, 'b, 'de> DerefMut for BorrowDecoder<'a, 'b, 'de> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.decoder
+    }
+}
+
+impl<'a, 'b, 'de> Decoder<'a, 'b, &'de [u8]> {
+    /// Take `len` bytes off the front of the input slice.
+    pub(crate) fn split_borrowed(&mut self, len: usize) -> Result<&'de [u8]> {
+        let slice = *self.read.inner;
+        if len > slice.len() {
+            let e = io::Error::from(io::ErrorKind::UnexpectedEof);
+            return Err(self.state.broken(e));
+        }
+        self.read
+            .advance(len)
+            .map_err(|e| self.state.broken(e))?;
+        let (bytes, rest) = slice.split_at(len);
+        *self.read.inner = rest;
+        Ok(bytes)
+    }
+
+    /// Decode a str or bytes, borrowing it from the input slice if possible.
+    /// Only fails to borrow when resolving, and the writer wrote the value
+    /// out of the order the reader needs it in.
+    pub(crate) fn decode_cow(&mut self, is_str: bool) -> Result<Cow<'de, [u8]>> {
+        self.state.set_offset(self.position());
+        if is_str {
+            self.state.code_str()?;
+        } else {
+            self.state.code_bytes()?;
+        }
+        let bytes =
+            if self.resolver.is_some() {
+                self.resolving_decode_cow(is_str)?
+            } else {
+                let len = self.read_len()?;
+                self.check_str_bytes_len(len)?;
+                Cow::Borrowed(self.split_borrowed(len)?)
+            };
+        if is_str && from_utf8(&bytes).is_err() {
+            self.state.mark_broken();
+            return Err(error!(
+                MalformedData, Some(self.coder_state()), "non UTF8 str bytes",
+            ));
+        }
+        Ok(bytes)
+    }
+
+    /// Decode a str as a reference into the input slice, without allocating.
+    pub fn decode_borrowed_str(&mut self) -> Result<&'de str> {
+        match self.decode_cow(true)? {
+            // already validated
+            Cow::Borrowed(bytes) => Ok(from_utf8(bytes).unwrap()),
+            Cow::Owned(_) => Err(self.cannot_borrow()),
+        }
+    }
+
+    /// Decode a bytes as a reference into the input slice, without
+    /// allocating.
+    pub fn decode_borrowed_bytes(&mut self) -> Result<&'de [u8]> {
+        match self.decode_cow(false)? {
+            Cow::Borrowed(bytes) => Ok(bytes),
+            Cow::Owned(_) => Err(self.cannot_borrow()),
+        }
+    }
+
+    fn cannot_borrow(&mut self) -> Error {
+        self.state.mark_broken();
+        error!(
+            ApiUsage,
+            Some(self.coder_state()),
+            "cannot borrow value which writer wrote out of order",
+        )
+    }
+}
+
+/// Convert bytes returned by `decode_cow(true)` into str.
+pub(crate) fn cow_bytes_into_str(bytes: Cow<[u8]>) -> Cow<str> {
+    // callers already validated
+    match bytes {
+        Cow::Borrowed(bytes) => Cow::Borrowed(from_utf8(bytes).unwrap()),
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8(bytes).unwrap()),
+    }
+}
@@ -0,0 +1,163 @@
+//! Byte buffer newtype which is coded as a bytes rather than as a seq of u8.
+
+use crate::{
+    error::Result,
+    schema::{
+        Schema,
+        schema,
+    },
+    Encoder,
+    Decoder,
+    KnownSchema,
+    RecurseStack,
+    Encode,
+    Decode,
+};
+use std::{
+    io::{
+        Read,
+        Write,
+    },
+    ops::{
+        Deref,
+        DerefMut,
+    },
+    fmt::{self, Formatter},
+};
+use serde::{
+    ser::{
+        Serialize,
+        Serializer,
+    },
+    de::{
+        Deserialize,
+        Deserializer,
+        Visitor,
+        SeqAccess,
+    },
+};
+
+
+/// Owned byte buffer with the schema `bytes`, whereas a `Vec<u8>` has the
+/// schema `seq(varlen)(u8)`. Serializes with `serialize_bytes`, the same as
+/// `serde_bytes::ByteBuf`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Bytes(pub Vec<u8>);
+
+impl Bytes {
+    pub fn new() -> Self {
+        Bytes(Vec::new())
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl Deref for Bytes {
+    type Target = Vec<u8>;
+
+    fn deref(&self) -> &Vec<u8> {
+        &self.0
+    }
+}
+
+impl DerefMut for Bytes {
+    fn deref_mut(&mut self) -> &mut Vec<u8> {
+        &mut self.0
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl AsMut<[u8]> for Bytes {
+    fn as_mut(&mut self) -> &mut [u8] {
+        &mut self.0
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(buf: Vec<u8>) -> Self {
+        Bytes(buf)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Bytes {
+    fn from(buf: &'a [u8]) -> Self {
+        Bytes(buf.to_vec())
+    }
+}
+
+impl From<Bytes> for Vec<u8> {
+    fn from(bytes: Bytes) -> Self {
+        bytes.0
+    }
+}
+
+impl KnownSchema for Bytes {
+    fn schema(_: RecurseStack) -> Schema {
+        schema!(bytes)
+    }
+}
+
+impl Encode for Bytes {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_bytes(&self.0)
+    }
+}
+
+impl Decode for Bytes {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.decode_bytes().map(Bytes)
+    }
+}
+
+impl Serialize for Bytes {
+    fn serialize<S: Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        s.serialize_bytes(&self.0)
+    }
+}
+
+impl<'d> Deserialize<'d> for Bytes {
+    fn deserialize<D: Deserializer<'d>>(d: D) -> std::result::Result<Self, D::Error> {
+        d.deserialize_byte_buf(BytesVisitor)
+    }
+}
+
+struct BytesVisitor;
+
+impl<'d> Visitor<'d> for BytesVisitor {
+    type Value = Bytes;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("bytes")
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, b: &[u8]) -> std::result::Result<Bytes, E> {
+        Ok(Bytes(b.to_vec()))
+    }
+
+    fn visit_byte_buf<E: serde::de::Error>(self, b: Vec<u8>) -> std::result::Result<Bytes, E> {
+        Ok(Bytes(b))
+    }
+
+    fn visit_str<E: serde::de::Error>(self, s: &str) -> std::result::Result<Bytes, E> {
+        Ok(Bytes(s.as_bytes().to_vec()))
+    }
+
+    fn visit_string<E: serde::de::Error>(self, s: String) -> std::result::Result<Bytes, E> {
+        Ok(Bytes(s.into_bytes()))
+    }
+
+    fn visit_seq<A: SeqAccess<'d>>(self, mut seq: A) -> std::result::Result<Bytes, A::Error> {
+        let mut buf = Vec::with_capacity(usize::min(seq.size_hint().unwrap_or(0), 1024));
+        while let Some(b) = seq.next_element()? {
+            buf.push(b);
+        }
+        Ok(Bytes(buf))
+    }
+}
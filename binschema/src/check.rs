@@ -0,0 +1,315 @@
+//! Checking values against schemas without encoding them.
+
+use crate::{
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    value::Value,
+    path::{
+        Path,
+        PathSegment,
+    },
+};
+use std::fmt::{self, Formatter, Display};
+
+
+/// Way in which a value doesn't conform to a schema, found by `Value::check`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Mismatch {
+    /// Path to the non-conforming part of the value.
+    pub path: Path,
+    pub kind: MismatchKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum MismatchKind {
+    /// Scalar of the wrong scalar type.
+    ScalarType {
+        expected: ScalarType,
+        found: ScalarType,
+    },
+    /// Value of the wrong kind altogether, such as a str where a struct is
+    /// expected.
+    Kind {
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// Fixed len seq of the wrong length.
+    SeqLen {
+        expected: usize,
+        found: usize,
+    },
+    /// Tuple of the wrong number of elements.
+    TupleLen {
+        expected: usize,
+        found: usize,
+    },
+    /// Struct field which the schema has, but at a different index.
+    FieldOrder {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// Struct field which the schema doesn't have.
+    UnknownField(String),
+    /// Struct field which the schema has, but the value doesn't.
+    MissingField(String),
+    /// Enum variant name which the schema doesn't have.
+    UnknownVariant(String),
+    /// Enum `variant_ord` which disagrees with `variant_name`.
+    VariantOrd {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    /// The schema contains an unresolvable recurse.
+    IllegalRecurse,
+}
+
+impl Display for MismatchKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &MismatchKind::ScalarType { expected, found } => write!(
+                f,
+                "expected {}, found {}",
+                expected.display_str(),
+                found.display_str(),
+            ),
+            &MismatchKind::Kind { expected, found } => write!(
+                f,
+                "expected {}, found {}",
+                expected,
+                found,
+            ),
+            &MismatchKind::SeqLen { expected, found } => write!(
+                f,
+                "expected seq of len {}, found len {}",
+                expected,
+                found,
+            ),
+            &MismatchKind::TupleLen { expected, found } => write!(
+                f,
+                "expected tuple of len {}, found len {}",
+                expected,
+                found,
+            ),
+            &MismatchKind::FieldOrder { ref name, expected, found } => write!(
+                f,
+                "field {:?} should be at index {}, found at index {}",
+                name,
+                expected,
+                found,
+            ),
+            &MismatchKind::UnknownField(ref name) => write!(
+                f,
+                "unknown field {:?}",
+                name,
+            ),
+            &MismatchKind::MissingField(ref name) => write!(
+                f,
+                "missing field {:?}",
+                name,
+            ),
+            &MismatchKind::UnknownVariant(ref name) => write!(
+                f,
+                "unknown variant {:?}",
+                name,
+            ),
+            &MismatchKind::VariantOrd { ref name, expected, found } => write!(
+                f,
+                "variant {:?} has ordinal {}, found ordinal {}",
+                name,
+                expected,
+                found,
+            ),
+            &MismatchKind::IllegalRecurse => f.write_str("illegal recurse in schema"),
+        }
+    }
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl std::error::Error for Mismatch {}
+
+impl Value {
+    /// Check that this value conforms to `schema`, such that encoding it with
+    /// `encode_to` would succeed, and report every way in which it doesn't.
+    pub fn check(&self, schema: &Schema) -> Result<(), Vec<Mismatch>> {
+        let mut check = Check {
+            ancestors: Vec::new(),
+            path: Path::new(),
+            mismatches: Vec::new(),
+        };
+        check.check(self, schema);
+        if check.mismatches.is_empty() {
+            Ok(())
+        } else {
+            Err(check.mismatches)
+        }
+    }
+}
+
+fn value_kind(value: &Value) -> &'static str {
+    match value {
+        &Value::Scalar(scalar) => scalar.scalar_type().display_str(),
+        &Value::Str(_) => "str",
+        &Value::Bytes(_) => "bytes",
+        &Value::Unit => "unit",
+        &Value::Option(_) => "option",
+        &Value::FixedLenSeq(_) => "fixed len seq",
+        &Value::VarLenSeq(_) => "var len seq",
+        &Value::Tuple(_) => "tuple",
+        &Value::Struct(_) => "struct",
+        &Value::Enum(_) => "enum",
+    }
+}
+
+fn schema_kind(schema: &Schema) -> &'static str {
+    match schema {
+        &Schema::Seq(SeqSchema { len: Some(_), .. }) => "fixed len seq",
+        &Schema::Seq(SeqSchema { len: None, .. }) => "var len seq",
+        &Schema::Option(_) => "option",
+        &Schema::Tuple(_) => "tuple",
+        &Schema::Struct(_) => "struct",
+        &Schema::Enum(_) => "enum",
+        schema => schema.non_recursive_display_str(),
+    }
+}
+
+struct Check<'a> {
+    ancestors: Vec<&'a Schema>,
+    path: Path,
+    mismatches: Vec<Mismatch>,
+}
+
+impl<'a> Check<'a> {
+    fn mismatch(&mut self, kind: MismatchKind) {
+        self.mismatches.push(Mismatch {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    fn check_in(&mut self, segment: PathSegment, value: &Value, schema: &'a Schema) {
+        self.path.push(segment);
+        self.check(value, schema);
+        self.path.pop();
+    }
+
+    fn check(&mut self, value: &Value, schema: &'a Schema) {
+        let schema =
+            match schema.resolve(&self.ancestors) {
+                Some(schema) => schema,
+                None => return self.mismatch(MismatchKind::IllegalRecurse),
+            };
+        self.ancestors.push(schema);
+        match (schema, value) {
+            (&Schema::Scalar(expected), &Value::Scalar(scalar)) => {
+                let found = scalar.scalar_type();
+                if found != expected {
+                    self.mismatch(MismatchKind::ScalarType { expected, found });
+                }
+            }
+            (&Schema::Str, &Value::Str(_)) => (),
+            (&Schema::Bytes, &Value::Bytes(_)) => (),
+            (&Schema::Unit, &Value::Unit) => (),
+            (&Schema::Option(_), &Value::Option(None)) => (),
+            (&Schema::Option(ref inner), &Value::Option(Some(ref value))) => {
+                self.check_in(PathSegment::Some, value, inner);
+            }
+            (
+                &Schema::Seq(SeqSchema { len: Some(len), .. }),
+                &Value::FixedLenSeq(ref elems),
+            ) if elems.len() != len => self.mismatch(MismatchKind::SeqLen {
+                expected: len,
+                found: elems.len(),
+            }),
+            (
+                &Schema::Seq(SeqSchema { len: Some(_), ref inner }),
+                &Value::FixedLenSeq(ref elems),
+            )
+            | (
+                &Schema::Seq(SeqSchema { len: None, ref inner }),
+                &Value::VarLenSeq(ref elems),
+            ) => {
+                for (i, elem) in elems.iter().enumerate() {
+                    self.check_in(PathSegment::SeqElem(Some(i)), elem, inner);
+                }
+            }
+            (&Schema::Tuple(ref inners), &Value::Tuple(ref elems)) => {
+                if elems.len() != inners.len() {
+                    self.mismatch(MismatchKind::TupleLen {
+                        expected: inners.len(),
+                        found: elems.len(),
+                    });
+                }
+                for (i, (elem, inner)) in elems.iter().zip(inners).enumerate() {
+                    self.check_in(PathSegment::TupleElem(i), elem, inner);
+                }
+            }
+            (&Schema::Struct(ref schema_fields), &Value::Struct(ref fields)) => {
+                for (found, field) in fields.iter().enumerate() {
+                    let expected = schema_fields
+                        .iter()
+                        .position(|schema_field| schema_field.name == field.name);
+                    let Some(expected) = expected else {
+                        self.mismatch(MismatchKind::UnknownField(field.name.clone()));
+                        continue;
+                    };
+                    if expected != found {
+                        self.mismatch(MismatchKind::FieldOrder {
+                            name: field.name.clone(),
+                            expected,
+                            found,
+                        });
+                    }
+                    self.check_in(
+                        PathSegment::Field(field.name.clone()),
+                        &field.value,
+                        &schema_fields[expected].inner,
+                    );
+                }
+                for schema_field in schema_fields {
+                    if !fields.iter().any(|field| field.name == schema_field.name) {
+                        self.mismatch(MismatchKind::MissingField(schema_field.name.clone()));
+                    }
+                }
+            }
+            (&Schema::Enum(ref variants), &Value::Enum(ref enum_value)) => {
+                let expected = variants
+                    .iter()
+                    .position(|variant| variant.name == enum_value.variant_name);
+                match expected {
+                    None => self.mismatch(MismatchKind::UnknownVariant(
+                        enum_value.variant_name.clone(),
+                    )),
+                    Some(expected) => {
+                        if expected != enum_value.variant_ord {
+                            self.mismatch(MismatchKind::VariantOrd {
+                                name: enum_value.variant_name.clone(),
+                                expected,
+                                found: enum_value.variant_ord,
+                            });
+                        }
+                        self.check_in(
+                            PathSegment::Variant(enum_value.variant_name.clone()),
+                            &enum_value.value,
+                            &variants[expected].inner,
+                        );
+                    }
+                }
+            }
+            (schema, value) => self.mismatch(MismatchKind::Kind {
+                expected: schema_kind(schema),
+                found: value_kind(value),
+            }),
+        }
+        self.ancestors.pop();
+    }
+}
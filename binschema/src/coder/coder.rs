@@ -6,16 +6,22 @@ use crate::{
     do_if_err::DoIfErr,
     error::{
         Result,
+        Error,
         error,
         ensure,
         bail,
     },
     schema::{
         Schema,
+        ScalarType,
         SeqSchema,
         schema,
     },
     coder::coder_alloc::CoderStateAlloc,
+    path::{
+        Path,
+        PathSegment,
+    },
 };
 use std::{
     write,
@@ -30,6 +36,11 @@ use std::{
 pub struct CoderState<'a> {
     stack: Vec<StackFrame<'a>>,
     broken: bool,
+    /// Whether the last operation finished coding a value, in which case
+    /// errors raised now are likely about that value.
+    finished_value: bool,
+    /// Byte offset in the stream at which the current coder API call began.
+    offset: u64,
     dbg_log: Option<DbgLog<'a>>,
 }
 
@@ -106,6 +117,12 @@ enum ApiState {
     /// element is sufficient for this element to be considered finished
     /// encoding.
     AutoFinish,
+    /// An enum variant's inner value is being coded, which auto-finishes the
+    /// enum like `AutoFinish`. The corresponding `schema` must be a
+    /// `Schema::Enum`.
+    EnumVariant {
+        variant_ord: usize,
+    },
     /// An option is being coded, but its someness is uninitialized.
     OptionUninitSomeness,
     /// A sequence is being coded, but its length is uninitialized.
@@ -138,10 +155,25 @@ enum ApiState {
     }
 }
 
+/// What a `CoderState` needs next. See `CoderState::next_step`.
+#[derive(Debug, Copy, Clone)]
+pub(crate) enum NextStep<'a> {
+    /// Needs a value of this schema, never `Schema::Recurse`.
+    Need(&'a Schema),
+    SeqElem,
+    FinishSeq,
+    TupleElem,
+    FinishTuple,
+    StructField(&'a str),
+    FinishStruct,
+    Finished,
+}
+
 impl<'a> Debug for CoderState<'a> {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         f.write_fmt(format_args!("CoderState {{\n"))?;
         f.write_fmt(format_args!("    broken: {},\n", self.broken))?;
+        f.write_fmt(format_args!("    offset: {},\n", self.offset))?;
         f.write_fmt(format_args!("    stack:\n"))?;
         for (i, frame) in self.stack.iter().rev().enumerate() {
             let i = format!("{:02}", i);
@@ -175,6 +207,8 @@ impl<'a> CoderState<'a> {
         CoderState {
             stack,
             broken: false,
+            finished_value: false,
+            offset: 0,
             dbg_log: dbg_log.map(|write| DbgLog { write, indent: 0 }),
         }
     }
@@ -200,10 +234,43 @@ impl<'a> CoderState<'a> {
         self.stack.clear();
         CoderStateAlloc::from_stack(self.stack)
     }
+
+    /// Path from the root to the value currently being coded. If the last
+    /// operation finished coding a value, such as decoding a scalar which
+    /// then turned out to be malformed, this is the path to that value.
+    pub fn path(&self) -> Path {
+        let mut path = Path::new();
+        for (i, frame) in self.stack.iter().enumerate() {
+            let is_top = i + 1 == self.stack.len();
+            if is_top && !self.finished_value {
+                break;
+            }
+            let segment =
+                match (&frame.api_state, frame.schema) {
+                    (&ApiState::AutoFinish, &Schema::Option(_)) =>
+                        PathSegment::Some,
+                    (
+                        &ApiState::EnumVariant { variant_ord },
+                        &Schema::Enum(ref variants),
+                    ) => PathSegment::Variant(variants[variant_ord].name.clone()),
+                    (&ApiState::Seq { next, .. }, _) if next > 0 =>
+                        PathSegment::SeqElem(Some(next - 1)),
+                    (&ApiState::Tuple { next }, _) if next > 0 =>
+                        PathSegment::TupleElem(next - 1),
+                    (&ApiState::Struct { next }, &Schema::Struct(ref fields))
+                        if next > 0 =>
+                        PathSegment::Field(fields[next - 1].name.clone()),
+                    _ => break,
+                };
+            path.push(segment);
+        }
+        path
+    }
 }
 
 macro_rules! validate_top {
     ($self:ident, |$top:ident| $opt_ret:expr, $got:expr)=>{{
+        $self.finished_value = false;
         ensure!(
             !$self.broken,
             ApiUsage,
@@ -216,6 +283,7 @@ macro_rules! validate_top {
                 Some(ret) => ret,
                 None => match &$top.api_state {
                     &ApiState::AutoFinish => unreachable!("{:#?}", $self.stack),
+                    &ApiState::EnumVariant { .. } => unreachable!(),
                     &ApiState::OptionUninitSomeness => unreachable!(),
                     &ApiState::SeqUninitLen => unreachable!(),
                     &ApiState::Need => bail!(
@@ -360,9 +428,13 @@ impl<'a> CoderState<'a> {
     /// Pop stack frame. If this uncovers auto finish frames, pop those too.
     fn pop(&mut self) {
         self.stack.pop().unwrap();
+        self.finished_value = true;
         while matches!(
             self.stack.iter().rev().next(),
-            Some(&StackFrame { api_state: ApiState::AutoFinish, .. })
+            Some(&StackFrame {
+                api_state: ApiState::AutoFinish | ApiState::EnumVariant { .. },
+                ..
+            })
         ) {
             dbg_log_pop!(self, "auto finish");
             self.stack.pop().unwrap();
@@ -385,12 +457,126 @@ impl<'a> CoderState<'a> {
         }
     }
 
+    /// What the coder needs next, for callers which drive coding from the
+    /// schema itself rather than from a value. Fails if the coder is between
+    /// two halves of a multi-call operation, such as between `begin_option`
+    /// and `set_option_some`.
+    pub(crate) fn next_step(&self) -> Result<NextStep<'a>> {
+        ensure!(
+            !self.broken,
+            ApiUsage,
+            Some(self),
+            "usage after IO error",
+        );
+        let frame =
+            match self.stack.iter().rev().next() {
+                Some(frame) => frame,
+                None => return Ok(NextStep::Finished),
+            };
+        Ok(match (&frame.api_state, frame.schema) {
+            (&ApiState::Need, schema) => NextStep::Need(schema),
+            (&ApiState::Seq { len, next }, _) =>
+                if next < len {
+                    NextStep::SeqElem
+                } else {
+                    NextStep::FinishSeq
+                },
+            (&ApiState::Tuple { next }, &Schema::Tuple(ref inners)) =>
+                if next < inners.len() {
+                    NextStep::TupleElem
+                } else {
+                    NextStep::FinishTuple
+                },
+            (&ApiState::Struct { next }, &Schema::Struct(ref fields)) =>
+                match fields.get(next) {
+                    Some(field) => NextStep::StructField(&field.name),
+                    None => NextStep::FinishStruct,
+                },
+            _ => bail!(
+                ApiUsage,
+                Some(self),
+                "next step requested in intermediate state",
+            ),
+        })
+    }
+
     /// Mark the coder as having experienced an irrecoverable error. Any
     /// further attempts at coding is an API usage error.
     pub(crate) fn mark_broken(&mut self) {
         self.broken = true;
     }
 
+    /// Mark broken, and give the error the path and offset of where it
+    /// occurred if it doesn't have them, as errors converted from IO errors
+    /// don't.
+    pub(crate) fn broken<E: Into<Error>>(&mut self, error: E) -> Error {
+        self.mark_broken();
+        error.into().with_coder_state(self)
+    }
+
+    /// Byte offset in the stream at which the current coder API call began.
+    /// Errors constructed from this state report it.
+    pub(crate) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Set the offset. Coders call this at the start of every API call.
+    pub(crate) fn set_offset(&mut self, offset: u64) {
+        self.offset = offset;
+    }
+
+    /// Mark the schema that needs to be coded as having been coded, without
+    /// coding it. This is for skipping over values when decoding.
+    pub(crate) fn code_skipped(&mut self) -> Result<()> {
+        let schema = self.need()?;
+        ensure!(
+            !self.broken,
+            ApiUsage,
+            Some(self),
+            "usage after IO error",
+        );
+        dbg_log!(self, "skipped {}", schema.non_recursive_display_str());
+        self.pop();
+        Ok(())
+    }
+
+    /// The schemas of all stack frames, root first.
+    pub(crate) fn stack_schemas(&self) -> Vec<&'a Schema> {
+        self.stack.iter().map(|frame| frame.schema).collect()
+    }
+
+    /// Number of stack frames. Every frame but the top one is a schema which
+    /// is partially coded, and the top one is the schema currently being
+    /// coded.
+    pub(crate) fn depth(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Get the schema of the top stack frame, regardless of API state.
+    pub(crate) fn top_schema(&self) -> Option<&'a Schema> {
+        self.stack.iter().rev().next().map(|frame| frame.schema)
+    }
+
+    /// Code a scalar of a type only known at runtime.
+    pub(crate) fn code_scalar(&mut self, scalar_type: ScalarType) -> Result<()> {
+        match scalar_type {
+            ScalarType::U8 => self.code_u8(),
+            ScalarType::U16 => self.code_u16(),
+            ScalarType::U32 => self.code_u32(),
+            ScalarType::U64 => self.code_u64(),
+            ScalarType::U128 => self.code_u128(),
+            ScalarType::I8 => self.code_i8(),
+            ScalarType::I16 => self.code_i16(),
+            ScalarType::I32 => self.code_i32(),
+            ScalarType::I64 => self.code_i64(),
+            ScalarType::I128 => self.code_i128(),
+            ScalarType::F32 => self.code_f32(),
+            ScalarType::F64 => self.code_f64(),
+            ScalarType::Char => self.code_char(),
+            ScalarType::Bool => self.code_bool(),
+        }
+    }
+
     code_simple!(
         code_u8(u8),
         code_u16(u16),
@@ -563,6 +749,52 @@ impl<'a> CoderState<'a> {
         Ok(())
     }
 
+    /// Code `n` elements of a seq of u8 at once, equivalently to
+    /// `begin_seq_elem` then `code_u8` for each, so that byte arrays can be
+    /// coded in bulk.
+    pub(crate) fn code_u8_seq_elems(&mut self, n: usize) -> Result<()> {
+        let (schema, len, next) =
+            validate_top_matches!(
+                self,
+                &mut StackFrame {
+                    schema,
+                    api_state: ApiState::Seq {
+                        len,
+                        next,
+                    },
+                } => (schema, len, next),
+                "u8 seq elems"
+            );
+        let inner =
+            match_or_unreachable!(
+                schema,
+                &Schema::Seq(SeqSchema { ref inner, .. }) => &**inner
+            );
+        ensure!(
+            inner == &schema!(u8),
+            SchemaNonConformance,
+            Some(self),
+            "\nneed seq elems of: {:#?}\ngot: u8 seq elems",
+            inner,
+        );
+        ensure!(
+            n <= len - next,
+            ApiUsage,
+            Some(self),
+            "code {} u8 seq elems at idx {}, but seq's declared len is {}",
+            n,
+            next,
+            len,
+        );
+        dbg_log!(self, "{} u8 seq elems", n);
+        self.top().api_state =
+            ApiState::Seq {
+                len,
+                next: next + n,
+            };
+        Ok(())
+    }
+
     /// Finish encoding a seq. See `begin_seq`.
     pub(crate) fn finish_seq(&mut self) -> Result<()> {
         let (len, next) =
@@ -854,7 +1086,7 @@ impl<'a> CoderState<'a> {
             need_variant_name,
         );
         dbg_log!(self, "variant name = {:?}", variant_name);
-        self.top().api_state = ApiState::AutoFinish;
+        self.top().api_state = ApiState::EnumVariant { variant_ord };
         self.push_need(&variants[variant_ord].inner)?;
         Ok(())
     }
@@ -1,3 +1,4 @@
 
+#[allow(clippy::module_inception)]
 pub mod coder;
 pub mod coder_alloc;
\ No newline at end of file
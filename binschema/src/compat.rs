@@ -0,0 +1,329 @@
+//! Checking whether data written with one schema can be read with another,
+//! for schema evolution.
+//!
+//! The rules, from the perspective of reading data written with the writer
+//! schema as if it were data of the reader schema:
+//!
+//! - a scalar may be widened to any scalar type that can represent all its
+//!   values, see `ScalarType::widens_to`
+//! - str may be read as bytes, but not the other way around
+//! - a non-option may be read as an option, in which case it is some
+//! - a fixed len seq may be read as a var len seq, but otherwise seq lengths
+//!   must match
+//! - tuples must have the same number of elements
+//! - struct fields are matched by name, not ordinal. fields only in the
+//!   writer are skipped. fields only in the reader are defaulted, which is
+//!   only possible if they have a default value, see `Schema::has_default`
+//! - enum variants are matched by name, not ordinal. every writer variant
+//!   must be present in the reader
+
+use crate::{
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    path::{
+        Path,
+        PathSegment,
+    },
+};
+use std::fmt::{self, Formatter, Display};
+
+
+/// Reason data written with one schema cannot be read with another. See
+/// `check_compat`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Incompatibility {
+    /// Path to where the schemas are incompatible.
+    pub path: Path,
+    pub kind: IncompatibilityKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum IncompatibilityKind {
+    /// The schemas are entirely different kinds of schema, such as a struct
+    /// and a str.
+    Mismatch {
+        writer: &'static str,
+        reader: &'static str,
+    },
+    /// The writer scalar type does not widen to the reader scalar type.
+    Narrowed {
+        writer: ScalarType,
+        reader: ScalarType,
+    },
+    /// Seq lengths are incompatible. `None` means var len.
+    SeqLen {
+        writer: Option<usize>,
+        reader: Option<usize>,
+    },
+    /// Tuples have different numbers of elements.
+    TupleLen {
+        writer: usize,
+        reader: usize,
+    },
+    /// Reader struct field which is not in the writer and has no default
+    /// value.
+    MissingField(String),
+    /// Writer enum variant which is not in the reader.
+    MissingVariant(String),
+    /// One of the schemas is illegal. See `Schema::validate`.
+    IllegalSchema,
+}
+
+impl Display for IncompatibilityKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &IncompatibilityKind::Mismatch { writer, reader } => write!(
+                f,
+                "writer has {}, reader has {}",
+                writer,
+                reader,
+            ),
+            &IncompatibilityKind::Narrowed { writer, reader } => write!(
+                f,
+                "writer has {}, which does not widen to reader's {}",
+                writer.display_str(),
+                reader.display_str(),
+            ),
+            &IncompatibilityKind::SeqLen { writer, reader } => write!(
+                f,
+                "writer has seq of {}, reader has seq of {}",
+                SeqLenDisplay(writer),
+                SeqLenDisplay(reader),
+            ),
+            &IncompatibilityKind::TupleLen { writer, reader } => write!(
+                f,
+                "writer has tuple of len {}, reader has tuple of len {}",
+                writer,
+                reader,
+            ),
+            &IncompatibilityKind::MissingField(ref name) => write!(
+                f,
+                "reader field {:?} is not in writer and has no default",
+                name,
+            ),
+            &IncompatibilityKind::MissingVariant(ref name) => write!(
+                f,
+                "writer variant {:?} is not in reader",
+                name,
+            ),
+            &IncompatibilityKind::IllegalSchema => f.write_str("illegal schema"),
+        }
+    }
+}
+
+struct SeqLenDisplay(Option<usize>);
+
+impl Display for SeqLenDisplay {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(len) => write!(f, "len {}", len),
+            None => f.write_str("variable len"),
+        }
+    }
+}
+
+impl Display for Incompatibility {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl std::error::Error for Incompatibility {}
+
+impl ScalarType {
+    /// Whether every value of self can be represented as a value of `other`,
+    /// such that data written as self can be read as `other`.
+    pub fn widens_to(self, other: ScalarType) -> bool {
+        use ScalarType::*;
+
+        self == other || matches!(
+            (self, other),
+            (U8, U16 | U32 | U64 | U128 | I16 | I32 | I64 | I128 | F32 | F64)
+            | (U16, U32 | U64 | U128 | I32 | I64 | I128 | F32 | F64)
+            | (U32, U64 | U128 | I64 | I128 | F64)
+            | (U64, U128 | I128)
+            | (I8, I16 | I32 | I64 | I128 | F32 | F64)
+            | (I16, I32 | I64 | I128 | F32 | F64)
+            | (I32, I64 | I128 | F64)
+            | (I64, I128)
+            | (F32, F64)
+        )
+    }
+}
+
+impl Schema {
+    /// Whether this schema has a default value which a reader can fill in
+    /// when the writer lacks it. These are: none for options, empty for var
+    /// len seqs, unit, and tuples and structs of entirely such things.
+    pub fn has_default(&self) -> bool {
+        match self {
+            &Schema::Unit
+            | &Schema::Option(_)
+            | &Schema::Seq(SeqSchema { len: None, .. })
+            | &Schema::Seq(SeqSchema { len: Some(0), .. }) => true,
+            &Schema::Tuple(ref inners) => inners
+                .iter()
+                .all(Schema::has_default),
+            &Schema::Struct(ref fields) => fields
+                .iter()
+                .all(|field| field.inner.has_default()),
+            _ => false,
+        }
+    }
+}
+
+/// Check whether data written with `writer` can be read with `reader`, and
+/// list every reason it can't if it can't. See module-level docs for rules.
+pub fn check_compat(
+    writer: &Schema,
+    reader: &Schema,
+) -> Result<(), Vec<Incompatibility>> {
+    let mut check = Check {
+        writer_stack: Vec::new(),
+        reader_stack: Vec::new(),
+        in_progress: Vec::new(),
+        path: Path::new(),
+        incompats: Vec::new(),
+    };
+    check.check(writer, reader);
+    if check.incompats.is_empty() {
+        Ok(())
+    } else {
+        Err(check.incompats)
+    }
+}
+
+struct Check<'a> {
+    writer_stack: Vec<&'a Schema>,
+    reader_stack: Vec<&'a Schema>,
+    /// Pairs of (writer, reader) currently being checked, by address, so that
+    /// recursive schemas are checked co-inductively.
+    in_progress: Vec<(*const Schema, *const Schema)>,
+    path: Path,
+    incompats: Vec<Incompatibility>,
+}
+
+impl<'a> Check<'a> {
+    fn incompat(&mut self, kind: IncompatibilityKind) {
+        self.incompats.push(Incompatibility {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    fn check_in(&mut self, segment: PathSegment, writer: &'a Schema, reader: &'a Schema) {
+        self.path.push(segment);
+        self.check(writer, reader);
+        self.path.pop();
+    }
+
+    fn check(&mut self, writer: &'a Schema, reader: &'a Schema) {
+        let (writer, reader) =
+            match (
+                writer.resolve(&self.writer_stack),
+                reader.resolve(&self.reader_stack),
+            ) {
+                (Some(writer), Some(reader)) => (writer, reader),
+                _ => {
+                    self.incompat(IncompatibilityKind::IllegalSchema);
+                    return;
+                }
+            };
+        let pair = (writer as *const Schema, reader as *const Schema);
+        if self.in_progress.contains(&pair) {
+            return;
+        }
+        self.in_progress.push(pair);
+        self.writer_stack.push(writer);
+        self.reader_stack.push(reader);
+
+        match (writer, reader) {
+            (&Schema::Scalar(w), &Schema::Scalar(r)) => {
+                if !w.widens_to(r) {
+                    self.incompat(IncompatibilityKind::Narrowed {
+                        writer: w,
+                        reader: r,
+                    });
+                }
+            }
+            (&Schema::Str, &Schema::Str)
+            | (&Schema::Str, &Schema::Bytes)
+            | (&Schema::Bytes, &Schema::Bytes)
+            | (&Schema::Unit, &Schema::Unit) => (),
+            (&Schema::Option(ref w), &Schema::Option(ref r)) => {
+                self.check_in(PathSegment::Some, w, r);
+            }
+            (w, &Schema::Option(ref r)) => {
+                // read as some. the writer has no option layer, so to keep
+                // its recursion levels right it mustn't be stacked twice
+                self.writer_stack.pop();
+                self.check_in(PathSegment::Some, w, r);
+                self.writer_stack.push(w);
+            }
+            (
+                &Schema::Seq(SeqSchema { len: w_len, inner: ref w }),
+                &Schema::Seq(SeqSchema { len: r_len, inner: ref r }),
+            ) => {
+                if r_len.is_some() && w_len != r_len {
+                    self.incompat(IncompatibilityKind::SeqLen {
+                        writer: w_len,
+                        reader: r_len,
+                    });
+                }
+                self.check_in(PathSegment::SeqElem(None), w, r);
+            }
+            (&Schema::Tuple(ref ws), &Schema::Tuple(ref rs)) => {
+                if ws.len() != rs.len() {
+                    self.incompat(IncompatibilityKind::TupleLen {
+                        writer: ws.len(),
+                        reader: rs.len(),
+                    });
+                }
+                for (i, (w, r)) in ws.iter().zip(rs).enumerate() {
+                    self.check_in(PathSegment::TupleElem(i), w, r);
+                }
+            }
+            (&Schema::Struct(ref ws), &Schema::Struct(ref rs)) => {
+                for r in rs {
+                    match ws.iter().find(|w| w.name == r.name) {
+                        Some(w) => self.check_in(
+                            PathSegment::Field(r.name.clone()),
+                            &w.inner,
+                            &r.inner,
+                        ),
+                        None => if !r.inner.has_default() {
+                            self.incompat(IncompatibilityKind::MissingField(
+                                r.name.clone(),
+                            ));
+                        },
+                    }
+                }
+            }
+            (&Schema::Enum(ref ws), &Schema::Enum(ref rs)) => {
+                for w in ws {
+                    match rs.iter().find(|r| r.name == w.name) {
+                        Some(r) => self.check_in(
+                            PathSegment::Variant(w.name.clone()),
+                            &w.inner,
+                            &r.inner,
+                        ),
+                        None => self.incompat(IncompatibilityKind::MissingVariant(
+                            w.name.clone(),
+                        )),
+                    }
+                }
+            }
+            (w, r) => self.incompat(IncompatibilityKind::Mismatch {
+                writer: w.non_recursive_display_str(),
+                reader: r.non_recursive_display_str(),
+            }),
+        }
+
+        self.writer_stack.pop();
+        self.reader_stack.pop();
+        self.in_progress.pop();
+    }
+}
@@ -0,0 +1,343 @@
+//! Trait for types which decode themselves by calling `Decoder` methods
+//! directly rather than going through serde, and implementations for common
+//! types.
+
+use crate::{
+    error::{
+        Result,
+        error,
+    },
+    Decoder,
+};
+use std::{
+    collections::{
+        BinaryHeap,
+        BTreeSet,
+        HashSet,
+        LinkedList,
+        VecDeque,
+        BTreeMap,
+        HashMap,
+    },
+    ops::{
+        Range,
+        RangeInclusive,
+        Bound,
+    },
+    borrow::Cow,
+    hash::Hash,
+    io::Read,
+};
+
+
+/// Type which can decode itself with a `Decoder`, in conformance with its
+/// `KnownSchema`. Can be derived alongside `KnownSchema`.
+pub trait Decode: Sized {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self>;
+
+    /// Decode `len` elements of a seq which has been begun, appending them to
+    /// `elems`. Overridden by `u8` to decode them in one read.
+    #[doc(hidden)]
+    fn decode_seq_elems<R: Read>(
+        len: usize,
+        decoder: &mut Decoder<R>,
+        elems: &mut Vec<Self>,
+    ) -> Result<()> {
+        for _ in 0..len {
+            decoder.begin_seq_elem()?;
+            elems.push(Self::decode(decoder)?);
+        }
+        Ok(())
+    }
+}
+
+macro_rules! scalars_decode {
+    ($($t:ident $m:ident,)*)=>{$(
+        impl Decode for $t {
+            fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+                decoder.$m()
+            }
+        }
+    )*};
+}
+
+scalars_decode!(
+    u16 decode_u16,
+    u32 decode_u32,
+    u64 decode_u64,
+    u128 decode_u128,
+    i8 decode_i8,
+    i16 decode_i16,
+    i32 decode_i32,
+    i64 decode_i64,
+    i128 decode_i128,
+    f32 decode_f32,
+    f64 decode_f64,
+    char decode_char,
+    bool decode_bool,
+);
+
+impl Decode for u8 {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.decode_u8()
+    }
+
+    fn decode_seq_elems<R: Read>(
+        len: usize,
+        decoder: &mut Decoder<R>,
+        elems: &mut Vec<Self>,
+    ) -> Result<()> {
+        decoder.decode_u8_seq_elems(len, elems)
+    }
+}
+
+impl Decode for usize {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let n = decoder.decode_u64()?;
+        usize::try_from(n)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(decoder.coder_state()),
+                "{} out of range for a usize",
+                n,
+            ))
+    }
+}
+
+impl Decode for isize {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let n = decoder.decode_i64()?;
+        isize::try_from(n)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(decoder.coder_state()),
+                "{} out of range for an isize",
+                n,
+            ))
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.decode_str()
+    }
+}
+
+impl Decode for () {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.decode_unit()
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Ok(if decoder.begin_option()? {
+            Some(T::decode(decoder)?)
+        } else {
+            None
+        })
+    }
+}
+
+/// Decode the elements of a var len seq into a collection.
+fn decode_var_len_seq<R, T, C>(decoder: &mut Decoder<R>) -> Result<C>
+where
+    R: Read,
+    T: Decode,
+    C: FromIterator<T>,
+{
+    let len = decoder.begin_var_len_seq()?;
+    let elems = (0..len)
+        .map(|_| {
+            decoder.begin_seq_elem()?;
+            T::decode(decoder)
+        })
+        .collect::<Result<C>>()?;
+    decoder.finish_seq()?;
+    Ok(elems)
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        let len = decoder.begin_var_len_seq()?;
+        let mut elems = Vec::with_capacity(usize::min(len, 1024));
+        T::decode_seq_elems(len, decoder, &mut elems)?;
+        decoder.finish_seq()?;
+        Ok(elems)
+    }
+}
+
+impl<T: Decode + Ord> Decode for BinaryHeap<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_var_len_seq(decoder)
+    }
+}
+
+impl<T: Decode + Ord> Decode for BTreeSet<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_var_len_seq(decoder)
+    }
+}
+
+impl<T: Decode + Eq + Hash> Decode for HashSet<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_var_len_seq(decoder)
+    }
+}
+
+impl<T: Decode> Decode for LinkedList<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_var_len_seq(decoder)
+    }
+}
+
+impl<T: Decode> Decode for VecDeque<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Vec::decode(decoder).map(VecDeque::from)
+    }
+}
+
+impl<T: Decode, const LEN: usize> Decode for [T; LEN] {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.begin_fixed_len_seq(LEN)?;
+        let mut elems = Vec::with_capacity(LEN);
+        T::decode_seq_elems(LEN, decoder, &mut elems)?;
+        decoder.finish_seq()?;
+        Ok(elems.try_into().ok().unwrap())
+    }
+}
+
+/// Decode a var len seq of key/value tuples into a map.
+fn decode_map<R, K, V, C>(decoder: &mut Decoder<R>) -> Result<C>
+where
+    R: Read,
+    K: Decode,
+    V: Decode,
+    C: FromIterator<(K, V)>,
+{
+    let len = decoder.begin_var_len_seq()?;
+    let entries = (0..len)
+        .map(|_| {
+            decoder.begin_seq_elem()?;
+            decoder.begin_tuple()?;
+            decoder.begin_tuple_elem()?;
+            let key = K::decode(decoder)?;
+            decoder.begin_tuple_elem()?;
+            let val = V::decode(decoder)?;
+            decoder.finish_tuple()?;
+            Ok((key, val))
+        })
+        .collect::<Result<C>>()?;
+    decoder.finish_seq()?;
+    Ok(entries)
+}
+
+impl<K: Decode + Ord, V: Decode> Decode for BTreeMap<K, V> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_map(decoder)
+    }
+}
+
+impl<K: Decode + Eq + Hash, V: Decode> Decode for HashMap<K, V> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decode_map(decoder)
+    }
+}
+
+macro_rules! tuples_decode {
+    ($(($($t:ident),*),)*)=>{$(
+        impl<$($t: Decode),*> Decode for ($($t,)*) {
+            fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+                decoder.begin_tuple()?;
+                let tuple = ($(
+                    {
+                        decoder.begin_tuple_elem()?;
+                        $t::decode(decoder)?
+                    },
+                )*);
+                decoder.finish_tuple()?;
+                Ok(tuple)
+            }
+        }
+    )*};
+}
+
+tuples_decode!(
+    (A),
+    (A, B),
+    (A, B, C),
+    (A, B, C, D),
+    (A, B, C, D, E),
+    (A, B, C, D, E, F),
+    (A, B, C, D, E, F, G),
+    (A, B, C, D, E, F, G, H),
+    (A, B, C, D, E, F, G, H, I),
+    (A, B, C, D, E, F, G, H, I, J),
+    (A, B, C, D, E, F, G, H, I, J, K),
+);
+
+impl<T: Decode> Decode for Range<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.begin_struct()?;
+        decoder.begin_struct_field("start")?;
+        let start = T::decode(decoder)?;
+        decoder.begin_struct_field("end")?;
+        let end = T::decode(decoder)?;
+        decoder.finish_struct()?;
+        Ok(start..end)
+    }
+}
+
+impl<T: Decode> Decode for RangeInclusive<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        decoder.begin_struct()?;
+        decoder.begin_struct_field("start")?;
+        let start = T::decode(decoder)?;
+        decoder.begin_struct_field("end")?;
+        let end = T::decode(decoder)?;
+        decoder.finish_struct()?;
+        Ok(start..=end)
+    }
+}
+
+impl<T: Decode> Decode for Bound<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        Ok(match decoder.begin_enum()? {
+            0 => {
+                decoder.begin_enum_variant("Unbounded")?;
+                decoder.decode_unit()?;
+                Bound::Unbounded
+            }
+            1 => {
+                decoder.begin_enum_variant("Included")?;
+                Bound::Included(T::decode(decoder)?)
+            }
+            2 => {
+                decoder.begin_enum_variant("Excluded")?;
+                Bound::Excluded(T::decode(decoder)?)
+            }
+            ord => return Err(error!(
+                SchemaNonConformance,
+                Some(decoder.coder_state()),
+                "variant ordinal {} out of range for {} variants",
+                ord,
+                3,
+            )),
+        })
+    }
+}
+
+impl<T: Decode> Decode for Box<T> {
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        T::decode(decoder).map(Box::new)
+    }
+}
+
+impl<'a, T: ToOwned + ?Sized> Decode for Cow<'a, T>
+where
+    T::Owned: Decode,
+{
+    fn decode<R: Read>(decoder: &mut Decoder<R>) -> Result<Self> {
+        T::Owned::decode(decoder).map(Cow::Owned)
+    }
+}
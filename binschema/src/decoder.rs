@@ -1,9 +1,9 @@
 
 use crate::{
-    do_if_err::DoIfErr,
     error::{
         Result,
         Error,
+        ErrorKind,
         error,
         bail,
     },
@@ -13,7 +13,21 @@ use crate::{
         read_var_len_sint,
         read_ord,
     },
-    schema::Schema,
+    schema::{
+        Schema,
+        ScalarType,
+    },
+    value::ScalarValue,
+    resolve::Resolver,
+    skip::{
+        skip_raw,
+        read_exact_onto,
+    },
+    limits::{
+        DecodeLimits,
+        LimitExceeded,
+        CountingRead,
+    },
 };
 use std::{
     mem::{
@@ -22,19 +36,61 @@ use std::{
     },
     io::Read,
     borrow::BorrowMut,
-    iter::repeat,
 };
 
 
 /// Decodes a value from a `std::io::Read` comforming to a schema.
 pub struct Decoder<'a, 'b, R> {
-    state: &'b mut CoderState<'a>,
-    read: &'b mut R,
+    pub(crate) state: &'b mut CoderState<'a>,
+    pub(crate) read: CountingRead<'b, R>,
+    pub(crate) resolver: Option<Resolver<'a>>,
+    limits: DecodeLimits,
 }
 
 impl<'a, 'b, R> Decoder<'a, 'b, R> {
     pub fn new(state: &'b mut CoderState<'a>, read: &'b mut R) -> Self {
-        Decoder { state, read }
+        Decoder {
+            state,
+            read: CountingRead::new(read),
+            resolver: None,
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// Construct a decoder which decodes data that was written with the
+    /// `writer` schema as if it were data of the schema `state` was
+    /// constructed with, the reader schema. Struct fields are matched by
+    /// name, and fields missing from the writer are defaulted. Enum variants
+    /// are matched by name. Scalars are widened. See `check_compat` for the
+    /// full rules, which can be used to front-run
+    /// `ErrorKind::IncompatibleSchemas` errors.
+    pub fn new_resolving(
+        state: &'b mut CoderState<'a>,
+        writer: &'a Schema,
+        read: &'b mut R,
+    ) -> Self {
+        Decoder {
+            state,
+            read: CountingRead::new(read),
+            resolver: Some(Resolver::new(writer)),
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// Set limits on the data this decoder will accept from here on. See
+    /// `DecodeLimits`.
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.read.max = limits.max_total_bytes;
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.read.pos
     }
 
     /// Get the schema that needs to be decoded. Fails if has already began
@@ -50,23 +106,66 @@ impl<'a, 'b, R> Decoder<'a, 'b, R> {
     pub fn coder_state(&self) -> &CoderState<'a> {
         &*self.state
     }
+
+    /// Mark broken and construct an `ErrorKind::LimitExceeded` error.
+    fn limit_exceeded(&mut self, limit: LimitExceeded) -> Error {
+        self.state.mark_broken();
+        Error::new(ErrorKind::LimitExceeded, limit, Some(self.coder_state()))
+    }
+
+    /// Fail if a decoded str or bytes len exceeds limits.
+    pub(crate) fn check_str_bytes_len(&mut self, len: usize) -> Result<()> {
+        self.limits
+            .check_str_bytes_len(len)
+            .map_err(|limit| self.limit_exceeded(limit))
+    }
+
+    /// Fail if a decoded var len seq len exceeds limits.
+    pub(crate) fn check_seq_len(&mut self, len: usize) -> Result<()> {
+        self.limits
+            .check_seq_len(len)
+            .map_err(|limit| self.limit_exceeded(limit))
+    }
+
+    /// Fail if the value that needs to be decoded is nested deeper than
+    /// limits allow. Called after beginning any inner value.
+    pub(crate) fn check_depth(&mut self) -> Result<()> {
+        self.limits
+            .check_depth(self.state.depth())
+            .map_err(|limit| self.limit_exceeded(limit))
+    }
+}
+
+macro_rules! resolving_decode_scalar {
+    ($self:ident, $st:ident)=>{
+        if $self.resolver.is_some() {
+            return match $self.resolving_decode_scalar(ScalarType::$st)? {
+                ScalarValue::$st(n) => Ok(n),
+                _ => unreachable!(),
+            };
+        }
+    };
 }
 
 macro_rules! decode_le_bytes {
-    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+    ($($m:ident($t:ident) $c:ident $st:ident,)*)=>{$(
         pub fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            resolving_decode_scalar!(self, $st);
             self.state.$c()?;
             let buf = self
                 .read([0; size_of::<$t>()])
-                .do_if_err(|| self.state.mark_broken())?;
+                .map_err(|e| self.state.broken(e))?;
             Ok($t::from_le_bytes(buf))
         }
     )*};
 }
 
 macro_rules! decode_var_len_uint {
-    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+    ($($m:ident($t:ident) $c:ident $st:ident,)*)=>{$(
         pub fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            resolving_decode_scalar!(self, $st);
             self.state.$c()?;
             read_var_len_uint(&mut self.read)
                 .map_err(Error::from)
@@ -80,14 +179,16 @@ macro_rules! decode_var_len_uint {
                         ),
                         n,
                     )))
-                .do_if_err(|| self.state.mark_broken())
+                .map_err(|e| self.state.broken(e))
         }
     )*};
 }
 
 macro_rules! decode_var_len_sint {
-    ($($m:ident($t:ident) $c:ident,)*)=>{$(
+    ($($m:ident($t:ident) $c:ident $st:ident,)*)=>{$(
         pub fn $m(&mut self) -> Result<$t> {
+            self.state.set_offset(self.position());
+            resolving_decode_scalar!(self, $st);
             self.state.$c()?;
             read_var_len_sint(&mut self.read)
                 .map_err(Error::from)
@@ -101,7 +202,7 @@ macro_rules! decode_var_len_sint {
                         ),
                         n,
                     )))
-                .do_if_err(|| self.state.mark_broken())
+                .map_err(|e| self.state.broken(e))
         }
     )*};
 }
@@ -110,12 +211,12 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     fn read<B: BorrowMut<[u8]>>(&mut self, mut buf: B) -> Result<B> {
         self.read
             .read_exact(buf.borrow_mut())
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         Ok(buf)
     }
 
     /// Read a varlen-encoded usize.
-    fn read_len(&mut self) -> Result<usize> {
+    pub(crate) fn read_len(&mut self) -> Result<usize> {
         read_var_len_uint(&mut self.read)
             .map_err(Error::from)
             .and_then(|n| usize::try_from(n)
@@ -125,31 +226,33 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
                     "{} out of range for a usize",
                     n,
                 )))
-            .do_if_err(|| self.state.mark_broken())
+            .map_err(|e| self.state.broken(e))
     }
 
     decode_le_bytes!(
-        decode_u8(u8) code_u8,
-        decode_u16(u16) code_u16,
-        decode_i8(i8) code_i8,
-        decode_i16(i16) code_i16,
-        decode_f32(f32) code_f32,
-        decode_f64(f64) code_f64,
+        decode_u8(u8) code_u8 U8,
+        decode_u16(u16) code_u16 U16,
+        decode_i8(i8) code_i8 I8,
+        decode_i16(i16) code_i16 I16,
+        decode_f32(f32) code_f32 F32,
+        decode_f64(f64) code_f64 F64,
     );
 
     decode_var_len_uint!(
-        decode_u32(u32) code_u32,
-        decode_u64(u64) code_u64,
-        decode_u128(u128) code_u128,
+        decode_u32(u32) code_u32 U32,
+        decode_u64(u64) code_u64 U64,
+        decode_u128(u128) code_u128 U128,
     );
 
     decode_var_len_sint!(
-        decode_i32(i32) code_i32,
-        decode_i64(i64) code_i64,
-        decode_i128(i128) code_i128,
+        decode_i32(i32) code_i32 I32,
+        decode_i64(i64) code_i64 I64,
+        decode_i128(i128) code_i128 I128,
     );
 
     pub fn decode_char(&mut self) -> Result<char> {
+        self.state.set_offset(self.position());
+        resolving_decode_scalar!(self, Char);
         self.state.code_char()?;
         let n = read_var_len_uint(&mut self.read)
             .map_err(Error::from)
@@ -160,7 +263,7 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
                     "{} out of range for a char",
                     n,
                 )))
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         char::from_u32(n)
             .ok_or_else(|| error!(
                 MalformedData,
@@ -171,6 +274,8 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     }
 
     pub fn decode_bool(&mut self) -> Result<bool> {
+        self.state.set_offset(self.position());
+        resolving_decode_scalar!(self, Bool);
         self.state.code_bool()?;
         let [n] = self.read([0])?;
         match n {
@@ -186,17 +291,21 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     }
 
     pub fn decode_unit(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_decode_unit();
+        }
         self.state.code_unit()?;
         Ok(())
     }
 
     /// Clear `buf` and decode a str into it.
     pub fn decode_str_into(&mut self, buf: &mut String) -> Result<()> {
+        self.state.set_offset(self.position());
         // always clear the buf, for consistency
         buf.clear();
 
         self.state.code_str()?;
-        let len = self.read_len()?;
 
         // do a little switcharoo to get ownership of raw Vec<u8> buf
         //
@@ -204,17 +313,30 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
         // characters are actually added to it.
         let mut bbuf = take(buf).into_bytes();
 
-        // TODO: protection against malicious payloads
+        if self.resolver.is_some() {
+            if let Err(e) = self.resolving_decode_str_or_bytes_into(&mut bbuf, true) {
+                *buf = String::from_utf8(bbuf).unwrap();
+                return Err(e);
+            }
+        } else {
+            let len = match self
+                .read_len()
+                .and_then(|len| self.check_str_bytes_len(len).map(|()| len))
+            {
+                Ok(len) => len,
+                Err(e) => {
+                    *buf = String::from_utf8(bbuf).unwrap();
+                    return Err(e);
+                }
+            };
 
-        // try to read all the bytes in
-        // on error, make sure to return the buffer
-        bbuf.reserve(len);
-        bbuf.extend(repeat(0).take(len));
-        if let Err(e) = self.read.read_exact(&mut bbuf) {
-            self.state.mark_broken();
-            bbuf.clear();
-            *buf = String::from_utf8(bbuf).unwrap();
-            return Err(e.into());
+            // try to read all the bytes in
+            // on error, make sure to return the buffer
+            if let Err(e) = read_exact_onto(&mut self.read, &mut bbuf, len) {
+                bbuf.clear();
+                *buf = String::from_utf8(bbuf).unwrap();
+                return Err(self.state.broken(e));
+            }
         }
 
         // try to convert to utf8
@@ -245,15 +367,18 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
 
     /// Clear `buf` and decode a bytes into it.
     pub fn decode_bytes_into(&mut self, buf: &mut Vec<u8>) -> Result<()> {
+        self.state.set_offset(self.position());
         // always clear the buf, for consistency
         buf.clear();
 
         self.state.code_bytes()?;
-        let len = self.read_len().do_if_err(|| self.state.mark_broken())?;
-        buf.reserve(len);
-        buf.extend(repeat(0).take(len));
-        self.read.read_exact(buf).do_if_err(|| self.state.mark_broken())?;
-        Ok(())
+        if self.resolver.is_some() {
+            return self.resolving_decode_str_or_bytes_into(buf, false);
+        }
+        let len = self.read_len()?;
+        self.check_str_bytes_len(len)?;
+        read_exact_onto(&mut self.read, buf, len)
+            .map_err(|e| self.state.broken(e))
     }
 
     /// Decode a bytes into a new alloc.
@@ -268,6 +393,14 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// this should be followed by decoding the inner value, when then
     /// auto-finishes the option.
     pub fn begin_option(&mut self) -> Result<bool> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            let is_some = self.resolving_begin_option()?;
+            if is_some {
+                self.check_depth()?;
+            }
+            return Ok(is_some);
+        }
         self.state.begin_option()?;
         let [n] = self.read([0])?;
         let is_some =
@@ -283,6 +416,7 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
             };
         if is_some {
             self.state.set_option_some()?;
+            self.check_depth()?;
         } else {
             self.state.set_option_none();
         }
@@ -293,6 +427,10 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// `len` elements with `begin_seq_elem` followed by a call to
     /// `finish_seq`.
     pub fn begin_fixed_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_begin_fixed_len_seq(len);
+        }
         self.state.begin_fixed_len_seq(len)?;
         Ok(())
     }
@@ -301,8 +439,13 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// followed by decoding `len` elements with `begin_seq_elem` followed by
     /// a call to `finish_seq`.
     pub fn begin_var_len_seq(&mut self) -> Result<usize> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_begin_var_len_seq();
+        }
         self.state.begin_var_len_seq()?;
         let len = self.read_len()?;
+        self.check_seq_len(len)?;
         self.state.set_var_len_seq_len(len);
         Ok(len)
     }
@@ -310,20 +453,50 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// Begin decoding an element in a seq. This should be followed by decoding
     /// the inner value. See `begin_fixed_len_seq` or `begin_var_len_seq`.
     pub fn begin_seq_elem(&mut self) -> Result<()> {
-        self.state.begin_seq_elem()?;
-        Ok(())
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            self.resolving_begin_seq_elem()?;
+        } else {
+            self.state.begin_seq_elem()?;
+        }
+        self.check_depth()
+    }
+
+    /// Decode `n` elements of a seq of u8 in one read, equivalently to
+    /// `begin_seq_elem` followed by `decode_u8` for each, appending them to
+    /// `buf`. See `begin_fixed_len_seq` or `begin_var_len_seq`.
+    pub fn decode_u8_seq_elems(&mut self, n: usize, buf: &mut Vec<u8>) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            for _ in 0..n {
+                self.begin_seq_elem()?;
+                buf.push(self.decode_u8()?);
+            }
+            return Ok(());
+        }
+        self.state.code_u8_seq_elems(n)?;
+        read_exact_onto(&mut self.read, buf, n)
+            .map_err(|e| self.state.broken(e))
     }
 
     /// Finish decoding a seq. See `begin_fixed_len_seq` or
     /// `begin_var_len_seq`.
     pub fn finish_seq(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.finish_seq()?;
+        if self.resolver.is_some() {
+            self.resolving_finish();
+        }
         Ok(())
     }
     
     /// Begin decoding a tuple. This should be followed by decoding the
     /// elements with `begin_tuple_elem` followed by a call to `finish_tuple`.
     pub fn begin_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_begin_tuple();
+        }
         self.state.begin_tuple()?;
         Ok(())
     }
@@ -331,19 +504,32 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// Begin decoding an element in a tuple. This should be followed by
     /// decoding the inner value. See `begin_tuple`,
     pub fn begin_tuple_elem(&mut self) -> Result<()> {
-        self.state.begin_tuple_elem()?;
-        Ok(())
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            self.resolving_begin_tuple_elem()?;
+        } else {
+            self.state.begin_tuple_elem()?;
+        }
+        self.check_depth()
     }
 
     /// Finish decoding a tuple. See `begin_tuple`.
     pub fn finish_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.finish_tuple()?;
+        if self.resolver.is_some() {
+            self.resolving_finish();
+        }
         Ok(())
     }
 
     /// Begin decoding a struct. This should be followed by decoding the
     /// fields with `begin_struct_field` followed by a call to `finish_struct`.
     pub fn begin_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_begin_struct();
+        }
         self.state.begin_struct()?;
         Ok(())
     }
@@ -351,12 +537,21 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// Begin decoding a field in a struct. This should be followed by
     /// decoding the inner value. See `begin_struct`,
     pub fn begin_struct_field(&mut self, name: &str) -> Result<()> {
-        self.state.begin_struct_field(name)?;
-        Ok(())
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            self.resolving_begin_struct_field(name)?;
+        } else {
+            self.state.begin_struct_field(name)?;
+        }
+        self.check_depth()
     }
 
     /// Finish decoding a struct. See `begin_struct`.
     pub fn finish_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_finish_struct();
+        }
         self.state.finish_struct()?;
         Ok(())
     }
@@ -365,18 +560,44 @@ impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
     /// followed by `begin_enum_variant`, then decoding the inner value, which
     /// then auto-finishes the enum.
     pub fn begin_enum(&mut self) -> Result<usize> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_begin_enum();
+        }
         let num_variants = self.state.begin_enum()?;
         let variant_ord = read_ord(&mut self.read, num_variants)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         self.state
             .begin_enum_variant_ord(variant_ord)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         Ok(variant_ord)
     }
 
+    /// Skip over the value that needs to be decoded, whatever its shape,
+    /// without materializing it. Str and bytes payloads are discarded without
+    /// being allocated, and fixed-size regions are skipped in bulk.
+    pub fn skip(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            return self.resolving_skip();
+        }
+        let schema = self.state.need()?;
+        let mut ancestors = self.state.stack_schemas();
+        ancestors.pop();
+        self.state.code_skipped()?;
+        let limits = self.limits;
+        skip_raw(schema, &mut ancestors, &limits, &mut self.read)
+            .map_err(|e| self.state.broken(e))
+    }
+
     /// Provide the name of the enum variant. See `begin_enum`.
     pub fn begin_enum_variant(&mut self, name: &str) -> Result<()> {
-        self.state.begin_enum_variant_name(name)?;
-        Ok(())
+        self.state.set_offset(self.position());
+        if self.resolver.is_some() {
+            self.resolving_begin_enum_variant(name)?;
+        } else {
+            self.state.begin_enum_variant_name(name)?;
+        }
+        self.check_depth()
     }
 }
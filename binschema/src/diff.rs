@@ -0,0 +1,610 @@
+//! Structural diffs between schemas, for reviewing how a type changed.
+//!
+//! Struct fields and enum variants are matched up by name. Of those left
+//! over, an old one and a new one at the same ordinal with the same inner
+//! schema are taken to be a rename. Whether a change is breaking is judged
+//! by the rules of `check_compat`, with the old schema as writer and the new
+//! one as reader.
+
+use crate::{
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    path::{
+        Path,
+        PathSegment,
+    },
+};
+use std::fmt::{self, Formatter, Display, Write};
+
+
+/// Every change between two schemas. See `diff_schemas`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Hash)]
+pub struct SchemaDiff {
+    pub changes: Vec<SchemaChange>,
+}
+
+/// Single change between two schemas.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SchemaChange {
+    /// Path to where the schemas differ. Below a field or variant which was
+    /// renamed, the path uses the new name.
+    pub path: Path,
+    pub kind: SchemaChangeKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SchemaChangeKind {
+    /// Replaced with an entirely different kind of schema, such as a struct
+    /// with a str.
+    Replaced {
+        old: &'static str,
+        new: &'static str,
+    },
+    /// Scalar type changed.
+    ScalarType {
+        old: ScalarType,
+        new: ScalarType,
+    },
+    /// Non-option became an option of it.
+    OptionAdded,
+    /// Option became its inner schema.
+    OptionRemoved,
+    /// Seq length changed. `None` means var len.
+    SeqLen {
+        old: Option<usize>,
+        new: Option<usize>,
+    },
+    /// Tuple number of elements changed.
+    TupleLen {
+        old: usize,
+        new: usize,
+    },
+    /// Struct field added. Adding a field is only non-breaking if it has a
+    /// default value, see `Schema::has_default`.
+    FieldAdded {
+        name: String,
+        has_default: bool,
+    },
+    FieldRemoved(String),
+    /// Struct field renamed. Since fields are read by name, this is only
+    /// non-breaking if the new field has a default value, like `FieldAdded`.
+    FieldRenamed {
+        old: String,
+        new: String,
+        has_default: bool,
+    },
+    /// Order of fields present in both changed. Lists their names in old
+    /// and new order.
+    FieldsReordered {
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    VariantAdded(String),
+    VariantRemoved(String),
+    /// Enum variant renamed. Since variants are read by name, and every
+    /// variant written must be present when reading, this is always
+    /// breaking, like `VariantRemoved`.
+    VariantRenamed {
+        old: String,
+        new: String,
+    },
+    /// Order of variants present in both changed. Lists their names in old
+    /// and new order.
+    VariantsReordered {
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+    /// Recurse changed to recurse to a different place.
+    RecurseLevel {
+        old: usize,
+        new: usize,
+    },
+    /// One of the schemas is illegal. See `Schema::validate`.
+    IllegalSchema,
+}
+
+impl SchemaChangeKind {
+    /// Whether this change means data written with the old schema can't be
+    /// read with the new one, by the rules of `check_compat`.
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            &SchemaChangeKind::Replaced { old, new } => !(old == "str" && new == "bytes"),
+            &SchemaChangeKind::ScalarType { old, new } => !old.widens_to(new),
+            &SchemaChangeKind::OptionAdded => false,
+            &SchemaChangeKind::OptionRemoved => true,
+            &SchemaChangeKind::SeqLen { new, .. } => new.is_some(),
+            &SchemaChangeKind::TupleLen { .. } => true,
+            &SchemaChangeKind::FieldAdded { has_default, .. } => !has_default,
+            &SchemaChangeKind::FieldRemoved(_) => false,
+            &SchemaChangeKind::FieldRenamed { has_default, .. } => !has_default,
+            &SchemaChangeKind::FieldsReordered { .. } => false,
+            &SchemaChangeKind::VariantAdded(_) => false,
+            &SchemaChangeKind::VariantRemoved(_) => true,
+            &SchemaChangeKind::VariantRenamed { .. } => true,
+            &SchemaChangeKind::VariantsReordered { .. } => false,
+            &SchemaChangeKind::RecurseLevel { .. } => true,
+            &SchemaChangeKind::IllegalSchema => true,
+        }
+    }
+}
+
+impl SchemaChange {
+    /// See `SchemaChangeKind::is_breaking`.
+    pub fn is_breaking(&self) -> bool {
+        self.kind.is_breaking()
+    }
+}
+
+impl SchemaDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Whether any change is breaking.
+    pub fn is_breaking(&self) -> bool {
+        self.changes.iter().any(SchemaChange::is_breaking)
+    }
+
+    /// Format as a human-readable tree, with each path followed by the
+    /// changes at it and then the paths below it, and breaking changes
+    /// marked. Paths with no changes of their own and only one path below
+    /// them are folded into it.
+    pub fn pretty_fmt(&self) -> String {
+        let mut tree = ChangeTree {
+            segment: None,
+            changes: Vec::new(),
+            children: Vec::new(),
+        };
+        for change in &self.changes {
+            tree.insert(change.path.segments(), change);
+        }
+        let mut lines = Vec::new();
+        if !self.is_empty() {
+            tree.inner_pretty_fmt(&mut lines, 0);
+        }
+        lines.join("\n")
+    }
+}
+
+/// Changes at some path and below it. See `SchemaDiff::pretty_fmt`.
+struct ChangeTree<'a> {
+    /// Last segment of the path, or `None` for the root.
+    segment: Option<&'a PathSegment>,
+    changes: Vec<&'a SchemaChange>,
+    /// In the order they were first reached.
+    children: Vec<ChangeTree<'a>>,
+}
+
+impl<'a> ChangeTree<'a> {
+    fn insert(&mut self, segments: &'a [PathSegment], change: &'a SchemaChange) {
+        let (segment, rest) =
+            match segments.split_first() {
+                Some(split) => split,
+                None => {
+                    self.changes.push(change);
+                    return;
+                }
+            };
+        let i =
+            match self.children.iter().position(|child| child.segment == Some(segment)) {
+                Some(i) => i,
+                None => {
+                    self.children.push(ChangeTree {
+                        segment: Some(segment),
+                        changes: Vec::new(),
+                        children: Vec::new(),
+                    });
+                    self.children.len() - 1
+                }
+            };
+        self.children[i].insert(rest, change);
+    }
+
+    fn inner_pretty_fmt(&self, lines: &mut Vec<String>, indent: usize) {
+        let mut line = String::new();
+        for _ in 0..indent {
+            line.push_str("    ");
+        }
+        let mut node = self;
+        if let Some(segment) = node.segment {
+            write!(&mut line, "{}", segment).unwrap();
+        }
+        while node.changes.is_empty() && node.children.len() == 1 {
+            node = &node.children[0];
+            write!(&mut line, "{}", node.segment.unwrap()).unwrap();
+        }
+        if node.segment.is_none() {
+            line.push('.');
+        }
+        line.push(':');
+        lines.push(line);
+        for change in &node.changes {
+            let mut line = String::new();
+            for _ in 0..indent + 1 {
+                line.push_str("    ");
+            }
+            write!(&mut line, "- {}", change.kind).unwrap();
+            if change.is_breaking() {
+                line.push_str(" (breaking)");
+            }
+            lines.push(line);
+        }
+        for child in &node.children {
+            child.inner_pretty_fmt(lines, indent + 1);
+        }
+    }
+}
+
+impl Display for SchemaChangeKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &SchemaChangeKind::Replaced { old, new } => write!(
+                f,
+                "{} replaced with {}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::ScalarType { old, new } => write!(
+                f,
+                "{} changed to {}",
+                old.display_str(),
+                new.display_str(),
+            ),
+            &SchemaChangeKind::OptionAdded => f.write_str("wrapped in option"),
+            &SchemaChangeKind::OptionRemoved => f.write_str("no longer option"),
+            &SchemaChangeKind::SeqLen { old, new } => write!(
+                f,
+                "seq of {} changed to seq of {}",
+                SeqLenDisplay(old),
+                SeqLenDisplay(new),
+            ),
+            &SchemaChangeKind::TupleLen { old, new } => write!(
+                f,
+                "tuple of len {} changed to tuple of len {}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::FieldAdded { ref name, has_default } => write!(
+                f,
+                "field {:?} added{}",
+                name,
+                if has_default { "" } else { ", with no default" },
+            ),
+            &SchemaChangeKind::FieldRemoved(ref name) => write!(
+                f,
+                "field {:?} removed",
+                name,
+            ),
+            &SchemaChangeKind::FieldRenamed { ref old, ref new, has_default } => write!(
+                f,
+                "field {:?} renamed to {:?}{}",
+                old,
+                new,
+                if has_default { "" } else { ", with no default" },
+            ),
+            &SchemaChangeKind::FieldsReordered { ref old, ref new } => write!(
+                f,
+                "fields reordered from {:?} to {:?}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::VariantAdded(ref name) => write!(
+                f,
+                "variant {:?} added",
+                name,
+            ),
+            &SchemaChangeKind::VariantRemoved(ref name) => write!(
+                f,
+                "variant {:?} removed",
+                name,
+            ),
+            &SchemaChangeKind::VariantRenamed { ref old, ref new } => write!(
+                f,
+                "variant {:?} renamed to {:?}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::VariantsReordered { ref old, ref new } => write!(
+                f,
+                "variants reordered from {:?} to {:?}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::RecurseLevel { old, new } => write!(
+                f,
+                "recurse level {} changed to {}",
+                old,
+                new,
+            ),
+            &SchemaChangeKind::IllegalSchema => f.write_str("illegal schema"),
+        }
+    }
+}
+
+struct SeqLenDisplay(Option<usize>);
+
+impl Display for SeqLenDisplay {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.0 {
+            Some(len) => write!(f, "len {}", len),
+            None => f.write_str("variable len"),
+        }
+    }
+}
+
+impl Display for SchemaChange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl Display for SchemaDiff {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&self.pretty_fmt())
+    }
+}
+
+/// List every change from `old` to `new`. See module-level docs for how
+/// fields and variants are matched up.
+pub fn diff_schemas(old: &Schema, new: &Schema) -> SchemaDiff {
+    let mut diff = Diff {
+        old_stack: Vec::new(),
+        new_stack: Vec::new(),
+        in_progress: Vec::new(),
+        path: Path::new(),
+        changes: Vec::new(),
+    };
+    diff.diff(old, new);
+    SchemaDiff {
+        changes: diff.changes,
+    }
+}
+
+struct Diff<'a> {
+    old_stack: Vec<&'a Schema>,
+    new_stack: Vec<&'a Schema>,
+    /// Pairs of (old, new) currently being diffed, by address, so that
+    /// recursive schemas are only diffed once.
+    in_progress: Vec<(*const Schema, *const Schema)>,
+    path: Path,
+    changes: Vec<SchemaChange>,
+}
+
+/// Match up old and new named items, fields or variants, by their index.
+/// `None` on one side for an item added or removed. Items in both come
+/// first, in new order.
+fn match_names(
+    old: &[&str],
+    new: &[&str],
+    same_inner: impl Fn(usize, usize) -> bool,
+) -> Vec<(Option<usize>, Option<usize>)> {
+    let mut old_matched = vec![false; old.len()];
+    let mut new_matched = vec![false; new.len()];
+    let mut both = Vec::new();
+    for (j, &name) in new.iter().enumerate() {
+        if let Some(i) = old.iter().position(|&o| o == name) {
+            old_matched[i] = true;
+            new_matched[j] = true;
+            both.push((i, j));
+        }
+    }
+    // renames
+    for j in 0..new.len().min(old.len()) {
+        if !new_matched[j] && !old_matched[j] && same_inner(j, j) {
+            old_matched[j] = true;
+            new_matched[j] = true;
+            both.push((j, j));
+        }
+    }
+    both.sort_by_key(|&(_, j)| j);
+
+    let mut pairs = both
+        .into_iter()
+        .map(|(i, j)| (Some(i), Some(j)))
+        .collect::<Vec<_>>();
+    for (i, _) in old_matched.iter().enumerate().filter(|&(_, &m)| !m) {
+        pairs.push((Some(i), None));
+    }
+    for (j, _) in new_matched.iter().enumerate().filter(|&(_, &m)| !m) {
+        pairs.push((None, Some(j)));
+    }
+    pairs
+}
+
+/// If the items present in both are in a different order, their names in
+/// old order and in new order.
+fn reordering(
+    old: &[&str],
+    new: &[&str],
+    pairs: &[(Option<usize>, Option<usize>)],
+) -> Option<(Vec<String>, Vec<String>)> {
+    let mut common = pairs
+        .iter()
+        .filter_map(|&(i, j)| i.and_then(|i| j.map(|j| (i, j))))
+        .filter(|&(i, j)| old[i] == new[j])
+        .collect::<Vec<_>>();
+    common.sort_by_key(|&(i, _)| i);
+    let old_order = common.iter().map(|&(i, _)| old[i].to_owned()).collect::<Vec<_>>();
+    common.sort_by_key(|&(_, j)| j);
+    let new_order = common.iter().map(|&(_, j)| new[j].to_owned()).collect::<Vec<_>>();
+    if old_order != new_order {
+        Some((old_order, new_order))
+    } else {
+        None
+    }
+}
+
+impl<'a> Diff<'a> {
+    fn change(&mut self, kind: SchemaChangeKind) {
+        self.changes.push(SchemaChange {
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    fn diff_in(&mut self, segment: PathSegment, old: &'a Schema, new: &'a Schema) {
+        self.path.push(segment);
+        self.diff(old, new);
+        self.path.pop();
+    }
+
+    fn diff(&mut self, old: &'a Schema, new: &'a Schema) {
+        let recurse_levels =
+            match (old, new) {
+                (&Schema::Recurse(o), &Schema::Recurse(n)) => Some((o, n)),
+                _ => None,
+            };
+        let (old, new) =
+            match (
+                old.resolve(&self.old_stack),
+                new.resolve(&self.new_stack),
+            ) {
+                (Some(old), Some(new)) => (old, new),
+                _ => {
+                    self.change(SchemaChangeKind::IllegalSchema);
+                    return;
+                }
+            };
+        let pair = (old as *const Schema, new as *const Schema);
+        if self.in_progress.contains(&pair) {
+            return;
+        }
+        if let Some((o, n)) = recurse_levels {
+            // both recurse, but not to corresponding places
+            self.change(SchemaChangeKind::RecurseLevel { old: o, new: n });
+            return;
+        }
+        self.in_progress.push(pair);
+        self.old_stack.push(old);
+        self.new_stack.push(new);
+
+        match (old, new) {
+            (&Schema::Scalar(o), &Schema::Scalar(n)) => {
+                if o != n {
+                    self.change(SchemaChangeKind::ScalarType { old: o, new: n });
+                }
+            }
+            (&Schema::Str, &Schema::Str)
+            | (&Schema::Bytes, &Schema::Bytes)
+            | (&Schema::Unit, &Schema::Unit) => (),
+            (&Schema::Option(ref o), &Schema::Option(ref n)) => {
+                self.diff_in(PathSegment::Some, o, n);
+            }
+            (o, &Schema::Option(ref n)) => {
+                self.change(SchemaChangeKind::OptionAdded);
+                // the old schema has no option layer, so to keep its
+                // recursion levels right it mustn't be stacked twice
+                self.old_stack.pop();
+                self.diff_in(PathSegment::Some, o, n);
+                self.old_stack.push(o);
+            }
+            (&Schema::Option(ref o), n) => {
+                self.change(SchemaChangeKind::OptionRemoved);
+                self.new_stack.pop();
+                self.diff_in(PathSegment::Some, o, n);
+                self.new_stack.push(n);
+            }
+            (
+                &Schema::Seq(SeqSchema { len: o_len, inner: ref o }),
+                &Schema::Seq(SeqSchema { len: n_len, inner: ref n }),
+            ) => {
+                if o_len != n_len {
+                    self.change(SchemaChangeKind::SeqLen { old: o_len, new: n_len });
+                }
+                self.diff_in(PathSegment::SeqElem(None), o, n);
+            }
+            (&Schema::Tuple(ref os), &Schema::Tuple(ref ns)) => {
+                if os.len() != ns.len() {
+                    self.change(SchemaChangeKind::TupleLen {
+                        old: os.len(),
+                        new: ns.len(),
+                    });
+                }
+                for (i, (o, n)) in os.iter().zip(ns).enumerate() {
+                    self.diff_in(PathSegment::TupleElem(i), o, n);
+                }
+            }
+            (&Schema::Struct(ref os), &Schema::Struct(ref ns)) => {
+                let old_names = os.iter().map(|f| &f.name[..]).collect::<Vec<_>>();
+                let new_names = ns.iter().map(|f| &f.name[..]).collect::<Vec<_>>();
+                let pairs = match_names(&old_names, &new_names, |i, j| {
+                    os[i].inner == ns[j].inner
+                });
+                if let Some((old, new)) = reordering(&old_names, &new_names, &pairs) {
+                    self.change(SchemaChangeKind::FieldsReordered { old, new });
+                }
+                for (i, j) in pairs {
+                    match (i, j) {
+                        (Some(i), Some(j)) => {
+                            if os[i].name != ns[j].name {
+                                self.change(SchemaChangeKind::FieldRenamed {
+                                    old: os[i].name.clone(),
+                                    new: ns[j].name.clone(),
+                                    has_default: ns[j].inner.has_default(),
+                                });
+                            }
+                            self.diff_in(
+                                PathSegment::Field(ns[j].name.clone()),
+                                &os[i].inner,
+                                &ns[j].inner,
+                            );
+                        }
+                        (Some(i), None) => self.change(SchemaChangeKind::FieldRemoved(
+                            os[i].name.clone(),
+                        )),
+                        (None, Some(j)) => self.change(SchemaChangeKind::FieldAdded {
+                            name: ns[j].name.clone(),
+                            has_default: ns[j].inner.has_default(),
+                        }),
+                        (None, None) => unreachable!(),
+                    }
+                }
+            }
+            (&Schema::Enum(ref os), &Schema::Enum(ref ns)) => {
+                let old_names = os.iter().map(|v| &v.name[..]).collect::<Vec<_>>();
+                let new_names = ns.iter().map(|v| &v.name[..]).collect::<Vec<_>>();
+                let pairs = match_names(&old_names, &new_names, |i, j| {
+                    os[i].inner == ns[j].inner
+                });
+                if let Some((old, new)) = reordering(&old_names, &new_names, &pairs) {
+                    self.change(SchemaChangeKind::VariantsReordered { old, new });
+                }
+                for (i, j) in pairs {
+                    match (i, j) {
+                        (Some(i), Some(j)) => {
+                            if os[i].name != ns[j].name {
+                                self.change(SchemaChangeKind::VariantRenamed {
+                                    old: os[i].name.clone(),
+                                    new: ns[j].name.clone(),
+                                });
+                            }
+                            self.diff_in(
+                                PathSegment::Variant(ns[j].name.clone()),
+                                &os[i].inner,
+                                &ns[j].inner,
+                            );
+                        }
+                        (Some(i), None) => self.change(SchemaChangeKind::VariantRemoved(
+                            os[i].name.clone(),
+                        )),
+                        (None, Some(j)) => self.change(SchemaChangeKind::VariantAdded(
+                            ns[j].name.clone(),
+                        )),
+                        (None, None) => unreachable!(),
+                    }
+                }
+            }
+            (o, n) => self.change(SchemaChangeKind::Replaced {
+                old: o.non_recursive_display_str(),
+                new: n.non_recursive_display_str(),
+            }),
+        }
+
+        self.old_stack.pop();
+        self.new_stack.pop();
+        self.in_progress.pop();
+    }
+}
@@ -0,0 +1,375 @@
+//! Annotated hex dumps of encoded messages, for debugging wire problems.
+
+use crate::{
+    error::{
+        Error,
+        Result,
+    },
+    schema::{
+        Schema,
+        SeqSchema,
+    },
+    path::{
+        Path,
+        PathSegment,
+    },
+    value::ScalarValue,
+    var_len::write_var_len_uint,
+    Decoder,
+    CoderState,
+    CoderStateAlloc,
+    SizeCounter,
+};
+use std::{
+    fmt::{self, Formatter, Display},
+    io::Read,
+    ops::Range,
+};
+
+
+/// Breakdown of an encoded message into the byte ranges of every value in
+/// it, and of the bytes which frame them. See `hex_dump`.
+#[derive(Debug)]
+pub struct HexDump {
+    /// The whole message.
+    pub bytes: Vec<u8>,
+    /// Entries in the order their ranges start, with a value's entry before
+    /// the entries within it.
+    pub entries: Vec<DumpEntry>,
+    /// Error decoding the message, if it's invalid. Entries are present up
+    /// to where the error occurred.
+    pub error: Option<Error>,
+    /// Offset the message ended at, before any trailing bytes.
+    pub end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DumpEntry {
+    /// Byte range within the message.
+    pub range: Range<usize>,
+    /// Nesting depth, for indentation. The root value is at depth 0, and the
+    /// framing bytes of a value are one deeper than it.
+    pub depth: usize,
+    /// Path to the value this entry is, or is part of.
+    pub path: Path,
+    pub kind: DumpEntryKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DumpEntryKind {
+    /// Entire scalar value.
+    Scalar(ScalarValue),
+    /// Str contents, after the len prefix.
+    Str(String),
+    /// Bytes contents, after the len prefix.
+    Bytes,
+    /// Unit value, which is always 0 bytes.
+    Unit,
+    /// Var len length prefix of a str, bytes, or var len seq.
+    Len(usize),
+    /// Byte saying whether an option is some.
+    Someness(bool),
+    /// Enum variant ordinal, which is as many bytes as are needed for the
+    /// enum's highest ordinal, possibly 0.
+    Ordinal {
+        ord: usize,
+        name: String,
+    },
+    /// Entire option value.
+    Option,
+    /// Entire seq value.
+    Seq {
+        len: usize,
+    },
+    /// Entire tuple value.
+    Tuple,
+    /// Entire struct value. The fields' entries within it mark where each
+    /// field starts and ends.
+    Struct,
+    /// Entire enum value.
+    Enum,
+}
+
+/// Decode `bytes` as a message of `schema`, recording the byte range and
+/// meaning of every part of it. Doesn't fail on an invalid message, but
+/// rather records the error and how far it got.
+pub fn hex_dump(schema: &Schema, bytes: &[u8]) -> HexDump {
+    let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+    let mut read = bytes;
+    let mut d = Decoder::new(&mut coder, &mut read);
+    let mut dumper = Dumper {
+        entries: Vec::new(),
+        open: Vec::new(),
+        path: Path::new(),
+    };
+    let result = dumper.dump(&mut d, 0);
+    let end = pos(&d);
+    drop(d);
+    let error = result.and_then(|()| coder.is_finished_or_err()).err();
+    for i in dumper.open {
+        dumper.entries[i].range.end = end;
+    }
+    HexDump {
+        bytes: bytes.to_vec(),
+        entries: dumper.entries,
+        error,
+        end,
+    }
+}
+
+fn pos<R>(d: &Decoder<R>) -> usize {
+    d.position() as usize
+}
+
+/// Number of bytes `n` takes to encode var len.
+fn var_len_size(n: usize) -> usize {
+    let mut counter = SizeCounter::new();
+    write_var_len_uint(&mut counter, n as u128).unwrap();
+    counter.len()
+}
+
+struct Dumper {
+    entries: Vec<DumpEntry>,
+    /// Indices of entries whose values are still being decoded.
+    open: Vec<usize>,
+    path: Path,
+}
+
+impl Dumper {
+    fn push(&mut self, range: Range<usize>, depth: usize, kind: DumpEntryKind) {
+        self.entries.push(DumpEntry {
+            range,
+            depth,
+            path: self.path.clone(),
+            kind,
+        });
+    }
+
+    /// Push an entry for a value whose end isn't known yet.
+    fn open(&mut self, start: usize, depth: usize, kind: DumpEntryKind) {
+        self.open.push(self.entries.len());
+        self.push(start..start, depth, kind);
+    }
+
+    fn close(&mut self, end: usize) {
+        let i = self.open.pop().unwrap();
+        self.entries[i].range.end = end;
+    }
+
+    fn dump_in<R: Read>(
+        &mut self,
+        d: &mut Decoder<R>,
+        segment: PathSegment,
+        depth: usize,
+    ) -> Result<()> {
+        self.path.push(segment);
+        self.dump(d, depth)?;
+        self.path.pop();
+        Ok(())
+    }
+
+    fn dump<R: Read>(&mut self, d: &mut Decoder<R>, depth: usize) -> Result<()> {
+        let start = pos(d);
+        match d.need()? {
+            &Schema::Scalar(scalar_type) => {
+                let value = ScalarValue::decode_from(d, scalar_type)?;
+                self.push(start..pos(d), depth, DumpEntryKind::Scalar(value));
+            }
+            &Schema::Str => {
+                let s = d.decode_str()?;
+                let contents = start + var_len_size(s.len())..pos(d);
+                self.push(start..contents.start, depth, DumpEntryKind::Len(s.len()));
+                self.push(contents, depth, DumpEntryKind::Str(s));
+            }
+            &Schema::Bytes => {
+                let len = d.decode_bytes()?.len();
+                let contents = start + var_len_size(len)..pos(d);
+                self.push(start..contents.start, depth, DumpEntryKind::Len(len));
+                self.push(contents, depth, DumpEntryKind::Bytes);
+            }
+            &Schema::Unit => {
+                d.decode_unit()?;
+                self.push(start..start, depth, DumpEntryKind::Unit);
+            }
+            &Schema::Option(_) => {
+                self.open(start, depth, DumpEntryKind::Option);
+                let is_some = d.begin_option()?;
+                self.push(start..pos(d), depth + 1, DumpEntryKind::Someness(is_some));
+                if is_some {
+                    self.dump_in(d, PathSegment::Some, depth + 1)?;
+                }
+                self.close(pos(d));
+            }
+            &Schema::Seq(SeqSchema { len, inner: _ }) => {
+                self.open(start, depth, DumpEntryKind::Seq { len: 0 });
+                let len =
+                    match len {
+                        Some(len) => {
+                            d.begin_fixed_len_seq(len)?;
+                            len
+                        }
+                        None => {
+                            let len = d.begin_var_len_seq()?;
+                            self.push(start..pos(d), depth + 1, DumpEntryKind::Len(len));
+                            len
+                        }
+                    };
+                let i = *self.open.last().unwrap();
+                self.entries[i].kind = DumpEntryKind::Seq { len };
+                for i in 0..len {
+                    d.begin_seq_elem()?;
+                    self.dump_in(d, PathSegment::SeqElem(Some(i)), depth + 1)?;
+                }
+                d.finish_seq()?;
+                self.close(pos(d));
+            }
+            &Schema::Tuple(ref inners) => {
+                self.open(start, depth, DumpEntryKind::Tuple);
+                d.begin_tuple()?;
+                for i in 0..inners.len() {
+                    d.begin_tuple_elem()?;
+                    self.dump_in(d, PathSegment::TupleElem(i), depth + 1)?;
+                }
+                d.finish_tuple()?;
+                self.close(pos(d));
+            }
+            &Schema::Struct(ref fields) => {
+                self.open(start, depth, DumpEntryKind::Struct);
+                d.begin_struct()?;
+                for field in fields {
+                    d.begin_struct_field(&field.name)?;
+                    self.dump_in(d, PathSegment::Field(field.name.clone()), depth + 1)?;
+                }
+                d.finish_struct()?;
+                self.close(pos(d));
+            }
+            &Schema::Enum(ref variants) => {
+                self.open(start, depth, DumpEntryKind::Enum);
+                let ord = d.begin_enum()?;
+                let name = &variants[ord].name;
+                self.push(start..pos(d), depth + 1, DumpEntryKind::Ordinal {
+                    ord,
+                    name: name.clone(),
+                });
+                d.begin_enum_variant(name)?;
+                self.dump_in(d, PathSegment::Variant(name.clone()), depth + 1)?;
+                self.close(pos(d));
+            }
+            &Schema::Recurse(_) => unreachable!(),
+        }
+        Ok(())
+    }
+}
+
+/// Max number of bytes shown per line.
+const MAX_LINE_BYTES: usize = 8;
+
+impl Display for DumpEntryKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &DumpEntryKind::Scalar(value) => write!(f, "{}", value),
+            &DumpEntryKind::Str(ref s) => write!(f, "str {:?}", s),
+            &DumpEntryKind::Bytes => f.write_str("bytes"),
+            &DumpEntryKind::Unit => f.write_str("unit"),
+            &DumpEntryKind::Len(len) => write!(f, "len = {}", len),
+            &DumpEntryKind::Someness(true) => f.write_str("some"),
+            &DumpEntryKind::Someness(false) => f.write_str("none"),
+            &DumpEntryKind::Ordinal { ord, ref name } => write!(
+                f,
+                "variant {} (name = {:?})",
+                ord,
+                name,
+            ),
+            &DumpEntryKind::Option => f.write_str("option"),
+            &DumpEntryKind::Seq { len } => write!(f, "seq of {}", len),
+            &DumpEntryKind::Tuple => f.write_str("tuple"),
+            &DumpEntryKind::Struct => f.write_str("struct"),
+            &DumpEntryKind::Enum => f.write_str("enum"),
+        }
+    }
+}
+
+/// Write one line, of the offset, up to `MAX_LINE_BYTES` of bytes in hex,
+/// and the description.
+fn write_line(
+    f: &mut Formatter,
+    bytes: &[u8],
+    range: Range<usize>,
+    depth: usize,
+    desc: fmt::Arguments,
+) -> fmt::Result {
+    write!(f, "{:08x} ", range.start)?;
+    let shown = &bytes[range.clone()][..range.len().min(MAX_LINE_BYTES)];
+    for byte in shown {
+        write!(f, " {:02x}", byte)?;
+    }
+    let width = 3 * shown.len();
+    if range.len() > MAX_LINE_BYTES {
+        f.write_str(" ..")?;
+    } else {
+        write!(f, "{:1$}", "", 3 * MAX_LINE_BYTES + 3 - width)?;
+    }
+    f.write_str("  ")?;
+    for _ in 0..depth {
+        f.write_str("  ")?;
+    }
+    writeln!(f, "{}", desc)
+}
+
+impl Display for HexDump {
+    /// One line per entry, such as:
+    ///
+    /// ```text
+    /// 00000000  00 05 01 07 09                - . enum (5 bytes)
+    /// 00000000  00                              variant 0 (name = "Branch")
+    /// 00000001  05 01 07 09                   - .Branch struct (4 bytes)
+    /// 00000001  05                              - .Branch.value u32 = 5
+    /// ```
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for entry in &self.entries {
+            let is_value = !matches!(
+                entry.kind,
+                DumpEntryKind::Len(_)
+                | DumpEntryKind::Someness(_)
+                | DumpEntryKind::Ordinal { .. }
+            );
+            let is_composite = matches!(
+                entry.kind,
+                DumpEntryKind::Option
+                | DumpEntryKind::Seq { .. }
+                | DumpEntryKind::Tuple
+                | DumpEntryKind::Struct
+                | DumpEntryKind::Enum
+            );
+            let range = entry.range.clone();
+            if is_composite {
+                write_line(f, &self.bytes, range.clone(), entry.depth, format_args!(
+                    "- {} {} ({} bytes)",
+                    entry.path,
+                    entry.kind,
+                    range.len(),
+                ))?;
+            } else if is_value {
+                write_line(f, &self.bytes, range, entry.depth, format_args!(
+                    "- {} {}",
+                    entry.path,
+                    entry.kind,
+                ))?;
+            } else {
+                write_line(f, &self.bytes, range, entry.depth, format_args!(
+                    "  {}",
+                    entry.kind,
+                ))?;
+            }
+        }
+        if let Some(ref error) = self.error {
+            writeln!(f, "{:08x}  error: {}, {}", self.end, error.kind(), error.inner())?;
+        } else if self.end < self.bytes.len() {
+            write_line(f, &self.bytes, self.end..self.bytes.len(), 0, format_args!(
+                "trailing bytes ({} bytes)",
+                self.bytes.len() - self.end,
+            ))?;
+        }
+        Ok(())
+    }
+}
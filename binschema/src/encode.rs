@@ -0,0 +1,329 @@
+//! Trait for types which encode themselves by calling `Encoder` methods
+//! directly rather than going through serde, and implementations for common
+//! types.
+
+use crate::{
+    error::{
+        Result,
+        error,
+    },
+    Encoder,
+};
+use std::{
+    collections::{
+        BinaryHeap,
+        BTreeSet,
+        HashSet,
+        LinkedList,
+        VecDeque,
+        BTreeMap,
+        HashMap,
+    },
+    ops::{
+        Range,
+        RangeInclusive,
+        Bound,
+    },
+    borrow::Cow,
+    io::Write,
+};
+
+
+/// Type which can encode itself with an `Encoder`, in conformance with its
+/// `KnownSchema`. Can be derived alongside `KnownSchema`.
+pub trait Encode {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()>;
+
+    /// Encode the elements of a seq which has been begun. Overridden by `u8`
+    /// to encode them in one write.
+    #[doc(hidden)]
+    fn encode_seq_elems<W: Write>(elems: &[Self], encoder: &mut Encoder<W>) -> Result<()>
+    where
+        Self: Sized,
+    {
+        for elem in elems {
+            encoder.begin_seq_elem()?;
+            elem.encode(encoder)?;
+        }
+        Ok(())
+    }
+}
+
+macro_rules! scalars_encode {
+    ($($t:ident $m:ident,)*)=>{$(
+        impl Encode for $t {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+                encoder.$m(*self)
+            }
+        }
+    )*};
+}
+
+scalars_encode!(
+    u16 encode_u16,
+    u32 encode_u32,
+    u64 encode_u64,
+    u128 encode_u128,
+    i8 encode_i8,
+    i16 encode_i16,
+    i32 encode_i32,
+    i64 encode_i64,
+    i128 encode_i128,
+    f32 encode_f32,
+    f64 encode_f64,
+    char encode_char,
+    bool encode_bool,
+);
+
+impl Encode for u8 {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_u8(*self)
+    }
+
+    fn encode_seq_elems<W: Write>(elems: &[Self], encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_u8_seq_elems(elems)
+    }
+}
+
+impl Encode for usize {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        let n = u64::try_from(*self)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(encoder.coder_state()),
+                "{} out of range for a u64",
+                self,
+            ))?;
+        encoder.encode_u64(n)
+    }
+}
+
+impl Encode for isize {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        let n = i64::try_from(*self)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(encoder.coder_state()),
+                "{} out of range for an i64",
+                self,
+            ))?;
+        encoder.encode_i64(n)
+    }
+}
+
+impl Encode for str {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_str(self)
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_str(self)
+    }
+}
+
+impl Encode for () {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.encode_unit()
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        match self {
+            &None => encoder.encode_none(),
+            &Some(ref inner) => {
+                encoder.begin_some()?;
+                inner.encode(encoder)
+            }
+        }
+    }
+}
+
+/// Encode the elements of a var len seq.
+fn encode_var_len_seq<'e, W, T, I>(
+    encoder: &mut Encoder<W>,
+    len: usize,
+    elems: I,
+) -> Result<()>
+where
+    W: Write,
+    T: Encode + 'e,
+    I: IntoIterator<Item=&'e T>,
+{
+    encoder.begin_var_len_seq(len)?;
+    for elem in elems {
+        encoder.begin_seq_elem()?;
+        elem.encode(encoder)?;
+    }
+    encoder.finish_seq()
+}
+
+macro_rules! seqs_encode {
+    ($($c:ident,)*)=>{$(
+        impl<T: Encode> Encode for $c<T> {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+                encode_var_len_seq(encoder, self.len(), self)
+            }
+        }
+    )*};
+}
+
+seqs_encode!(
+    BinaryHeap,
+    BTreeSet,
+    HashSet,
+    LinkedList,
+);
+
+impl<T: Encode> Encode for VecDeque<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        let (front, back) = self.as_slices();
+        encoder.begin_var_len_seq(self.len())?;
+        T::encode_seq_elems(front, encoder)?;
+        T::encode_seq_elems(back, encoder)?;
+        encoder.finish_seq()
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.begin_var_len_seq(self.len())?;
+        T::encode_seq_elems(self, encoder)?;
+        encoder.finish_seq()
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        <[T]>::encode(self, encoder)
+    }
+}
+
+impl<T: Encode, const LEN: usize> Encode for [T; LEN] {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.begin_fixed_len_seq(LEN)?;
+        T::encode_seq_elems(self, encoder)?;
+        encoder.finish_seq()
+    }
+}
+
+macro_rules! maps_encode {
+    ($($c:ident,)*)=>{$(
+        impl<K: Encode, V: Encode> Encode for $c<K, V> {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+                encoder.begin_var_len_seq(self.len())?;
+                for (key, val) in self {
+                    encoder.begin_seq_elem()?;
+                    encoder.begin_tuple()?;
+                    encoder.begin_tuple_elem()?;
+                    key.encode(encoder)?;
+                    encoder.begin_tuple_elem()?;
+                    val.encode(encoder)?;
+                    encoder.finish_tuple()?;
+                }
+                encoder.finish_seq()
+            }
+        }
+    )*};
+}
+
+maps_encode!(
+    BTreeMap,
+    HashMap,
+);
+
+macro_rules! tuples_encode {
+    ($(($($t:ident $i:tt),*),)*)=>{$(
+        impl<$($t: Encode),*> Encode for ($($t,)*) {
+            fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+                encoder.begin_tuple()?;
+                $(
+                    encoder.begin_tuple_elem()?;
+                    self.$i.encode(encoder)?;
+                )*
+                encoder.finish_tuple()
+            }
+        }
+    )*};
+}
+
+tuples_encode!(
+    (A 0),
+    (A 0, B 1),
+    (A 0, B 1, C 2),
+    (A 0, B 1, C 2, D 3),
+    (A 0, B 1, C 2, D 3, E 4),
+    (A 0, B 1, C 2, D 3, E 4, F 5),
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6),
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7),
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8),
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9),
+    (A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10),
+);
+
+impl<T: Encode> Encode for Range<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.begin_struct()?;
+        encoder.begin_struct_field("start")?;
+        self.start.encode(encoder)?;
+        encoder.begin_struct_field("end")?;
+        self.end.encode(encoder)?;
+        encoder.finish_struct()
+    }
+}
+
+impl<T: Encode> Encode for RangeInclusive<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        encoder.begin_struct()?;
+        encoder.begin_struct_field("start")?;
+        self.start().encode(encoder)?;
+        encoder.begin_struct_field("end")?;
+        self.end().encode(encoder)?;
+        encoder.finish_struct()
+    }
+}
+
+impl<T: Encode> Encode for Bound<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        match self {
+            &Bound::Unbounded => {
+                encoder.begin_enum(0, "Unbounded")?;
+                encoder.encode_unit()
+            }
+            &Bound::Included(ref inner) => {
+                encoder.begin_enum(1, "Included")?;
+                inner.encode(encoder)
+            }
+            &Bound::Excluded(ref inner) => {
+                encoder.begin_enum(2, "Excluded")?;
+                inner.encode(encoder)
+            }
+        }
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &T {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        T::encode(self, encoder)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for &mut T {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        T::encode(self, encoder)
+    }
+}
+
+impl<T: Encode + ?Sized> Encode for Box<T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        T::encode(self, encoder)
+    }
+}
+
+impl<T: Encode + ToOwned + ?Sized> Encode for Cow<'_, T> {
+    fn encode<W: Write>(&self, encoder: &mut Encoder<W>) -> Result<()> {
+        T::encode(self, encoder)
+    }
+}
@@ -1,9 +1,6 @@
 
 use crate::{
-    error::{
-        Error,
-        Result,
-    },
+    error::Result,
     do_if_err::DoIfErr,
     coder::coder::CoderState,
     var_len::{
@@ -13,18 +10,50 @@ use crate::{
     },
     schema::Schema,
 };
-use std::io::Write;
+use std::io::{
+    self,
+    Write,
+};
 
 
 /// Encodes a value to a `std::io::Write` comforming to a schema.
 pub struct Encoder<'a, 'b, W> {
     state: &'b mut CoderState<'a>,
-    write: &'b mut W,
+    write: CountingWrite<'b, W>,
+}
+
+/// Write adapter which counts bytes written.
+struct CountingWrite<'b, W> {
+    inner: &'b mut W,
+    pos: u64,
+}
+
+impl<'b, W: Write> Write for CountingWrite<'b, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
 }
 
 impl<'a, 'b, W> Encoder<'a, 'b, W> {
     pub fn new(state: &'b mut CoderState<'a>, write: &'b mut W) -> Self {
-        Encoder { state, write }
+        Encoder {
+            state,
+            write: CountingWrite {
+                inner: write,
+                pos: 0,
+            },
+        }
+    }
+
+    /// Number of bytes written so far.
+    pub fn position(&self) -> u64 {
+        self.write.pos
     }
 
     /// Get the schema that needs to be encoded. Fails if has already began
@@ -46,6 +75,7 @@ impl<'a, 'b, W> Encoder<'a, 'b, W> {
 macro_rules! encode_le_bytes {
     ($($m:ident($t:ident) $c:ident,)*)=>{$(
         pub fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
             self.state.$c()?;
             self.write(&n.to_le_bytes())?;
             Ok(())
@@ -56,9 +86,10 @@ macro_rules! encode_le_bytes {
 macro_rules! encode_var_len_uint {
     ($($m:ident($t:ident) $c:ident,)*)=>{$(
         pub fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
             self.state.$c()?;
             write_var_len_uint(&mut self.write, n as u128)
-                .do_if_err(|| self.state.mark_broken())?;
+                .map_err(|e| self.state.broken(e))?;
             Ok(())
         }
     )*};
@@ -67,9 +98,10 @@ macro_rules! encode_var_len_uint {
 macro_rules! encode_var_len_sint {
     ($($m:ident($t:ident) $c:ident,)*)=>{$(
         pub fn $m(&mut self, n: $t) -> Result<()> {
+            self.state.set_offset(self.position());
             self.state.$c()?;
             write_var_len_sint(&mut self.write, n as i128)
-                .do_if_err(|| self.state.mark_broken())?;
+                .map_err(|e| self.state.broken(e))?;
             Ok(())
         }
     )*};
@@ -79,8 +111,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     fn write(&mut self, b: &[u8]) -> Result<()> {
         self.write
             .write_all(b)
-            .map_err(Error::from)
-            .do_if_err(|| self.state.mark_broken())
+            .map_err(|e| self.state.broken(e))
     }
 
     encode_le_bytes!(
@@ -106,34 +137,39 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     );
 
     pub fn encode_bool(&mut self, b: bool) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.code_bool()?;
         self.write(&[b as u8])?;
         Ok(())
     }
 
     pub fn encode_unit(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.code_unit()?;
         Ok(())
     }
 
     pub fn encode_str(&mut self, s: &str) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.code_str()?;
         write_var_len_uint(&mut self.write, s.len() as u128)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         self.write(s.as_bytes())?;
         Ok(())
     }
 
     pub fn encode_bytes(&mut self, s: &[u8]) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.code_bytes()?;
         write_var_len_uint(&mut self.write, s.len() as u128)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         self.write(s)?;
         Ok(())
     }
 
     /// Completely encode an option none value.
     pub fn encode_none(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_option()?;
         self.state.set_option_none();
         self.write(&[0])?;
@@ -143,6 +179,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding an option some value. This should be followed by
     /// encoding the inner value, which then auto-finishes the option.
     pub fn begin_some(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_option()?;
         self.state.set_option_some()?;
         self.write(&[1])?;
@@ -153,6 +190,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// `len` elements with `begin_seq_elem` followed by a call to
     /// `finish_seq`.
     pub fn begin_fixed_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_fixed_len_seq(len)?;
         Ok(())
     }
@@ -160,22 +198,34 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding a var len seq. This should be followed by encoding `len`
     /// elements with `begin_seq_elem` followed by a call to `finish_seq`.
     pub fn begin_var_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_var_len_seq()?;
         self.state.set_var_len_seq_len(len);
         write_var_len_uint(&mut self.write, len as u128)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         Ok(())
     }
     
     /// Begin encoding an element in a seq. This should be followed by encoding
     /// the inner value. See `begin_seq`.
     pub fn begin_seq_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_seq_elem()?;
         Ok(())
     }
 
+    /// Encode elements of a seq of u8 in one write, equivalently to
+    /// `begin_seq_elem` followed by `encode_u8` for each. See `begin_seq`.
+    pub fn encode_u8_seq_elems(&mut self, elems: &[u8]) -> Result<()> {
+        self.state.set_offset(self.position());
+        self.state.code_u8_seq_elems(elems.len())?;
+        self.write(elems)?;
+        Ok(())
+    }
+
     /// Finish encoding a seq. See `begin_seq`.
     pub fn finish_seq(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.finish_seq()?;
         Ok(())
     }
@@ -183,6 +233,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding a tuple. This should be followed by encoding the
     /// elements with `begin_tuple_elem` followed by a call to `finish_tuple`.
     pub fn begin_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_tuple()?;
         Ok(())
     }
@@ -190,12 +241,14 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding an element in a tuple. This should be followed by
     /// encoding the inner value. See `begin_tuple`,
     pub fn begin_tuple_elem(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_tuple_elem()?;
         Ok(())
     }
 
     /// Finish encoding a tuple. See `begin_tuple`.
     pub fn finish_tuple(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.finish_tuple()?;
         Ok(())
     }
@@ -203,6 +256,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding a struct. This should be followed by encoding the
     /// fields with `begin_struct_field` followed by a call to `finish_struct`.
     pub fn begin_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_struct()?;
         Ok(())
     }
@@ -210,12 +264,14 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
     /// Begin encoding a field in a struct. This should be followed by
     /// encoding the inner value. See `begin_struct`,
     pub fn begin_struct_field(&mut self, name: &str) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.begin_struct_field(name)?;
         Ok(())
     }
 
     /// Finish encoding a struct. See `begin_struct`.
     pub fn finish_struct(&mut self) -> Result<()> {
+        self.state.set_offset(self.position());
         self.state.finish_struct()?;
         Ok(())
     }
@@ -227,6 +283,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
         variant_ord: usize,
         variant_name: &str,
     ) -> Result<()> {
+        self.state.set_offset(self.position());
         // as a single all-or-nothing state change (via cancel if non-immediate
         // fail):
         //
@@ -242,7 +299,7 @@ impl<'a, 'b, W: Write> Encoder<'a, 'b, W> {
             .do_if_err(|| self.state.cancel_enum())?;
 
         write_ord(&mut self.write, variant_ord, num_variants)
-            .do_if_err(|| self.state.mark_broken())?;
+            .map_err(|e| self.state.broken(e))?;
         Ok(())
     }
 }
\ No newline at end of file
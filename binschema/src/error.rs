@@ -1,6 +1,10 @@
 //! Error types.
 
-use crate::coder::coder::CoderState;
+use crate::{
+    coder::coder::CoderState,
+    limits::LimitExceeded,
+    path::Path,
+};
 use std::fmt::{self, Formatter, Display};
 
 
@@ -10,6 +14,8 @@ pub type Result<I> = std::result::Result<I, Error>;
 pub struct Error {
     kind: ErrorKind,
     error: Box<dyn std::error::Error + Send + Sync>,
+    path: Option<Path>,
+    offset: Option<u64>,
     coder_state: Option<String>,
 }
 
@@ -45,12 +51,27 @@ pub enum ErrorKind {
     PlatformLimits,
 
     /// The schema itself is illegal. This kind of error can be front-runned by
-    /// validating the schema.
+    /// validating the schema with `Schema::validate`.
     ///
     /// This corresponds with the (en/de)coder being left in a **"broken"**
     /// state that rejects further coding API calls.
     IllegalSchema,
 
+    /// (Only when resolving) data written with the writer schema cannot be
+    /// read with the reader schema. This kind of error can be front-runned by
+    /// checking the schemas with `check_compat`.
+    ///
+    /// This corresponds with the decoder being left in a **"broken"** state
+    /// that rejects further coding API calls.
+    IncompatibleSchemas,
+
+    /// (Only when decoding) the data exceeds one of the decoder's
+    /// `DecodeLimits`. The inner error is a `LimitExceeded`.
+    ///
+    /// This corresponds with the decoder being left in a **"broken"** state
+    /// that rejects further coding API calls.
+    LimitExceeded,
+
     /// The user of this library performed a sequence of API calls that would
     /// never be valid.
     ///
@@ -76,6 +97,8 @@ impl Error {
         Error {
             kind,
             error: error.into(),
+            path: coder_state.map(CoderState::path),
+            offset: coder_state.map(CoderState::offset),
             coder_state: coder_state.map(|state| format!("{:?}", state)),
         }
     }
@@ -91,6 +114,40 @@ impl Error {
         self.kind
     }
 
+    /// Path to the value being coded when the error occurred, such as
+    /// `.users[3].address.Some.zip`. Present if the error was raised with a
+    /// coder state.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_ref()
+    }
+
+    /// Byte offset in the stream at which the coder began the API call that
+    /// failed, such as the start of a scalar which turned out to be
+    /// malformed. Present if the error was raised by an encoder or decoder.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    /// Set the path, offset, and coder state from `coder_state`, if not
+    /// already set.
+    pub(crate) fn with_coder_state(mut self, coder_state: &CoderState) -> Self {
+        if self.offset.is_none() {
+            self.path = self.path.or_else(|| Some(coder_state.path()));
+            self.offset = Some(coder_state.offset());
+            self.coder_state = self.coder_state
+                .or_else(|| Some(format!("{:?}", coder_state)));
+        }
+        self
+    }
+
+    /// Set the path, for errors about values which aren't being coded by a
+    /// coder state.
+    #[cfg(feature = "serde_json")]
+    pub(crate) fn with_path(mut self, path: Path) -> Self {
+        self.path = Some(path);
+        self
+    }
+
     pub fn inner(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
         &*self.error
     }
@@ -106,6 +163,14 @@ impl Error {
 
 impl From<std::io::Error> for Error {
     fn from(error: std::io::Error) -> Self {
+        // unwrap limit errors raised from within reads
+        if error.get_ref().is_some_and(|e| e.is::<LimitExceeded>()) {
+            return Self::new(
+                ErrorKind::LimitExceeded,
+                error.into_inner().unwrap(),
+                None,
+            );
+        }
         Self::new(ErrorKind::Io, error, None)
     }
 }
@@ -118,6 +183,8 @@ impl Display for ErrorKind {
             ErrorKind::SchemaNonConformance => "schema non-comformance error",
             ErrorKind::PlatformLimits => "platform limits or malformed data",
             ErrorKind::IllegalSchema => "illegal schema",
+            ErrorKind::IncompatibleSchemas => "incompatible schemas",
+            ErrorKind::LimitExceeded => "decode limit exceeded",
             ErrorKind::ApiUsage => "API usage error",
             ErrorKind::Other => "unknown error",
         })
@@ -129,6 +196,12 @@ impl Display for Error {
         Display::fmt(&self.kind, f)?;
         f.write_str(", ")?;
         Display::fmt(&self.error, f)?;
+        if let Some(ref path) = self.path {
+            write!(f, "\npath: {}", path)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "\noffset: {}", offset)?;
+        }
         if let Some(ref coder_state) = self.coder_state {
             f.write_str("\nstate: ")?;
             f.write_str(coder_state)?;
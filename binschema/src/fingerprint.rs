@@ -0,0 +1,149 @@
+//! Stable content hash of schemas.
+
+use crate::schema::{
+    Schema,
+    ScalarType,
+    SeqSchema,
+};
+
+
+const FNV_OFFSET_BASIS: u128 = 0x6c62272e07bb014262b821756295c58d;
+const FNV_PRIME: u128 = 0x0000000001000000000000000000013b;
+
+impl Schema {
+    /// Stable 128-bit hash of this schema, so that peers can compare
+    /// schemas without exchanging them in full.
+    ///
+    /// The fingerprint is the 128-bit FNV-1a hash of the schema's canonical
+    /// encoding, and doesn't depend on the platform or on Rust's `Hash`. The
+    /// canonical encoding is a tag byte followed by the schema's contents,
+    /// where integers are 8 bytes little-endian and names are their byte
+    /// length as an integer followed by their UTF-8 bytes:
+    ///
+    /// | schema           | encoding                                           |
+    /// |------------------|----------------------------------------------------|
+    /// | `Scalar(t)`      | `0x00`, then one byte for `t`, below               |
+    /// | `Str`            | `0x01`                                             |
+    /// | `Bytes`          | `0x02`                                             |
+    /// | `Unit`           | `0x03`                                             |
+    /// | `Option(inner)`  | `0x04`, then `inner`                               |
+    /// | `Seq` varlen     | `0x05`, `0x00`, then inner                         |
+    /// | `Seq` fixed len  | `0x05`, `0x01`, len as integer, then inner         |
+    /// | `Tuple(inners)`  | `0x06`, count as integer, then each inner          |
+    /// | `Struct(fields)` | `0x07`, count as integer, then each name and inner |
+    /// | `Enum(variants)` | `0x08`, count as integer, then each name and inner |
+    /// | `Recurse(n)`     | `0x09`, `n` as integer                             |
+    ///
+    /// Scalar type bytes are `0x00` through `0x0d` for `u8`, `u16`, `u32`,
+    /// `u64`, `u128`, `i8`, `i16`, `i32`, `i64`, `i128`, `f32`, `f64`,
+    /// `char`, `bool`, in that order.
+    ///
+    /// 128-bit FNV-1a starts with the offset basis
+    /// `0x6c62272e07bb014262b821756295c58d` and, for each byte, XORs the
+    /// byte into the hash then multiplies it by the prime
+    /// `0x0000000001000000000000000000013b`, wrapping. For example, `Unit`
+    /// is the single byte `0x03`, and its fingerprint is
+    /// `0xd228cb69111a8caf78912b704e4a15ba`.
+    pub fn fingerprint(&self) -> u128 {
+        let mut hasher = Fnv1a(FNV_OFFSET_BASIS);
+        hasher.schema(self);
+        hasher.0
+    }
+}
+
+struct Fnv1a(u128);
+
+impl Fnv1a {
+    fn bytes(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 ^= b as u128;
+            self.0 = self.0.wrapping_mul(FNV_PRIME);
+        }
+    }
+
+    fn byte(&mut self, b: u8) {
+        self.bytes(&[b]);
+    }
+
+    fn int(&mut self, n: usize) {
+        self.bytes(&(n as u64).to_le_bytes());
+    }
+
+    fn name(&mut self, name: &str) {
+        self.int(name.len());
+        self.bytes(name.as_bytes());
+    }
+
+    fn schema(&mut self, schema: &Schema) {
+        match schema {
+            &Schema::Scalar(scalar_type) => {
+                self.byte(0x00);
+                self.byte(scalar_type_byte(scalar_type));
+            }
+            &Schema::Str => self.byte(0x01),
+            &Schema::Bytes => self.byte(0x02),
+            &Schema::Unit => self.byte(0x03),
+            &Schema::Option(ref inner) => {
+                self.byte(0x04);
+                self.schema(inner);
+            }
+            &Schema::Seq(SeqSchema { len, ref inner }) => {
+                self.byte(0x05);
+                match len {
+                    None => self.byte(0x00),
+                    Some(len) => {
+                        self.byte(0x01);
+                        self.int(len);
+                    }
+                }
+                self.schema(inner);
+            }
+            &Schema::Tuple(ref inners) => {
+                self.byte(0x06);
+                self.int(inners.len());
+                for inner in inners {
+                    self.schema(inner);
+                }
+            }
+            &Schema::Struct(ref fields) => {
+                self.byte(0x07);
+                self.int(fields.len());
+                for field in fields {
+                    self.name(&field.name);
+                    self.schema(&field.inner);
+                }
+            }
+            &Schema::Enum(ref variants) => {
+                self.byte(0x08);
+                self.int(variants.len());
+                for variant in variants {
+                    self.name(&variant.name);
+                    self.schema(&variant.inner);
+                }
+            }
+            &Schema::Recurse(n) => {
+                self.byte(0x09);
+                self.int(n);
+            }
+        }
+    }
+}
+
+fn scalar_type_byte(scalar_type: ScalarType) -> u8 {
+    match scalar_type {
+        ScalarType::U8 => 0x00,
+        ScalarType::U16 => 0x01,
+        ScalarType::U32 => 0x02,
+        ScalarType::U64 => 0x03,
+        ScalarType::U128 => 0x04,
+        ScalarType::I8 => 0x05,
+        ScalarType::I16 => 0x06,
+        ScalarType::I32 => 0x07,
+        ScalarType::I64 => 0x08,
+        ScalarType::I128 => 0x09,
+        ScalarType::F32 => 0x0a,
+        ScalarType::F64 => 0x0b,
+        ScalarType::Char => 0x0c,
+        ScalarType::Bool => 0x0d,
+    }
+}
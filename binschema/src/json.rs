@@ -0,0 +1,423 @@
+//! Transcoding between binschema data and JSON, for debugging and for
+//! hand-writing messages.
+//!
+//! Each schema maps to JSON as follows:
+//!
+//! - `u8` through `u64` and `i8` through `i64` are numbers
+//! - `u128` and `i128` are decimal strings, since JSON numbers beyond 53
+//!   bits aren't portable. Numbers are also accepted when reading JSON
+//! - `f32` and `f64` are numbers, except NaN and infinities, which are the
+//!   strings `"NaN"`, `"inf"`, and `"-inf"`
+//! - `char` is a string of that one char
+//! - `bool` is a boolean
+//! - `str` is a string
+//! - `bytes` is a string of the bytes in standard padded base64
+//! - `unit` is `null`
+//! - option is `null` if none, and the inner value if some. If the inner
+//!   schema is itself an option or unit, which could also be `null`, some is
+//!   instead a 1-element array of the inner value
+//! - seq and tuple are arrays
+//! - struct is an object with a key per field, in field order
+//! - enum is an object with a single key, the variant name, whose value is
+//!   the inner value, such as `{"Leaf": 5}`
+
+use crate::{
+    error::{
+        Error,
+        ErrorKind,
+        Result,
+        error,
+        bail,
+        ensure,
+    },
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    path::{
+        Path,
+        PathSegment,
+    },
+    value::{
+        Value,
+        ScalarValue,
+        StructValueField,
+        EnumValue,
+    },
+    Encoder,
+    Decoder,
+    CoderState,
+    CoderStateAlloc,
+};
+use serde_json::{
+    Value as Json,
+    Map,
+    Number,
+};
+
+
+/// Decode `bytes` as a message of `schema`, and print it as pretty JSON. See
+/// module-level docs for the mapping.
+pub fn bytes_to_json(schema: &Schema, bytes: &[u8]) -> Result<String> {
+    let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+    let mut read = bytes;
+    let value = Value::decode_from(&mut Decoder::new(&mut coder, &mut read))?;
+    coder.is_finished_or_err()?;
+    ensure!(
+        read.is_empty(),
+        MalformedData,
+        None,
+        "{} trailing bytes after message",
+        read.len(),
+    );
+    Ok(serde_json::to_string_pretty(&value.to_json()).unwrap())
+}
+
+/// Parse `json` as a value of `schema`, and encode it. See module-level docs
+/// for the mapping.
+pub fn json_to_bytes(schema: &Schema, json: &str) -> Result<Vec<u8>> {
+    let json = serde_json::from_str::<Json>(json)
+        .map_err(|e| Error::new(ErrorKind::MalformedData, e, None))?;
+    let value = Value::from_json(&json, schema)?;
+    let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    value.encode_to(&mut Encoder::new(&mut coder, &mut buf))?;
+    coder.is_finished_or_err()?;
+    Ok(buf)
+}
+
+impl Value {
+    /// Convert to JSON. See `json` module-level docs for the mapping.
+    pub fn to_json(&self) -> Json {
+        match self {
+            &Value::Scalar(scalar) => scalar_to_json(scalar),
+            &Value::Str(ref s) => Json::String(s.clone()),
+            &Value::Bytes(ref b) => Json::String(base64_encode(b)),
+            &Value::Unit => Json::Null,
+            &Value::Option(None) => Json::Null,
+            &Value::Option(Some(ref inner)) => match &**inner {
+                &Value::Option(_) | &Value::Unit => Json::Array(vec![inner.to_json()]),
+                _ => inner.to_json(),
+            },
+            &Value::FixedLenSeq(ref elems)
+            | &Value::VarLenSeq(ref elems)
+            | &Value::Tuple(ref elems) => Json::Array(elems
+                .iter()
+                .map(Value::to_json)
+                .collect()),
+            &Value::Struct(ref fields) => Json::Object(fields
+                .iter()
+                .map(|field| (field.name.clone(), field.value.to_json()))
+                .collect()),
+            &Value::Enum(ref enum_value) => {
+                let mut map = Map::new();
+                map.insert(enum_value.variant_name.clone(), enum_value.value.to_json());
+                Json::Object(map)
+            }
+        }
+    }
+
+    /// Convert from JSON, as a value of `schema`. See `json` module-level
+    /// docs for the mapping. Errors with the path to where the JSON doesn't
+    /// fit the schema.
+    pub fn from_json(json: &Json, schema: &Schema) -> Result<Value> {
+        FromJson {
+            ancestors: Vec::new(),
+            path: Path::new(),
+        }.convert(json, schema)
+    }
+}
+
+fn scalar_to_json(scalar: ScalarValue) -> Json {
+    match scalar {
+        ScalarValue::U8(n) => Json::from(n),
+        ScalarValue::U16(n) => Json::from(n),
+        ScalarValue::U32(n) => Json::from(n),
+        ScalarValue::U64(n) => Json::from(n),
+        ScalarValue::U128(n) => Json::String(n.to_string()),
+        ScalarValue::I8(n) => Json::from(n),
+        ScalarValue::I16(n) => Json::from(n),
+        ScalarValue::I32(n) => Json::from(n),
+        ScalarValue::I64(n) => Json::from(n),
+        ScalarValue::I128(n) => Json::String(n.to_string()),
+        // go through the shortest decimal form, so 1.1f32 prints as 1.1
+        ScalarValue::F32(n) => float_to_json(n.to_string().parse().unwrap()),
+        ScalarValue::F64(n) => float_to_json(n),
+        ScalarValue::Char(c) => Json::String(c.to_string()),
+        ScalarValue::Bool(b) => Json::Bool(b),
+    }
+}
+
+fn float_to_json(n: f64) -> Json {
+    match Number::from_f64(n) {
+        Some(n) => Json::Number(n),
+        None if n.is_nan() => Json::String("NaN".into()),
+        None if n > 0.0 => Json::String("inf".into()),
+        None => Json::String("-inf".into()),
+    }
+}
+
+fn json_kind(json: &Json) -> &'static str {
+    match json {
+        &Json::Null => "null",
+        &Json::Bool(_) => "boolean",
+        &Json::Number(_) => "number",
+        &Json::String(_) => "string",
+        &Json::Array(_) => "array",
+        &Json::Object(_) => "object",
+    }
+}
+
+struct FromJson<'a> {
+    ancestors: Vec<&'a Schema>,
+    path: Path,
+}
+
+impl<'a> FromJson<'a> {
+    fn error(&self, message: String) -> Error {
+        error!(SchemaNonConformance, None, "{}", message).with_path(self.path.clone())
+    }
+
+    fn expected(&self, expected: &str, json: &Json) -> Error {
+        self.error(format!("expected {}, found {}", expected, json_kind(json)))
+    }
+
+    fn convert_in(
+        &mut self,
+        segment: PathSegment,
+        json: &Json,
+        schema: &'a Schema,
+    ) -> Result<Value> {
+        self.path.push(segment);
+        let value = self.convert(json, schema)?;
+        self.path.pop();
+        Ok(value)
+    }
+
+    fn convert(&mut self, json: &Json, schema: &'a Schema) -> Result<Value> {
+        let schema = schema
+            .resolve(&self.ancestors)
+            .ok_or_else(|| error!(IllegalSchema, None, "illegal recurse")
+                .with_path(self.path.clone()))?;
+        self.ancestors.push(schema);
+        let value =
+            match (schema, json) {
+                (&Schema::Scalar(scalar_type), json) => Value::Scalar(
+                    self.convert_scalar(json, scalar_type)?
+                ),
+                (&Schema::Str, &Json::String(ref s)) => Value::Str(s.clone()),
+                (&Schema::Bytes, &Json::String(ref s)) => Value::Bytes(
+                    base64_decode(s)
+                        .ok_or_else(|| self.error("malformed base64".into()))?
+                ),
+                (&Schema::Unit, &Json::Null) => Value::Unit,
+                (&Schema::Option(_), &Json::Null) => Value::Option(None),
+                (&Schema::Option(ref inner), json) => {
+                    let inner_schema = inner
+                        .resolve(&self.ancestors)
+                        .ok_or_else(|| error!(IllegalSchema, None, "illegal recurse")
+                            .with_path(self.path.clone()))?;
+                    let json =
+                        match inner_schema {
+                            &Schema::Option(_) | &Schema::Unit => match json {
+                                &Json::Array(ref elems) if elems.len() == 1 => &elems[0],
+                                json => return Err(self.expected("null or 1-element array", json)),
+                            },
+                            _ => json,
+                        };
+                    Value::Option(Some(Box::new(self.convert_in(PathSegment::Some, json, inner)?)))
+                }
+                (&Schema::Seq(SeqSchema { len, ref inner }), &Json::Array(ref elems)) => {
+                    if let Some(len) = len {
+                        if elems.len() != len {
+                            return Err(self.error(format!(
+                                "expected array of len {}, found len {}",
+                                len,
+                                elems.len(),
+                            )));
+                        }
+                    }
+                    let elems = elems
+                        .iter()
+                        .enumerate()
+                        .map(|(i, elem)| self.convert_in(PathSegment::SeqElem(Some(i)), elem, inner))
+                        .collect::<Result<Vec<_>>>()?;
+                    match len {
+                        Some(_) => Value::FixedLenSeq(elems),
+                        None => Value::VarLenSeq(elems),
+                    }
+                }
+                (&Schema::Tuple(ref inners), &Json::Array(ref elems)) => {
+                    if elems.len() != inners.len() {
+                        return Err(self.error(format!(
+                            "expected array of len {}, found len {}",
+                            inners.len(),
+                            elems.len(),
+                        )));
+                    }
+                    Value::Tuple(inners
+                        .iter()
+                        .zip(elems)
+                        .enumerate()
+                        .map(|(i, (inner, elem))| self.convert_in(PathSegment::TupleElem(i), elem, inner))
+                        .collect::<Result<_>>()?)
+                }
+                (&Schema::Struct(ref fields), &Json::Object(ref map)) => {
+                    if let Some(key) = map
+                        .keys()
+                        .find(|&key| !fields.iter().any(|field| &field.name == key))
+                    {
+                        return Err(self.error(format!("unknown field {:?}", key)));
+                    }
+                    Value::Struct(fields
+                        .iter()
+                        .map(|field| {
+                            let json = map
+                                .get(&field.name)
+                                .ok_or_else(|| self.error(format!("missing field {:?}", field.name)))?;
+                            Ok(StructValueField {
+                                name: field.name.clone(),
+                                value: self.convert_in(
+                                    PathSegment::Field(field.name.clone()),
+                                    json,
+                                    &field.inner,
+                                )?,
+                            })
+                        })
+                        .collect::<Result<_>>()?)
+                }
+                (&Schema::Enum(ref variants), &Json::Object(ref map)) if map.len() == 1 => {
+                    let (name, json) = map.iter().next().unwrap();
+                    let variant_ord = variants
+                        .iter()
+                        .position(|variant| &variant.name == name)
+                        .ok_or_else(|| self.error(format!("unknown variant {:?}", name)))?;
+                    Value::Enum(EnumValue {
+                        variant_ord,
+                        variant_name: name.clone(),
+                        value: Box::new(self.convert_in(
+                            PathSegment::Variant(name.clone()),
+                            json,
+                            &variants[variant_ord].inner,
+                        )?),
+                    })
+                }
+                (&Schema::Str, json) => return Err(self.expected("string", json)),
+                (&Schema::Bytes, json) => return Err(self.expected("base64 string", json)),
+                (&Schema::Unit, json) => return Err(self.expected("null", json)),
+                (&Schema::Seq(_), json) | (&Schema::Tuple(_), json) => {
+                    return Err(self.expected("array", json));
+                }
+                (&Schema::Struct(_), json) => return Err(self.expected("object", json)),
+                (&Schema::Enum(_), json) => {
+                    return Err(self.expected("object with a single key", json));
+                }
+                (&Schema::Recurse(_), _) => unreachable!(),
+            };
+        self.ancestors.pop();
+        Ok(value)
+    }
+
+    fn convert_scalar(&self, json: &Json, scalar_type: ScalarType) -> Result<ScalarValue> {
+        macro_rules! int {
+            ($variant:ident, $t:ty, $as:ident)=>{
+                match json.$as().map(<$t>::try_from) {
+                    Some(Ok(n)) => ScalarValue::$variant(n),
+                    _ => return Err(self.expected(scalar_type.display_str(), json)),
+                }
+            };
+        }
+        macro_rules! int128 {
+            ($variant:ident, $t:ty)=>{
+                match json {
+                    &Json::String(ref s) => s.parse::<$t>().ok(),
+                    &Json::Number(ref n) => n.to_string().parse::<$t>().ok(),
+                    _ => None,
+                }
+                    .map(ScalarValue::$variant)
+                    .ok_or_else(|| self.expected(scalar_type.display_str(), json))?
+            };
+        }
+        Ok(match scalar_type {
+            ScalarType::U8 => int!(U8, u8, as_u64),
+            ScalarType::U16 => int!(U16, u16, as_u64),
+            ScalarType::U32 => int!(U32, u32, as_u64),
+            ScalarType::U64 => int!(U64, u64, as_u64),
+            ScalarType::U128 => int128!(U128, u128),
+            ScalarType::I8 => int!(I8, i8, as_i64),
+            ScalarType::I16 => int!(I16, i16, as_i64),
+            ScalarType::I32 => int!(I32, i32, as_i64),
+            ScalarType::I64 => int!(I64, i64, as_i64),
+            ScalarType::I128 => int128!(I128, i128),
+            ScalarType::F32 => ScalarValue::F32(self.convert_float(json)? as f32),
+            ScalarType::F64 => ScalarValue::F64(self.convert_float(json)?),
+            ScalarType::Char => {
+                let mut chars = json.as_str().unwrap_or("").chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => ScalarValue::Char(c),
+                    _ => return Err(self.expected("string of one char", json)),
+                }
+            }
+            ScalarType::Bool => ScalarValue::Bool(json
+                .as_bool()
+                .ok_or_else(|| self.expected("boolean", json))?),
+        })
+    }
+
+    fn convert_float(&self, json: &Json) -> Result<f64> {
+        match json {
+            &Json::Number(ref n) => Ok(n.as_f64().unwrap()),
+            &Json::String(ref s) if s == "NaN" => Ok(f64::NAN),
+            &Json::String(ref s) if s == "inf" => Ok(f64::INFINITY),
+            &Json::String(ref s) if s == "-inf" => Ok(f64::NEG_INFINITY),
+            json => Err(self.expected("number", json)),
+        }
+    }
+}
+
+
+// ==== base64 ====
+
+const BASE64_CHARS: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64_CHARS[(n >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn base64_decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.as_bytes();
+    if !s.len().is_multiple_of(4) {
+        return None;
+    }
+    let mut out = Vec::with_capacity(s.len() / 4 * 3);
+    for (i, chunk) in s.chunks(4).enumerate() {
+        let is_last = (i + 1) * 4 == s.len();
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && !is_last) {
+            return None;
+        }
+        let mut n = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let digit = BASE64_CHARS.iter().position(|&d| d == c)?;
+            n = n << 6 | digit as u32;
+        }
+        n <<= 6 * padding;
+        out.extend_from_slice(&n.to_be_bytes()[1..4 - padding]);
+    }
+    Some(out)
+}
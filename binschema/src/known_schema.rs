@@ -34,8 +34,8 @@ pub struct RecurseStack<'a>(Option<Node<'a>>);
 
 #[derive(Copy, Clone)]
 struct Node<'a> {
-    val: Option<fn(RecurseStack) -> Schema>,
-    dbg: &'static str,
+    /// Name of the type, or none for a layer which isn't a type.
+    type_name: Option<&'static str>,
     next: &'a Option<Node<'a>>,
 }
 
@@ -52,29 +52,35 @@ impl<'a> RecurseStack<'a> {
 
     pub fn with_type_layer<'b, T: KnownSchema + ?Sized>(&'b self) -> RecurseStack<'b> {
         RecurseStack(Some(Node {
-            val: Some(T::schema),
-            dbg: type_name::<T>(),
+            type_name: Some(type_name::<T>()),
             next: &self.0,
         }))
     }
 
     pub fn with_none_layer<'b>(&'b self) -> RecurseStack<'b> {
         RecurseStack(Some(Node {
-            val: None,
-            dbg: "",
+            type_name: None,
             next: &self.0,
         }))
     }
 
+    /// How many layers up the nearest layer of type `T` is, if any.
+    ///
+    /// Types are compared by `std::any::type_name`, since `TypeId` would
+    /// require `T: 'static`, ruling out types which borrow. Type names aren't
+    /// guaranteed to be unique, so two distinct types with the same name,
+    /// which in practice only happens across different versions of one
+    /// crate, are taken to be the same type.
     pub fn find_type<T: KnownSchema>(&self) -> Option<usize> {
         let mut opt_curr = &self.0;
         let mut level = 0;
 
+        // types are identified by name rather than by the address of their
+        // schema fn, because different instantiations of a generic type can
+        // share an address and one instantiation can have several
         while let Some(curr) = opt_curr {
-            if let Some(curr_val) = curr.val {
-                if curr_val as usize == T::schema as usize {
-                    return Some(level);
-                }
+            if curr.type_name == Some(type_name::<T>()) {
+                return Some(level);
             }
             opt_curr = curr.next;
             level += 1;
@@ -94,7 +100,7 @@ impl<'a> Debug for RecurseStack<'a> {
         let mut list = f.debug_list();
         let mut opt_curr = &self.0;
         while let &Some(curr) = opt_curr {
-            list.entry(&curr.dbg);
+            list.entry(&curr.type_name.unwrap_or(""));
             opt_curr = curr.next;
         }
         list.finish()
@@ -234,7 +240,7 @@ impl<T: KnownSchema> KnownSchema for Range<T> {
     fn schema(parent_stack: RecurseStack) -> Schema {
         let stack = parent_stack.with_type_layer::<Self>();
         schema!(struct {
-            (begin: %T::schema(stack)),
+            (start: %T::schema(stack)),
             (end: %T::schema(stack)),
         })
     }
@@ -244,7 +250,7 @@ impl<T: KnownSchema> KnownSchema for RangeInclusive<T> {
     fn schema(parent_stack: RecurseStack) -> Schema {
         let stack = parent_stack.with_type_layer::<Self>();
         schema!(struct {
-            (begin: %T::schema(stack)),
+            (start: %T::schema(stack)),
             (end: %T::schema(stack)),
         })
     }
@@ -254,20 +260,20 @@ impl<T: KnownSchema> KnownSchema for Bound<T> {
     fn schema(parent_stack: RecurseStack) -> Schema {
         let stack = parent_stack.with_type_layer::<Self>();
         schema!(enum {
+            Unbounded(unit),
             Included(%T::schema(stack)),
             Excluded(%T::schema(stack)),
-            Unbounded(unit),
         })
     }
 }
 
-impl<'a, T: KnownSchema> KnownSchema for &'a T {
+impl<T: KnownSchema> KnownSchema for &T {
     fn schema(parent_stack: RecurseStack) -> Schema {
         T::schema(parent_stack)
     }
 }
 
-impl<'a, T: KnownSchema> KnownSchema for &'a mut T {
+impl<T: KnownSchema> KnownSchema for &mut T {
     fn schema(parent_stack: RecurseStack) -> Schema {
         T::schema(parent_stack)
     }
@@ -279,7 +285,7 @@ impl<T: KnownSchema> KnownSchema for Box<T> {
     }
 }
 
-impl<'a, T: KnownSchema + ToOwned> KnownSchema for Cow<'a, T> {
+impl<T: KnownSchema + ToOwned> KnownSchema for Cow<'_, T> {
     fn schema(parent_stack: RecurseStack) -> Schema {
         T::schema(parent_stack)
     }
@@ -32,7 +32,10 @@
 //! - `u8` through `u128`, `i8` through `i128`(32 bits and above are encoded
 //!    variable length)
 //! - `f32` and `f64`, `char`, `bool`
-//! - utf8 string, byte string
+//! - utf8 string, byte string (`Bytes`, or a field with `#[schema(bytes)]`).
+//!   a field with `#[serde(with = "serde_bytes")]` keeps the schema of its
+//!   type, so a `Vec<u8>` is still `seq(varlen)(u8)` and a `[u8; N]`
+//!   `seq(N)(u8)`, rather than `bytes`
 //! - option
 //! - fixed length array, variable length array
 //! - tuple (just values back-to-back)
@@ -41,18 +44,66 @@
 //! - enum, as in rust-style enum, as in tagged union, as in "one of"
 //! - recursing up in the schema, so as to support recursive schema types like
 //!   trees
+//!
+//! Besides going through serde, a value can be encoded or decoded by
+//! implementing `Encode` or `Decode`, which call `Encoder` and `Decoder`
+//! methods directly. These can be derived alongside `KnownSchema`, in which
+//! case they respect the same `#[serde(...)]` and `#[schema(...)]` attributes.
+//!
+//! With the `tokio` feature, `AsyncEncoder` and `AsyncDecoder` do the same
+//! against `tokio::io::AsyncWrite` and `AsyncRead`. Since `CoderState` may
+//! hold a `&mut dyn Write` debug log, their futures are not `Send`, so they
+//! should be driven within a single task, e.g. with `tokio::task::LocalSet`.
+//!
+//! With the `serde_json` feature, the `json` module transcodes between
+//! encoded data and JSON, for debugging.
+//!
+//! Breaking changes to the schemas of standard library types:
+//!
+//! - `Range` and `RangeInclusive` were `struct { begin, end }`, and are now
+//!   `struct { start, end }`, and `Bound` was `enum { Included, Excluded,
+//!   Unbounded }`, and is now `enum { Unbounded, Included, Excluded }`, to
+//!   match how serde serializes them. Their fingerprints changed, and a
+//!   `Bound` encoded with the old native `Encode` can't be decoded with the
+//!   new `Decode`, since the ordinals moved.
 
+// the `&Pattern(ref x)` match style is used throughout
+#![allow(clippy::match_ref_pats, clippy::needless_borrowed_reference)]
 
 pub mod error;
 pub mod value;
+pub mod path;
 
 mod schema;
+mod validate;
+mod check;
+mod compat;
+mod diff;
+mod named;
+mod fingerprint;
+mod dump;
+mod text;
+mod skip;
+mod resolve;
 mod known_schema;
+mod encode;
+mod decode;
+mod bytes;
 mod do_if_err;
 mod var_len;
 mod coder;
 mod encoder;
 mod decoder;
+mod borrow_decoder;
+mod limits;
+mod push_decoder;
+mod size_counter;
+#[cfg(feature = "tokio")]
+mod async_encoder;
+#[cfg(feature = "tokio")]
+mod async_decoder;
+#[cfg(feature = "serde_json")]
+pub mod json;
 mod serde;
 
 pub use crate::{
@@ -61,11 +112,25 @@ pub use crate::{
         coder_alloc::CoderStateAlloc,
     },
     encoder::Encoder,
+    size_counter::SizeCounter,
     decoder::Decoder,
+    borrow_decoder::BorrowDecoder,
+    push_decoder::{
+        PushDecoder,
+        PushStep,
+        PushEvent,
+    },
+    limits::{
+        DecodeLimits,
+        LimitExceeded,
+    },
     known_schema::{
         KnownSchema,
         RecurseStack,
     },
+    encode::Encode,
+    decode::Decode,
+    bytes::Bytes,
     schema::{
         Schema,
         ScalarType,
@@ -73,6 +138,43 @@ pub use crate::{
         StructSchemaField,
         EnumSchemaVariant,
     },
+    validate::{
+        SchemaIssue,
+        SchemaIssueKind,
+    },
+    named::{
+        NamedSchema,
+        NamedType,
+        NamedSchemaError,
+    },
+    text::SchemaParseError,
+    compat::{
+        check_compat,
+        Incompatibility,
+        IncompatibilityKind,
+    },
+    diff::{
+        diff_schemas,
+        SchemaDiff,
+        SchemaChange,
+        SchemaChangeKind,
+    },
+    dump::{
+        hex_dump,
+        HexDump,
+        DumpEntry,
+        DumpEntryKind,
+    },
 };
 
-pub use binschema_derive::KnownSchema;
+#[cfg(feature = "tokio")]
+pub use crate::{
+    async_encoder::AsyncEncoder,
+    async_decoder::AsyncDecoder,
+};
+
+pub use binschema_derive::{
+    KnownSchema,
+    Encode,
+    Decode,
+};
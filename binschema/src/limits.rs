@@ -0,0 +1,156 @@
+//! Limits on what a decoder will accept, to defend against malicious
+//! payloads.
+
+use std::{
+    io::{
+        self,
+        Read,
+    },
+    fmt::{self, Formatter, Display},
+};
+
+
+/// Limits on the data a `Decoder` will accept. Exceeding one causes an
+/// `ErrorKind::LimitExceeded` error. `None` means unlimited, which is the
+/// default for all of them.
+///
+/// Even without limits, a decoder never allocates more for a str or bytes
+/// than the bytes actually present in the input. Setting limits protects
+/// against inputs which are actually large or deeply nested.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Hash)]
+pub struct DecodeLimits {
+    /// Max number of bytes read from the input in total.
+    pub max_total_bytes: Option<u64>,
+    /// Max len in bytes of a single str or bytes.
+    pub max_str_bytes_len: Option<usize>,
+    /// Max len of a single var len seq, including maps.
+    pub max_seq_len: Option<usize>,
+    /// Max nesting depth of values, counting the root value as depth 1. Also
+    /// bounds recursion when decoding or skipping recursive schemas.
+    pub max_depth: Option<usize>,
+}
+
+impl DecodeLimits {
+    pub(crate) fn check_str_bytes_len(&self, len: usize) -> Result<(), LimitExceeded> {
+        match self.max_str_bytes_len {
+            Some(max) if len > max => Err(LimitExceeded::StrBytesLen { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_seq_len(&self, len: usize) -> Result<(), LimitExceeded> {
+        match self.max_seq_len {
+            Some(max) if len > max => Err(LimitExceeded::SeqLen { len, max }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn check_depth(&self, depth: usize) -> Result<(), LimitExceeded> {
+        match self.max_depth {
+            Some(max) if depth > max => Err(LimitExceeded::Depth(max)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Which limit was exceeded, used as the inner error of
+/// `ErrorKind::LimitExceeded` errors.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum LimitExceeded {
+    TotalBytes(u64),
+    StrBytesLen {
+        len: usize,
+        max: usize,
+    },
+    SeqLen {
+        len: usize,
+        max: usize,
+    },
+    Depth(usize),
+}
+
+impl Display for LimitExceeded {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &LimitExceeded::TotalBytes(max) => write!(
+                f,
+                "input exceeds max total bytes {}",
+                max,
+            ),
+            &LimitExceeded::StrBytesLen { len, max } => write!(
+                f,
+                "str or bytes of len {} exceeds max len {}",
+                len,
+                max,
+            ),
+            &LimitExceeded::SeqLen { len, max } => write!(
+                f,
+                "seq of len {} exceeds max len {}",
+                len,
+                max,
+            ),
+            &LimitExceeded::Depth(max) => write!(
+                f,
+                "value nesting exceeds max depth {}",
+                max,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LimitExceeded {}
+
+/// Read adapter which counts bytes read and enforces
+/// `DecodeLimits::max_total_bytes`. Going over the limit produces an IO error
+/// wrapping `LimitExceeded`, which converts into an
+/// `ErrorKind::LimitExceeded` error.
+pub(crate) struct CountingRead<'b, R> {
+    pub inner: &'b mut R,
+    pub pos: u64,
+    pub max: Option<u64>,
+}
+
+impl<'b, R> CountingRead<'b, R> {
+    pub fn new(inner: &'b mut R) -> Self {
+        CountingRead {
+            inner,
+            pos: 0,
+            max: None,
+        }
+    }
+
+    /// Remaining bytes allowed to be read, or an error if there are none.
+    fn budget(&self) -> io::Result<u64> {
+        match self.max {
+            Some(max) if self.pos >= max => Err(io::Error::other(
+                LimitExceeded::TotalBytes(max),
+            )),
+            Some(max) => Ok(max - self.pos),
+            None => Ok(u64::MAX),
+        }
+    }
+
+    /// Count `n` bytes consumed from the inner reader some other way.
+    pub fn advance(&mut self, n: usize) -> io::Result<()> {
+        if n > 0 && (n as u64) > self.budget()? {
+            return Err(io::Error::other(
+                LimitExceeded::TotalBytes(self.max.unwrap()),
+            ));
+        }
+        self.pos += n as u64;
+        Ok(())
+    }
+}
+
+impl<'b, R: Read> Read for CountingRead<'b, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let budget = self.budget()?;
+        let len = usize::try_from(budget).unwrap_or(usize::MAX).min(buf.len());
+        let n = self.inner.read(&mut buf[..len])?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
@@ -0,0 +1,409 @@
+//! Schema form with named type definitions, in which recursion is by name
+//! rather than by counting levels up.
+
+use crate::schema::{
+    Schema,
+    ScalarType,
+    SeqSchema,
+    StructSchemaField,
+    EnumSchemaVariant,
+};
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+        HashSet,
+    },
+    fmt::{self, Formatter, Display},
+};
+
+
+/// Schema as a table of named type definitions and a root type, in which
+/// types refer to each other with `NamedType::Ref`.
+///
+/// Unlike `Schema::Recurse`, a ref stays correct when the types around it
+/// change, and types may refer to each other mutually. Convert to a
+/// `Schema` with `to_schema`, and back with `Schema::to_named`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub struct NamedSchema {
+    pub defs: BTreeMap<String, NamedType>,
+    pub root: NamedType,
+}
+
+/// Type in a `NamedSchema`. Mirrors `Schema`, plus `Ref`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
+pub enum NamedType {
+    Scalar(ScalarType),
+    Str,
+    Bytes,
+    Unit,
+    Option(Box<NamedType>),
+    Seq {
+        len: Option<usize>,
+        inner: Box<NamedType>,
+    },
+    Tuple(Vec<NamedType>),
+    Struct(Vec<(String, NamedType)>),
+    Enum(Vec<(String, NamedType)>),
+    /// Level-based recursion, as in `Schema::Recurse`. Only produced by
+    /// `Schema::to_named` for recurses which are illegal, or which resolve
+    /// to different types depending on how they're reached.
+    Recurse(usize),
+    /// The type defined with this name.
+    Ref(String),
+}
+
+/// Error converting a `NamedSchema` to a `Schema`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum NamedSchemaError {
+    /// Ref to a name with no definition.
+    UndefinedRef(String),
+    /// Definition which refers to itself without any type around the ref,
+    /// such as `A = B, B = A`, which has no meaning.
+    RefWithoutNesting(String),
+    /// Definition whose refs to itself can't be expressed with
+    /// `Schema::Recurse`, because other types between them can recurse to
+    /// themselves any number of times. For example, with
+    /// `A = enum { Stop(unit), ToA(A), ToB(B) }` and
+    /// `B = enum { Stop(unit), ToB(B), ToA(A) }`, how far up a recurse from
+    /// within `B` to `A` would have to reach depends on how many times `B`
+    /// recursed to itself.
+    Inexpressible(String),
+}
+
+impl Display for NamedSchemaError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &NamedSchemaError::UndefinedRef(ref name) => write!(
+                f,
+                "undefined type `{}`",
+                name,
+            ),
+            &NamedSchemaError::RefWithoutNesting(ref name) => write!(
+                f,
+                "type `{}` refers to itself without nesting",
+                name,
+            ),
+            &NamedSchemaError::Inexpressible(ref name) => write!(
+                f,
+                "recursion to type `{}` can't be expressed as a schema",
+                name,
+            ),
+        }
+    }
+}
+
+impl std::error::Error for NamedSchemaError {}
+
+impl NamedSchema {
+    /// Convert to a `Schema`, by substituting in definitions where they're
+    /// referred to. A ref to a definition from within that same definition
+    /// becomes a `Schema::Recurse` back up to it where possible.
+    ///
+    /// A recurse resolves against the stack of values being coded, onto which
+    /// the type it recurses to is pushed again. So a recurse can only reach
+    /// back up past types which are never recursed to themselves. Where a ref
+    /// can't, the definition is substituted in again instead, and where that
+    /// would never end, this fails with `NamedSchemaError::Inexpressible`.
+    pub fn to_schema(&self) -> Result<Schema, NamedSchemaError> {
+        let mut resolver = Resolver {
+            named: self,
+            expanding: Vec::new(),
+            targets: Vec::new(),
+        };
+        resolver.resolve(&self.root, 0)
+    }
+}
+
+/// Definition being substituted in, around the type being converted.
+struct Expansion<'s> {
+    name: &'s str,
+    /// Depth in the schema tree.
+    depth: usize,
+    /// Whether recurses may land on it. If so, recurses from within it can't
+    /// reach past it, because what's below it on the coding stack depends on
+    /// where the recurse came from.
+    landing: bool,
+}
+
+struct Resolver<'s> {
+    named: &'s NamedSchema,
+    expanding: Vec<Expansion<'s>>,
+    /// Tree depths of the targets of the recurses produced so far.
+    targets: Vec<usize>,
+}
+
+impl<'s> Resolver<'s> {
+    /// Convert `named_type` at `depth` in the schema tree to schema.
+    fn resolve(
+        &mut self,
+        named_type: &'s NamedType,
+        depth: usize,
+    ) -> Result<Schema, NamedSchemaError> {
+        Ok(match named_type {
+            &NamedType::Scalar(scalar_type) => Schema::Scalar(scalar_type),
+            &NamedType::Str => Schema::Str,
+            &NamedType::Bytes => Schema::Bytes,
+            &NamedType::Unit => Schema::Unit,
+            &NamedType::Option(ref inner) => Schema::Option(Box::new(
+                self.resolve(inner, depth + 1)?
+            )),
+            &NamedType::Seq { len, ref inner } => Schema::Seq(SeqSchema {
+                len,
+                inner: Box::new(self.resolve(inner, depth + 1)?),
+            }),
+            &NamedType::Tuple(ref inners) => Schema::Tuple(inners
+                .iter()
+                .map(|inner| self.resolve(inner, depth + 1))
+                .collect::<Result<_, _>>()?),
+            &NamedType::Struct(ref fields) => Schema::Struct(fields
+                .iter()
+                .map(|&(ref name, ref inner)| Ok(StructSchemaField {
+                    name: name.clone(),
+                    inner: self.resolve(inner, depth + 1)?,
+                }))
+                .collect::<Result<_, _>>()?),
+            &NamedType::Enum(ref variants) => Schema::Enum(variants
+                .iter()
+                .map(|&(ref name, ref inner)| Ok(EnumSchemaVariant {
+                    name: name.clone(),
+                    inner: self.resolve(inner, depth + 1)?,
+                }))
+                .collect::<Result<_, _>>()?),
+            &NamedType::Recurse(n) => Schema::Recurse(n),
+            &NamedType::Ref(ref name) => {
+                let outer = self.expanding
+                    .iter()
+                    .rev()
+                    .find(|expansion| expansion.name == name);
+                if let Some(outer) = outer {
+                    if outer.depth == depth {
+                        return Err(NamedSchemaError::RefWithoutNesting(name.clone()));
+                    }
+                    let blocked = self.expanding
+                        .iter()
+                        .any(|expansion| {
+                            expansion.landing
+                                && expansion.depth > outer.depth
+                                && expansion.depth < depth
+                        });
+                    if !blocked {
+                        self.targets.push(outer.depth);
+                        return Ok(Schema::Recurse(depth - outer.depth));
+                    }
+                }
+                let (name, def) = self.named.defs
+                    .get_key_value(name)
+                    .ok_or_else(|| NamedSchemaError::UndefinedRef(name.clone()))?;
+                self.expand(name, def, depth)?
+            }
+        })
+    }
+
+    /// Substitute in the definition `def` of `name` at `depth`.
+    fn expand(
+        &mut self,
+        name: &'s str,
+        def: &'s NamedType,
+        depth: usize,
+    ) -> Result<Schema, NamedSchemaError> {
+        // first try letting recurses from within it reach past it, which is
+        // fine as long as none also land on it
+        let start = self.targets.len();
+        self.expanding.push(Expansion { name, depth, landing: false });
+        let result = self.resolve(def, depth);
+        self.expanding.pop();
+        match result {
+            Ok(schema) => {
+                let targets = &self.targets[start..];
+                let landed = targets.contains(&depth);
+                let passed = targets.iter().any(|&target| target < depth);
+                if !(landed && passed) {
+                    return Ok(schema);
+                }
+            }
+            Err(NamedSchemaError::Inexpressible(_)) => (),
+            Err(e) => return Err(e),
+        }
+
+        // otherwise, recurses from within it which would reach past it
+        // substitute in their definitions again. if it's already landed on
+        // further up, this would repeat forever.
+        let repeats = self.expanding
+            .iter()
+            .any(|expansion| expansion.landing && expansion.name == name);
+        if repeats {
+            return Err(NamedSchemaError::Inexpressible(name.to_owned()));
+        }
+        self.targets.truncate(start);
+        self.expanding.push(Expansion { name, depth, landing: true });
+        let result = self.resolve(def, depth);
+        self.expanding.pop();
+        result
+    }
+}
+
+impl Schema {
+    /// Convert to a `NamedSchema`. Types which are recursed to are given
+    /// definitions with generated names `T1`, `T2`, and so on, numbered in
+    /// pre-order, and recurses to them become refs.
+    pub fn to_named(&self) -> NamedSchema {
+        let mut namer = Namer {
+            targets: recurse_targets(self),
+            names: HashMap::new(),
+            num_names: 0,
+            defs: BTreeMap::new(),
+        };
+        for target in namer.targets.values().flatten() {
+            namer.names.insert(*target, String::new());
+        }
+        namer.name_targets(self);
+        let root = namer.convert(self, false);
+        NamedSchema {
+            defs: namer.defs,
+            root,
+        }
+    }
+}
+
+fn node_key(node: &Schema) -> *const Schema {
+    node as *const Schema
+}
+
+fn children(schema: &Schema) -> Vec<&Schema> {
+    match schema {
+        &Schema::Option(ref inner) => vec![&**inner],
+        &Schema::Seq(SeqSchema { ref inner, .. }) => vec![&**inner],
+        &Schema::Tuple(ref inners) => inners.iter().collect(),
+        &Schema::Struct(ref fields) => fields.iter().map(|f| &f.inner).collect(),
+        &Schema::Enum(ref variants) => variants.iter().map(|v| &v.inner).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Find the node each recurse node resolves to, by address. Like
+/// `Schema::validate`, this explores states of (node, last `max_level`
+/// ancestors on the coding stack), because what a recurse resolves to can
+/// depend on how it was reached. Recurses which are illegal, or resolve to
+/// different nodes in different states, map to `None`.
+fn recurse_targets(root: &Schema) -> HashMap<*const Schema, Option<*const Schema>> {
+    let max_level = max_recurse_level(root);
+    let mut targets = HashMap::new();
+    let mut explored = HashSet::new();
+    let mut to_explore = vec![(root, Vec::new())];
+    while let Some((node, mut ctx)) = to_explore.pop() {
+        if ctx.len() > max_level {
+            ctx.drain(..ctx.len() - max_level);
+        }
+        let state = (
+            node_key(node),
+            ctx.iter().map(|&ancestor| node_key(ancestor)).collect::<Vec<_>>(),
+        );
+        if !explored.insert(state) {
+            continue;
+        }
+        if let &Schema::Recurse(_) = node {
+            // the target is pushed on top of the whole coding stack
+            let target = node.resolve(&ctx);
+            let target_key = target.map(node_key);
+            let entry = targets.entry(node_key(node)).or_insert(target_key);
+            if *entry != target_key {
+                *entry = None;
+            }
+            if let Some(target) = target {
+                to_explore.push((target, ctx));
+            }
+        } else {
+            ctx.push(node);
+            for child in children(node) {
+                to_explore.push((child, ctx.clone()));
+            }
+        }
+    }
+    targets
+}
+
+fn max_recurse_level(schema: &Schema) -> usize {
+    match schema {
+        &Schema::Recurse(level) => level,
+        schema => children(schema)
+            .into_iter()
+            .map(max_recurse_level)
+            .max()
+            .unwrap_or(0),
+    }
+}
+
+struct Namer {
+    /// What each recurse node resolves to, from `recurse_targets`.
+    targets: HashMap<*const Schema, Option<*const Schema>>,
+    /// Names of nodes which are recursed to, by address.
+    names: HashMap<*const Schema, String>,
+    num_names: usize,
+    defs: BTreeMap<String, NamedType>,
+}
+
+impl Namer {
+    /// Name marked nodes in pre-order.
+    fn name_targets(&mut self, schema: &Schema) {
+        if let Some(name) = self.names.get_mut(&node_key(schema)) {
+            self.num_names += 1;
+            *name = format!("T{}", self.num_names);
+        }
+        for child in children(schema) {
+            self.name_targets(child);
+        }
+    }
+
+    /// Convert, replacing named nodes with refs and moving them into
+    /// definitions, unless `is_def` and it's the definition itself.
+    fn convert(&mut self, schema: &Schema, is_def: bool) -> NamedType {
+        if !is_def {
+            if let Some(name) = self.names.get(&node_key(schema)) {
+                let name = name.clone();
+                let def = self.convert(schema, true);
+                self.defs.insert(name.clone(), def);
+                return NamedType::Ref(name);
+            }
+        }
+        match schema {
+            &Schema::Scalar(scalar_type) => NamedType::Scalar(scalar_type),
+            &Schema::Str => NamedType::Str,
+            &Schema::Bytes => NamedType::Bytes,
+            &Schema::Unit => NamedType::Unit,
+            &Schema::Option(ref inner) => NamedType::Option(Box::new(
+                self.convert(inner, false)
+            )),
+            &Schema::Seq(SeqSchema { len, ref inner }) => NamedType::Seq {
+                len,
+                inner: Box::new(self.convert(inner, false)),
+            },
+            &Schema::Tuple(ref inners) => NamedType::Tuple(inners
+                .iter()
+                .map(|inner| self.convert(inner, false))
+                .collect()),
+            &Schema::Struct(ref fields) => NamedType::Struct(fields
+                .iter()
+                .map(|field| (
+                    field.name.clone(),
+                    self.convert(&field.inner, false),
+                ))
+                .collect()),
+            &Schema::Enum(ref variants) => NamedType::Enum(variants
+                .iter()
+                .map(|variant| (
+                    variant.name.clone(),
+                    self.convert(&variant.inner, false),
+                ))
+                .collect()),
+            &Schema::Recurse(level) => match self.targets[&node_key(schema)] {
+                Some(target) => NamedType::Ref(self.names[&target].clone()),
+                None => NamedType::Recurse(level),
+            },
+        }
+    }
+}
@@ -0,0 +1,86 @@
+//! Paths into schema trees and value trees, for pointing at where something
+//! went wrong.
+
+use std::fmt::{self, Formatter, Display};
+
+
+/// Sequence of steps from the root of a schema or value to some node within
+/// it.
+///
+/// Displays in a jq-like style, such as `.users[3].address.Some.zip`. The
+/// empty path displays as `.`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Path(pub Vec<PathSegment>);
+
+/// Single step in a `Path`.
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub enum PathSegment {
+    /// Into a struct field, by name.
+    Field(String),
+    /// Into an enum variant, by name.
+    Variant(String),
+    /// Into a tuple element, by index.
+    TupleElem(usize),
+    /// Into a seq element. When pathing into a value this is the element's
+    /// index. When pathing into a schema this is `None`, as a seq schema has
+    /// only one inner schema.
+    SeqElem(Option<usize>),
+    /// Into the inner value of an option.
+    Some,
+}
+
+impl Path {
+    pub fn new() -> Self {
+        Path(Vec::new())
+    }
+
+    pub fn segments(&self) -> &[PathSegment] {
+        &self.0
+    }
+
+    pub fn push(&mut self, segment: PathSegment) {
+        self.0.push(segment);
+    }
+
+    pub fn pop(&mut self) -> Option<PathSegment> {
+        self.0.pop()
+    }
+
+    /// Clone of self with `segment` appended.
+    pub fn join(&self, segment: PathSegment) -> Self {
+        let mut path = self.clone();
+        path.push(segment);
+        path
+    }
+}
+
+impl From<Vec<PathSegment>> for Path {
+    fn from(segments: Vec<PathSegment>) -> Self {
+        Path(segments)
+    }
+}
+
+impl Display for PathSegment {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &PathSegment::Field(ref name) => write!(f, ".{}", name),
+            &PathSegment::Variant(ref name) => write!(f, ".{}", name),
+            &PathSegment::TupleElem(i) => write!(f, ".{}", i),
+            &PathSegment::SeqElem(Some(i)) => write!(f, "[{}]", i),
+            &PathSegment::SeqElem(None) => f.write_str("[]"),
+            &PathSegment::Some => f.write_str(".Some"),
+        }
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str(".");
+        }
+        for segment in &self.0 {
+            Display::fmt(segment, f)?;
+        }
+        Ok(())
+    }
+}
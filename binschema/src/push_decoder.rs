@@ -0,0 +1,472 @@
+
+use crate::{
+    error::{
+        Result,
+        Error,
+        ErrorKind,
+        error,
+        bail,
+    },
+    coder::coder::{
+        CoderState,
+        NextStep,
+    },
+    var_len::{
+        read_var_len_uint,
+        read_var_len_sint,
+        read_ord,
+    },
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    value::ScalarValue,
+    limits::{
+        DecodeLimits,
+        LimitExceeded,
+    },
+};
+use std::io::{
+    self,
+    Read,
+};
+
+
+/// Decodes a value comforming to a schema from input which arrives in
+/// arbitrary chunks, such as in event loop code with non-blocking IO.
+///
+/// Input is provided with `feed`, and the value is pulled out as a sequence
+/// of `PushEvent`s with `next_event`, which reports `PushStep::NeedMore`
+/// rather than failing when the input fed so far ends mid-value. Progress
+/// through the schema and through partly received var len ints and strs or
+/// bytes is kept between calls.
+///
+/// Unlike `Decoder`, this owns its `CoderState`, so that it can be stored
+/// alongside the connection it's decoding from.
+pub struct PushDecoder<'a> {
+    state: CoderState<'a>,
+    /// Fed input. Bytes before `start` have been consumed.
+    buf: Vec<u8>,
+    start: usize,
+    /// Total bytes consumed.
+    pos: u64,
+    /// Str or bytes which has had its len read, but not all its bytes.
+    partial: Option<PartialStrBytes>,
+    limits: DecodeLimits,
+}
+
+struct PartialStrBytes {
+    is_str: bool,
+    len: usize,
+    bytes: Vec<u8>,
+}
+
+/// Result of `PushDecoder::next_event`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushStep<'a> {
+    Event(PushEvent<'a>),
+    /// Input fed so far ends mid-value. Feed more and try again.
+    NeedMore,
+    /// The value has been completely decoded.
+    Finished,
+}
+
+/// Piece of a value decoded by a `PushDecoder`. Seq and tuple elements are
+/// not delimited by their own events, as they just follow each other until
+/// the finish event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushEvent<'a> {
+    Scalar(ScalarValue),
+    Str(String),
+    Bytes(Vec<u8>),
+    Unit,
+    OptionNone,
+    /// Followed by the events of the inner value.
+    OptionSome,
+    /// Followed by the events of `len` elements, then `FinishSeq`.
+    BeginSeq {
+        len: usize,
+    },
+    FinishSeq,
+    /// Followed by the events of the elements, then `FinishTuple`.
+    BeginTuple,
+    FinishTuple,
+    /// Followed by a `StructField` and field value events for each field,
+    /// then `FinishStruct`.
+    BeginStruct,
+    StructField(&'a str),
+    FinishStruct,
+    /// Followed by the events of the variant's inner value.
+    Enum {
+        variant_ord: usize,
+        variant_name: &'a str,
+    },
+}
+
+impl<'a> PushDecoder<'a> {
+    pub fn new(state: CoderState<'a>) -> Self {
+        PushDecoder {
+            state,
+            buf: Vec::new(),
+            start: 0,
+            pos: 0,
+            partial: None,
+            limits: DecodeLimits::default(),
+        }
+    }
+
+    /// See `Decoder::set_limits`.
+    pub fn set_limits(&mut self, limits: DecodeLimits) {
+        self.limits = limits;
+    }
+
+    pub fn limits(&self) -> &DecodeLimits {
+        &self.limits
+    }
+
+    /// Number of bytes consumed so far. Fed bytes which haven't been decoded
+    /// yet aren't counted.
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    pub fn coder_state(&self) -> &CoderState<'a> {
+        &self.state
+    }
+
+    pub fn into_state(self) -> CoderState<'a> {
+        self.state
+    }
+
+    /// Provide more input.
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buf.drain(..self.start);
+        self.start = 0;
+        self.buf.extend_from_slice(bytes);
+    }
+
+    /// Input which has been fed but not yet consumed. Once finished, this is
+    /// whatever was fed after the end of the value.
+    pub fn remaining(&self) -> &[u8] {
+        &self.buf[self.start..]
+    }
+
+    /// Decode as far as the next event, if input fed so far allows.
+    pub fn next_event(&mut self) -> Result<PushStep<'a>> {
+        if self.partial.is_some() {
+            return self.continue_str_bytes();
+        }
+        self.state.set_offset(self.pos);
+        loop {
+            match self.state.next_step()? {
+                NextStep::Need(schema) => return self.decode_need(schema),
+                NextStep::SeqElem => {
+                    self.state.begin_seq_elem()?;
+                    self.check_depth()?;
+                }
+                NextStep::FinishSeq => {
+                    self.state.finish_seq()?;
+                    return Ok(PushStep::Event(PushEvent::FinishSeq));
+                }
+                NextStep::TupleElem => {
+                    self.state.begin_tuple_elem()?;
+                    self.check_depth()?;
+                }
+                NextStep::FinishTuple => {
+                    self.state.finish_tuple()?;
+                    return Ok(PushStep::Event(PushEvent::FinishTuple));
+                }
+                NextStep::StructField(name) => {
+                    self.state.begin_struct_field(name)?;
+                    self.check_depth()?;
+                    return Ok(PushStep::Event(PushEvent::StructField(name)));
+                }
+                NextStep::FinishStruct => {
+                    self.state.finish_struct()?;
+                    return Ok(PushStep::Event(PushEvent::FinishStruct));
+                }
+                NextStep::Finished => return Ok(PushStep::Finished),
+            }
+        }
+    }
+
+    /// Mark broken and construct an `ErrorKind::LimitExceeded` error.
+    fn limit_exceeded(&mut self, limit: LimitExceeded) -> Error {
+        self.state.mark_broken();
+        Error::new(ErrorKind::LimitExceeded, limit, Some(self.coder_state()))
+    }
+
+    fn check_depth(&mut self) -> Result<()> {
+        self.limits
+            .check_depth(self.state.depth())
+            .map_err(|limit| self.limit_exceeded(limit))
+    }
+
+    /// Mark `n` available bytes as consumed.
+    fn consume(&mut self, n: usize) -> Result<()> {
+        match self.limits.max_total_bytes {
+            Some(max) if self.pos + n as u64 > max => {
+                return Err(self.limit_exceeded(LimitExceeded::TotalBytes(max)));
+            }
+            _ => (),
+        }
+        self.start += n;
+        self.pos += n as u64;
+        Ok(())
+    }
+
+    /// Attempt to parse something from the available bytes, consuming the
+    /// bytes parsed if it succeeds, and consuming nothing if the available
+    /// bytes end too early.
+    fn try_parse<T, F>(&mut self, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce(&mut &[u8]) -> io::Result<T>,
+    {
+        let mut read = &self.buf[self.start..];
+        let available = read.len();
+        match f(&mut read) {
+            Ok(t) => {
+                let n = available - read.len();
+                self.consume(n)?;
+                Ok(Some(t))
+            }
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(self.state.broken(e)),
+        }
+    }
+
+    fn try_parse_byte(&mut self) -> Result<Option<u8>> {
+        self.try_parse(|read| {
+            let mut buf = [0];
+            read.read_exact(&mut buf)?;
+            Ok(buf[0])
+        })
+    }
+
+    fn try_parse_len(&mut self) -> Result<Option<usize>> {
+        let n =
+            match self.try_parse(|read| read_var_len_uint(read))? {
+                Some(n) => n,
+                None => return Ok(None),
+            };
+        usize::try_from(n)
+            .map(Some)
+            .map_err(|_| error!(
+                PlatformLimits,
+                Some(self.coder_state()),
+                "{} out of range for a usize",
+                n,
+            ))
+            .map_err(|e| self.state.broken(e))
+    }
+
+    fn decode_need(&mut self, schema: &'a Schema) -> Result<PushStep<'a>> {
+        let event =
+            match schema {
+                &Schema::Scalar(scalar_type) => {
+                    match self.try_parse_scalar(scalar_type)? {
+                        Some(scalar) => {
+                            self.state.code_scalar(scalar_type)?;
+                            PushEvent::Scalar(scalar)
+                        }
+                        None => return Ok(PushStep::NeedMore),
+                    }
+                }
+                &Schema::Str | &Schema::Bytes => {
+                    let len =
+                        match self.try_parse_len()? {
+                            Some(len) => len,
+                            None => return Ok(PushStep::NeedMore),
+                        };
+                    if let Err(limit) = self.limits.check_str_bytes_len(len) {
+                        return Err(self.limit_exceeded(limit));
+                    }
+                    self.partial = Some(PartialStrBytes {
+                        is_str: schema == &Schema::Str,
+                        len,
+                        bytes: Vec::new(),
+                    });
+                    return self.continue_str_bytes();
+                }
+                &Schema::Unit => {
+                    self.state.code_unit()?;
+                    PushEvent::Unit
+                }
+                &Schema::Option(_) => {
+                    let someness =
+                        match self.try_parse_byte()? {
+                            Some(b) => b,
+                            None => return Ok(PushStep::NeedMore),
+                        };
+                    self.state.begin_option()?;
+                    match someness {
+                        0 => {
+                            self.state.set_option_none();
+                            PushEvent::OptionNone
+                        }
+                        1 => {
+                            self.state.set_option_some()?;
+                            self.check_depth()?;
+                            PushEvent::OptionSome
+                        }
+                        n => {
+                            self.state.mark_broken();
+                            bail!(
+                                MalformedData,
+                                Some(self.coder_state()),
+                                "{} is not a valid option someness",
+                                n,
+                            );
+                        }
+                    }
+                }
+                &Schema::Seq(SeqSchema { len: Some(len), .. }) => {
+                    self.state.begin_fixed_len_seq(len)?;
+                    PushEvent::BeginSeq { len }
+                }
+                &Schema::Seq(SeqSchema { len: None, .. }) => {
+                    let len =
+                        match self.try_parse_len()? {
+                            Some(len) => len,
+                            None => return Ok(PushStep::NeedMore),
+                        };
+                    if let Err(limit) = self.limits.check_seq_len(len) {
+                        return Err(self.limit_exceeded(limit));
+                    }
+                    self.state.begin_var_len_seq()?;
+                    self.state.set_var_len_seq_len(len);
+                    PushEvent::BeginSeq { len }
+                }
+                &Schema::Tuple(_) => {
+                    self.state.begin_tuple()?;
+                    PushEvent::BeginTuple
+                }
+                &Schema::Struct(_) => {
+                    self.state.begin_struct()?;
+                    PushEvent::BeginStruct
+                }
+                &Schema::Enum(ref variants) => {
+                    let num_variants = variants.len();
+                    let variant_ord =
+                        match self.try_parse(|read| read_ord(read, num_variants))? {
+                            Some(variant_ord) => variant_ord,
+                            None => return Ok(PushStep::NeedMore),
+                        };
+                    let variant_name = &variants[variant_ord].name;
+                    self.state.begin_enum()?;
+                    self.state.begin_enum_variant_ord(variant_ord)?;
+                    self.state.begin_enum_variant_name(variant_name)?;
+                    self.check_depth()?;
+                    PushEvent::Enum {
+                        variant_ord,
+                        variant_name,
+                    }
+                }
+                &Schema::Recurse(_) => unreachable!("need returned recurse"),
+            };
+        Ok(PushStep::Event(event))
+    }
+
+    /// Read more bytes of the partly read str or bytes.
+    fn continue_str_bytes(&mut self) -> Result<PushStep<'a>> {
+        let mut partial = self.partial.take().unwrap();
+        let available = self.buf.len() - self.start;
+        let n = usize::min(partial.len - partial.bytes.len(), available);
+        self.consume(n)?;
+        partial.bytes.extend_from_slice(&self.buf[self.start - n..self.start]);
+        if partial.bytes.len() < partial.len {
+            self.partial = Some(partial);
+            return Ok(PushStep::NeedMore);
+        }
+        Ok(PushStep::Event(if partial.is_str {
+            self.state.code_str()?;
+            let s = String::from_utf8(partial.bytes)
+                .map_err(|_| error!(
+                    MalformedData, Some(self.coder_state()), "non UTF8 str bytes",
+                ))
+                .map_err(|e| self.state.broken(e))?;
+            PushEvent::Str(s)
+        } else {
+            self.state.code_bytes()?;
+            PushEvent::Bytes(partial.bytes)
+        }))
+    }
+
+    fn try_parse_scalar(
+        &mut self,
+        scalar_type: ScalarType,
+    ) -> Result<Option<ScalarValue>> {
+        macro_rules! le_bytes {
+            ($t:ident, $v:ident)=>{
+                self.try_parse(|read| {
+                    let mut buf = [0; std::mem::size_of::<$t>()];
+                    read.read_exact(&mut buf)?;
+                    Ok(ScalarValue::$v($t::from_le_bytes(buf)))
+                })
+            };
+        }
+        macro_rules! var_len {
+            ($read:ident, $t:ident, $v:ident)=>{
+                match self.try_parse(|read| $read(read))? {
+                    Some(n) => $t::try_from(n)
+                        .map(|n| Some(ScalarValue::$v(n)))
+                        .map_err(|_| error!(
+                            MalformedData,
+                            Some(self.coder_state()),
+                            concat!("{} out of range for a ", stringify!($t)),
+                            n,
+                        ))
+                        .map_err(|e| self.state.broken(e)),
+                    None => Ok(None),
+                }
+            };
+        }
+        match scalar_type {
+            ScalarType::U8 => le_bytes!(u8, U8),
+            ScalarType::U16 => le_bytes!(u16, U16),
+            ScalarType::U32 => var_len!(read_var_len_uint, u32, U32),
+            ScalarType::U64 => var_len!(read_var_len_uint, u64, U64),
+            ScalarType::U128 => var_len!(read_var_len_uint, u128, U128),
+            ScalarType::I8 => le_bytes!(i8, I8),
+            ScalarType::I16 => le_bytes!(i16, I16),
+            ScalarType::I32 => var_len!(read_var_len_sint, i32, I32),
+            ScalarType::I64 => var_len!(read_var_len_sint, i64, I64),
+            ScalarType::I128 => var_len!(read_var_len_sint, i128, I128),
+            ScalarType::F32 => le_bytes!(f32, F32),
+            ScalarType::F64 => le_bytes!(f64, F64),
+            ScalarType::Char => {
+                let n =
+                    match self.try_parse(|read| read_var_len_uint(read))? {
+                        Some(n) => n,
+                        None => return Ok(None),
+                    };
+                u32::try_from(n)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .map(|c| Some(ScalarValue::Char(c)))
+                    .ok_or_else(|| error!(
+                        MalformedData,
+                        Some(self.coder_state()),
+                        "{} is not a valid char",
+                        n,
+                    ))
+                    .map_err(|e| self.state.broken(e))
+            }
+            ScalarType::Bool => match self.try_parse_byte()? {
+                Some(0) => Ok(Some(ScalarValue::Bool(false))),
+                Some(1) => Ok(Some(ScalarValue::Bool(true))),
+                Some(n) => {
+                    self.state.mark_broken();
+                    Err(error!(
+                        MalformedData,
+                        Some(self.coder_state()),
+                        "{} is not a valid bool",
+                        n,
+                    ))
+                }
+                None => Ok(None),
+            },
+        }
+    }
+}
@@ -0,0 +1,585 @@
+//! Schema resolution, for decoding data written with one schema (the writer
+//! schema) as if it were data of another schema (the reader schema), in the
+//! style of Avro. See `check_compat` for the rules.
+//!
+//! The `CoderState` of a resolving `Decoder` validates the reader schema, as
+//! usual. Alongside it, the `Resolver` keeps a stack of frames mirroring the
+//! `CoderState` stack, which track where in the writer schema each reader
+//! stack frame is reading from.
+
+use crate::{
+    error::{
+        Error,
+        Result,
+        error,
+        bail,
+    },
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    value::ScalarValue,
+    skip::{
+        skip_raw,
+        read_len,
+        read_exact_onto,
+        Tee,
+    },
+    var_len::{
+        read_var_len_uint,
+        read_var_len_sint,
+        read_ord,
+    },
+    Decoder,
+};
+use std::{
+    borrow::Cow,
+    io::{
+        Read,
+        Cursor,
+    },
+};
+
+
+pub(crate) struct Resolver<'a> {
+    frames: Vec<Frame<'a>>,
+}
+
+struct Frame<'a> {
+    /// Writer schema this is reading from, or `None` if the value is absent
+    /// from the writer and is being defaulted. Resolved of recursion, unless
+    /// it couldn't be resolved.
+    writer: Option<&'a Schema>,
+    /// Whether `writer` is the same writer schema node as the parent frame's,
+    /// because a non-option is being read as an option.
+    promoted: bool,
+    /// For tuples, the next element index. For structs, the index of the
+    /// next writer field in the source.
+    next: usize,
+    /// For structs, the bytes of writer fields which were read ahead of
+    /// their turn, because the reader needs them in a different order.
+    read_ahead: Vec<Option<Vec<u8>>>,
+    /// For enums, the writer variant ordinal, once read.
+    variant: Option<usize>,
+    /// If this value was read ahead of its turn, the bytes to read it from
+    /// instead of the underlying reader.
+    replay: Option<Cursor<Vec<u8>>>,
+}
+
+impl<'a> Resolver<'a> {
+    pub(crate) fn new(writer: &'a Schema) -> Self {
+        Resolver {
+            frames: vec![Frame::new(Some(writer), false)],
+        }
+    }
+
+    fn top(&mut self) -> &mut Frame<'a> {
+        let i = self.frames.len() - 1;
+        &mut self.frames[i]
+    }
+
+    /// The stack of writer schemas, for resolving recursion.
+    fn writer_ancestors(&self) -> Vec<&'a Schema> {
+        self.frames
+            .iter()
+            .filter(|frame| !frame.promoted)
+            .filter_map(|frame| frame.writer)
+            .collect()
+    }
+
+    /// Frame for reading the writer schema `inner` as a child of the top
+    /// frame.
+    fn child(&self, inner: &'a Schema) -> Frame<'a> {
+        let ancestors = self.writer_ancestors();
+        Frame::new(Some(inner.resolve(&ancestors).unwrap_or(inner)), false)
+    }
+}
+
+impl<'a> Frame<'a> {
+    fn new(writer: Option<&'a Schema>, promoted: bool) -> Self {
+        Frame {
+            writer,
+            promoted,
+            next: 0,
+            read_ahead: Vec::new(),
+            variant: None,
+            replay: None,
+        }
+    }
+}
+
+/// The bytes source for the top frame: the innermost replay buffer, if any,
+/// or else the underlying reader.
+fn source<'s, R: Read>(
+    frames: &'s mut [Frame<'_>],
+    read: &'s mut R,
+) -> &'s mut dyn Read {
+    match frames.iter_mut().rev().find_map(|frame| frame.replay.as_mut()) {
+        Some(replay) => replay,
+        None => read,
+    }
+}
+
+/// Read a scalar of the given type from raw bytes.
+fn read_scalar(read: &mut dyn Read, st: ScalarType) -> Result<ScalarValue> {
+    macro_rules! le {
+        ($t:ident)=>{{
+            let mut buf = [0; std::mem::size_of::<$t>()];
+            read.read_exact(&mut buf)?;
+            $t::from_le_bytes(buf)
+        }};
+    }
+    macro_rules! var_len {
+        ($f:ident, $t:ident)=>{{
+            let n = $f(read)?;
+            $t::try_from(n)
+                .map_err(|_| error!(
+                    MalformedData,
+                    None,
+                    concat!("{} out of range for a ", stringify!($t)),
+                    n,
+                ))?
+        }};
+    }
+    Ok(match st {
+        ScalarType::U8 => ScalarValue::U8(le!(u8)),
+        ScalarType::U16 => ScalarValue::U16(le!(u16)),
+        ScalarType::U32 => ScalarValue::U32(var_len!(read_var_len_uint, u32)),
+        ScalarType::U64 => ScalarValue::U64(var_len!(read_var_len_uint, u64)),
+        ScalarType::U128 => ScalarValue::U128(read_var_len_uint(read)?),
+        ScalarType::I8 => ScalarValue::I8(le!(i8)),
+        ScalarType::I16 => ScalarValue::I16(le!(i16)),
+        ScalarType::I32 => ScalarValue::I32(var_len!(read_var_len_sint, i32)),
+        ScalarType::I64 => ScalarValue::I64(var_len!(read_var_len_sint, i64)),
+        ScalarType::I128 => ScalarValue::I128(read_var_len_sint(read)?),
+        ScalarType::F32 => ScalarValue::F32(le!(f32)),
+        ScalarType::F64 => ScalarValue::F64(le!(f64)),
+        ScalarType::Char => {
+            let n = var_len!(read_var_len_uint, u32);
+            ScalarValue::Char(char::from_u32(n)
+                .ok_or_else(|| error!(
+                    MalformedData,
+                    None,
+                    "{} is not a valid char",
+                    n,
+                ))?)
+        }
+        ScalarType::Bool => match le!(u8) {
+            0 => ScalarValue::Bool(false),
+            1 => ScalarValue::Bool(true),
+            n => bail!(MalformedData, None, "{} is not a valid bool", n),
+        },
+    })
+}
+
+impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
+    fn resolver(&mut self) -> &mut Resolver<'a> {
+        self.resolver.as_mut().unwrap()
+    }
+
+    /// Top frame's writer schema.
+    fn writer(&mut self) -> Option<&'a Schema> {
+        self.resolver().top().writer
+    }
+
+    /// Bytes source for the top frame.
+    fn source(&mut self) -> &mut dyn Read {
+        source(&mut self.resolver.as_mut().unwrap().frames, &mut self.read)
+    }
+
+    /// Pop resolver frames to match the coder state, after it finishes
+    /// something.
+    fn sync_pop(&mut self) {
+        let depth = self.state.depth();
+        self.resolver().frames.truncate(depth);
+    }
+
+    /// Push a resolver frame to match the coder state, after it begins
+    /// coding some inner value.
+    fn sync_push(&mut self, frame: Frame<'a>) {
+        debug_assert_eq!(self.state.depth(), self.resolver().frames.len() + 1);
+        self.resolver().frames.push(frame);
+    }
+
+    /// Construct an error that the writer has the wrong kind of schema, and
+    /// mark broken.
+    fn incompatible(&mut self, writer: Option<&Schema>, reader: &str) -> Error {
+        self.state.mark_broken();
+        match writer {
+            Some(&Schema::Recurse(_)) => error!(
+                IllegalSchema,
+                Some(self.coder_state()),
+                "unresolvable recurse in writer schema",
+            ),
+            Some(writer) => error!(
+                IncompatibleSchemas,
+                Some(self.coder_state()),
+                "writer has {}, reader has {}",
+                writer.non_recursive_display_str(),
+                reader,
+            ),
+            None => error!(
+                IncompatibleSchemas,
+                Some(self.coder_state()),
+                "writer lacks {}, and it has no default",
+                reader,
+            ),
+        }
+    }
+
+    pub(crate) fn resolving_decode_scalar(
+        &mut self,
+        st: ScalarType,
+    ) -> Result<ScalarValue> {
+        self.state.code_scalar(st)?;
+        let value =
+            match self.writer() {
+                Some(&Schema::Scalar(wst)) if wst.widens_to(st) => {
+                    read_scalar(self.source(), wst)
+                        .map_err(|e| self.state.broken(e))?
+                        .widen(st)
+                        .unwrap()
+                }
+                writer => return Err(self.incompatible(writer, st.display_str())),
+            };
+        self.sync_pop();
+        Ok(value)
+    }
+
+    pub(crate) fn resolving_decode_unit(&mut self) -> Result<()> {
+        self.state.code_unit()?;
+        match self.writer() {
+            Some(&Schema::Unit) | None => (),
+            writer => return Err(self.incompatible(writer, "unit")),
+        }
+        self.sync_pop();
+        Ok(())
+    }
+
+    /// Decode the raw bytes of a str or bytes into `buf`, after the coder
+    /// state has been updated.
+    pub(crate) fn resolving_decode_str_or_bytes_into(
+        &mut self,
+        buf: &mut Vec<u8>,
+        is_str: bool,
+    ) -> Result<()> {
+        match self.writer() {
+            Some(&Schema::Str) => (),
+            Some(&Schema::Bytes) if !is_str => (),
+            writer => return Err(self.incompatible(
+                writer,
+                if is_str { "str" } else { "bytes" },
+            )),
+        }
+        let result = read_len(self.source())
+            .and_then(|len| self.check_str_bytes_len(len).map(|()| len))
+            .and_then(|len| read_exact_onto(self.source(), buf, len));
+        if result.is_err() {
+            buf.clear();
+        }
+        self.sync_pop();
+        result.map_err(|e| self.state.broken(e))
+    }
+
+    pub(crate) fn resolving_begin_option(&mut self) -> Result<bool> {
+        self.state.begin_option()?;
+        let (is_some, promoted) =
+            match self.writer() {
+                Some(&Schema::Option(_)) => {
+                    let mut buf = [0];
+                    self.source()
+                        .read_exact(&mut buf)
+                        .map_err(|e| self.state.broken(e))?;
+                    match buf[0] {
+                        0 => (false, false),
+                        1 => (true, false),
+                        n => {
+                            self.state.mark_broken();
+                            bail!(
+                                MalformedData,
+                                Some(self.coder_state()),
+                                "{} is not a valid option someness",
+                                n,
+                            );
+                        }
+                    }
+                }
+                None => (false, false),
+                // read non-option as some
+                Some(_) => (true, true),
+            };
+        if is_some {
+            self.state.set_option_some()?;
+            let frame =
+                match self.writer() {
+                    Some(&Schema::Option(ref inner)) if !promoted => self.resolver().child(inner),
+                    writer => Frame::new(writer, true),
+                };
+            self.sync_push(frame);
+        } else {
+            self.state.set_option_none();
+            self.sync_pop();
+        }
+        Ok(is_some)
+    }
+
+    pub(crate) fn resolving_begin_fixed_len_seq(&mut self, len: usize) -> Result<()> {
+        self.state.begin_fixed_len_seq(len)?;
+        match self.writer() {
+            Some(&Schema::Seq(SeqSchema { len: Some(wlen), .. })) if wlen == len => (),
+            None if len == 0 => (),
+            writer => return Err(self.incompatible(writer, "fixed len seq")),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_var_len_seq(&mut self) -> Result<usize> {
+        self.state.begin_var_len_seq()?;
+        let len =
+            match self.writer() {
+                Some(&Schema::Seq(SeqSchema { len: Some(len), .. })) => len,
+                Some(&Schema::Seq(SeqSchema { len: None, .. })) => {
+                    read_len(self.source())
+                        .map_err(|e| self.state.broken(e))?
+                }
+                None => 0,
+                writer => return Err(self.incompatible(writer, "var len seq")),
+            };
+        self.check_seq_len(len)?;
+        self.state.set_var_len_seq_len(len);
+        Ok(len)
+    }
+
+    pub(crate) fn resolving_begin_seq_elem(&mut self) -> Result<()> {
+        self.state.begin_seq_elem()?;
+        let frame =
+            match self.writer() {
+                Some(&Schema::Seq(SeqSchema { ref inner, .. })) => self.resolver().child(inner),
+                _ => Frame::new(None, false),
+            };
+        self.sync_push(frame);
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_tuple(&mut self) -> Result<()> {
+        let reader_len =
+            match self.state.top_schema() {
+                Some(&Schema::Tuple(ref inners)) => inners.len(),
+                _ => 0,
+            };
+        self.state.begin_tuple()?;
+        match self.writer() {
+            Some(&Schema::Tuple(ref inners)) if inners.len() == reader_len => (),
+            None => (),
+            writer => return Err(self.incompatible(writer, "tuple")),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_tuple_elem(&mut self) -> Result<()> {
+        self.state.begin_tuple_elem()?;
+        let next = self.resolver().top().next;
+        self.resolver().top().next += 1;
+        let frame =
+            match self.writer() {
+                Some(&Schema::Tuple(ref inners)) => self.resolver().child(&inners[next]),
+                _ => Frame::new(None, false),
+            };
+        self.sync_push(frame);
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_struct(&mut self) -> Result<()> {
+        self.state.begin_struct()?;
+        match self.writer() {
+            Some(&Schema::Struct(ref fields)) => {
+                self.resolver().top().read_ahead = vec![None; fields.len()];
+            }
+            None => (),
+            writer => return Err(self.incompatible(writer, "struct")),
+        }
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_struct_field(&mut self, name: &str) -> Result<()> {
+        self.state.begin_struct_field(name)?;
+        let fields =
+            match self.writer() {
+                Some(&Schema::Struct(ref fields)) => fields,
+                _ => {
+                    self.sync_push(Frame::new(None, false));
+                    return Ok(());
+                }
+            };
+        let frame =
+            match fields.iter().position(|field| field.name == name) {
+                None => {
+                    let has_default = self.state
+                        .need()
+                        .map(Schema::has_default)
+                        .unwrap_or(false);
+                    if !has_default {
+                        self.state.mark_broken();
+                        bail!(
+                            IncompatibleSchemas,
+                            Some(self.coder_state()),
+                            "reader field {:?} is not in writer and has no default",
+                            name,
+                        );
+                    }
+                    Frame::new(None, false)
+                }
+                Some(i) => {
+                    if let Some(bytes) = self.resolver().top().read_ahead[i].take() {
+                        let mut frame = self.resolver().child(&fields[i].inner);
+                        frame.replay = Some(Cursor::new(bytes));
+                        frame
+                    } else {
+                        let next = self.resolver().top().next;
+                        if i < next {
+                            self.state.mark_broken();
+                            bail!(
+                                IllegalSchema,
+                                Some(self.coder_state()),
+                                "writer field {:?} read twice",
+                                name,
+                            );
+                        }
+                        // read ahead the fields the writer wrote first
+                        let mut ancestors = self.resolver().writer_ancestors();
+                        let limits = *self.limits();
+                        for (j, field) in fields[..i].iter().enumerate().skip(next) {
+                            let mut bytes = Vec::new();
+                            let mut tee = Tee {
+                                read: self.source(),
+                                capture: &mut bytes,
+                            };
+                            skip_raw(&field.inner, &mut ancestors, &limits, &mut tee)
+                                .map_err(|e| self.state.broken(e))?;
+                            self.resolver().top().read_ahead[j] = Some(bytes);
+                        }
+                        self.resolver().top().next = i + 1;
+                        self.resolver().child(&fields[i].inner)
+                    }
+                }
+            };
+        self.sync_push(frame);
+        Ok(())
+    }
+
+    pub(crate) fn resolving_finish_struct(&mut self) -> Result<()> {
+        self.state.finish_struct()?;
+        if let Some(&Schema::Struct(ref fields)) = self.writer() {
+            // skip the fields the reader doesn't want
+            let mut ancestors = self.resolver().writer_ancestors();
+            let next = self.resolver().top().next;
+            let limits = *self.limits();
+            for field in &fields[next..] {
+                skip_raw(&field.inner, &mut ancestors, &limits, self.source())
+                    .map_err(|e| self.state.broken(e))?;
+            }
+        }
+        self.sync_pop();
+        Ok(())
+    }
+
+    pub(crate) fn resolving_begin_enum(&mut self) -> Result<usize> {
+        let reader_variants =
+            match self.state.top_schema() {
+                Some(&Schema::Enum(ref variants)) => &variants[..],
+                _ => &[],
+            };
+        self.state.begin_enum()?;
+        let writer_variants =
+            match self.writer() {
+                Some(&Schema::Enum(ref variants)) => variants,
+                writer => return Err(self.incompatible(writer, "enum")),
+            };
+        let writer_ord = read_ord(self.source(), writer_variants.len())
+            .map_err(|e| self.state.broken(e))?;
+        let name = &writer_variants[writer_ord].name;
+        let reader_ord = reader_variants
+            .iter()
+            .position(|variant| &variant.name == name);
+        let reader_ord =
+            match reader_ord {
+                Some(reader_ord) => reader_ord,
+                None => {
+                    self.state.mark_broken();
+                    bail!(
+                        IncompatibleSchemas,
+                        Some(self.coder_state()),
+                        "writer variant {:?} is not in reader",
+                        name,
+                    );
+                }
+            };
+        self.state
+            .begin_enum_variant_ord(reader_ord)
+            .map_err(|e| self.state.broken(e))?;
+        self.resolver().top().variant = Some(writer_ord);
+        Ok(reader_ord)
+    }
+
+    pub(crate) fn resolving_begin_enum_variant(&mut self, name: &str) -> Result<()> {
+        self.state.begin_enum_variant_name(name)?;
+        let writer_ord = self.resolver().top().variant.unwrap();
+        let frame =
+            match self.writer() {
+                Some(&Schema::Enum(ref variants)) => self.resolver().child(&variants[writer_ord].inner),
+                _ => unreachable!(),
+            };
+        self.sync_push(frame);
+        Ok(())
+    }
+
+    pub(crate) fn resolving_skip(&mut self) -> Result<()> {
+        self.state.code_skipped()?;
+        let writer = self.writer();
+        if let Some(writer) = writer {
+            let mut ancestors = self.resolver().writer_ancestors();
+            ancestors.pop();
+            let limits = *self.limits();
+            skip_raw(writer, &mut ancestors, &limits, self.source())
+                .map_err(|e| self.state.broken(e))?;
+        }
+        self.sync_pop();
+        Ok(())
+    }
+
+    /// Call after a successful coder state call which may have finished a
+    /// seq or tuple.
+    pub(crate) fn resolving_finish(&mut self) {
+        self.sync_pop();
+    }
+}
+
+impl<'a, 'b, 'de> Decoder<'a, 'b, &'de [u8]> {
+    /// Resolving version of `decode_cow`. Borrows from the input slice unless
+    /// the value is being replayed from a read ahead buffer.
+    pub(crate) fn resolving_decode_cow(&mut self, is_str: bool) -> Result<Cow<'de, [u8]>> {
+        let replaying = self.resolver().frames
+            .iter()
+            .any(|frame| frame.replay.is_some());
+        if replaying {
+            let mut buf = Vec::new();
+            self.resolving_decode_str_or_bytes_into(&mut buf, is_str)?;
+            return Ok(Cow::Owned(buf));
+        }
+        match self.writer() {
+            Some(&Schema::Str) => (),
+            Some(&Schema::Bytes) if !is_str => (),
+            writer => return Err(self.incompatible(
+                writer,
+                if is_str { "str" } else { "bytes" },
+            )),
+        }
+        let bytes = read_len(&mut self.read)
+            .map_err(|e| self.state.broken(e))
+            .and_then(|len| self.check_str_bytes_len(len).map(|()| len))
+            .and_then(|len| self.split_borrowed(len))?;
+        self.sync_pop();
+        Ok(Cow::Borrowed(bytes))
+    }
+}
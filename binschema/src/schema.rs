@@ -59,6 +59,23 @@ struct ParentNode<'a> {
 }
 
 impl Schema {
+    /// If self is `Recurse`, resolve it against `ancestors`, the stack of
+    /// schemas being coded with the root first, in the same way `CoderState`
+    /// does. Returns `None` if the recurse is illegal.
+    pub(crate) fn resolve<'a>(
+        &'a self,
+        ancestors: &[&'a Schema],
+    ) -> Option<&'a Schema> {
+        match self {
+            &Schema::Recurse(0) => None,
+            &Schema::Recurse(n) => ancestors
+                .len()
+                .checked_sub(n)
+                .map(|i| ancestors[i]),
+            schema => Some(schema),
+        }
+    }
+
     pub(crate) fn non_recursive_display_str(&self) -> &'static str {
         match self {
             Schema::Scalar(st) => st.display_str(),
@@ -291,7 +308,7 @@ pub enum ScalarType {
 }
 
 impl ScalarType {
-    fn display_str(self) -> &'static str {
+    pub(crate) fn display_str(self) -> &'static str {
         match self {
             ScalarType::U8 => "u8",
             ScalarType::U16 => "u16",
@@ -14,11 +14,16 @@ use crate::{
         StructSchemaField,
         EnumSchemaVariant,
     },
+    coder::coder::CoderState,
+    borrow_decoder::cow_bytes_into_str,
     Decoder,
+    BorrowDecoder,
 };
 use std::{
     io::Read,
     fmt::Display,
+    borrow::Cow,
+    marker::PhantomData,
 };
 use serde::de::{
     value::{
@@ -40,95 +45,167 @@ impl serde::de::Error for Error {
     }
 }
 
-impl<'a, 'b, R: Read> Decoder<'a, 'b, R> {
-    fn deserialize_seq_like<'d, V: Visitor<'d>>(
-        &mut self,
-        v: V,
-        got_len: Option<usize>,
-    ) -> Result<V::Value> {
-        let (len, seq_like) =
-            match self.need()? {
-                &Schema::Seq(SeqSchema { len: Some(len), .. }) => {
-                    self.begin_fixed_len_seq(len)?;
-                    (len, SeqLike::Seq)
-                },
-                &Schema::Seq(SeqSchema { len: None, .. }) => {
-                    let len = self.begin_var_len_seq()?;
-                    (len, SeqLike::Seq)
-                },
-                &Schema::Tuple(ref inner) => {
-                    self.begin_tuple()?;
-                    (inner.len(), SeqLike::Tuple)
-                }
-                &Schema::Unit => {
-                    self.decode_unit()?;
-                    (0, SeqLike::Unit)
-                }
-                schema => bail!(
-                    SchemaNonConformance,
-                    Some(self.coder_state()),
-                    "need {:?}, got seq-like",
-                    schema,
-                ),
-            };
-        if let Some(got_len) = got_len {
-            ensure!(
-                len == got_len,
+/// Serde glue is shared between `Decoder` and `BorrowDecoder`, which differ
+/// only in how they visit strs and bytes. This gets at the underlying
+/// `Decoder` of either.
+trait AsDecoder<'a, 'b>: 'b {
+    type Read: Read + 'b;
+
+    fn as_decoder(&mut self) -> &mut Decoder<'a, 'b, Self::Read>;
+}
+
+impl<'a, 'b, R: Read> AsDecoder<'a, 'b> for Decoder<'a, 'b, R> {
+    type Read = R;
+
+    fn as_decoder(&mut self) -> &mut Self {
+        self
+    }
+}
+
+impl<'a, 'b, 'de> AsDecoder<'a, 'b> for BorrowDecoder<'a, 'b, 'de> {
+    type Read = &'de [u8];
+
+    fn as_decoder(&mut self) -> &mut Decoder<'a, 'b, &'de [u8]> {
+        self
+    }
+}
+
+fn deserialize_seq_like<'a, 'b, 'c, 'd, D, V>(
+    d: &'c mut D,
+    v: V,
+    got_len: Option<usize>,
+) -> Result<V::Value>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+    V: Visitor<'d>,
+{
+    let decoder = d.as_decoder();
+    let (len, seq_like) =
+        match decoder.need()? {
+            &Schema::Seq(SeqSchema { len: Some(len), .. }) => {
+                decoder.begin_fixed_len_seq(len)?;
+                (len, SeqLike::Seq)
+            },
+            &Schema::Seq(SeqSchema { len: None, .. }) => {
+                let len = decoder.begin_var_len_seq()?;
+                (len, SeqLike::Seq)
+            },
+            &Schema::Tuple(ref inner) => {
+                decoder.begin_tuple()?;
+                (inner.len(), SeqLike::Tuple)
+            }
+            &Schema::Unit => {
+                decoder.decode_unit()?;
+                (0, SeqLike::Unit)
+            }
+            schema => bail!(
                 SchemaNonConformance,
-                Some(self.coder_state()),
-                "need seq-like len {}, got seq-like len {}",
-                len,
-                got_len,
-            );
+                Some(decoder.coder_state()),
+                "need {:?}, got seq-like",
+                schema,
+            ),
+        };
+    if let Some(got_len) = got_len {
+        ensure!(
+            len == got_len,
+            SchemaNonConformance,
+            Some(decoder.coder_state()),
+            "need seq-like len {}, got seq-like len {}",
+            len,
+            got_len,
+        );
+    }
+    if len == 0 {
+        // there will be no last element to finish after
+        match seq_like {
+            SeqLike::Seq => decoder.finish_seq()?,
+            SeqLike::Tuple => decoder.finish_tuple()?,
+            SeqLike::Unit => (),
         }
-        v.visit_seq(SeqDecoder {
-            decoder: self,
-            remaining: len,
-            seq_like,
-        })
     }
+    v.visit_seq(SeqDecoder {
+        decoder: d,
+        remaining: len,
+        seq_like,
+        _p: PhantomData,
+    })
+}
 
-    fn inner_deserialize_struct<'d, V, N>(
-        &mut self,
-        fields: &[N],
-        v: V,
-    ) -> Result<V::Value>
-    where
-        V: Visitor<'d>,
-        N: AsName,
-    {
-        self.begin_struct()?;
-        v.visit_map(StructDecoder {
-            decoder: self,
-            remaining: fields,
-        })
+fn inner_deserialize_struct<'a, 'b, 'c, 'd, D, V, N>(
+    d: &'c mut D,
+    fields: &[N],
+    v: V,
+) -> Result<V::Value>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+    V: Visitor<'d>,
+    N: AsName,
+{
+    d.as_decoder().begin_struct()?;
+    if fields.is_empty() {
+        // there will be no last field to finish after
+        d.as_decoder().finish_struct()?;
     }
+    v.visit_map(StructDecoder {
+        decoder: d,
+        remaining: fields,
+        _p: PhantomData,
+    })
+}
 
-    fn inner_deserialize_enum<'d, V, N>(
-        &mut self,
-        variants: &[N],
-        v: V,
-    ) -> Result<V::Value>
-    where
-        V: Visitor<'d>,
-        N: AsName,
-    {
-        let ord = self.begin_enum()?;
-        let name = variants
-            .get(ord)
-            .ok_or_else(|| error!(
-                SchemaNonConformance,
-                Some(self.coder_state()),
-                "decoded ord {}, but only {} variants provided to deserialize_enum",
-                ord,
-                variants.len(),
-            ))?;
-        self.begin_enum_variant(name.as_name())?;
-        v.visit_enum(EnumDecoder {
-            decoder: self,
+fn inner_deserialize_enum<'a, 'b, 'c, 'd, D, V, N>(
+    d: &'c mut D,
+    variants: &[N],
+    v: V,
+) -> Result<V::Value>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+    V: Visitor<'d>,
+    for<'x> &'x mut D: VariantAccess<'d, Error = Error>,
+    N: AsName,
+{
+    let decoder = d.as_decoder();
+    let ord = decoder.begin_enum()?;
+    let name = variants
+        .get(ord)
+        .ok_or_else(|| error!(
+            SchemaNonConformance,
+            Some(decoder.coder_state()),
+            "decoded ord {}, but only {} variants provided to deserialize_enum",
             ord,
-        })
+            variants.len(),
+        ))?;
+    decoder.begin_enum_variant(name.as_name())?;
+    v.visit_enum(EnumDecoder {
+        decoder: d,
+        ord,
+        _p: PhantomData,
+    })
+}
+
+fn deserialize_map<'a, 'b, 'c, 'd, D, V>(d: &'c mut D, v: V) -> Result<V::Value>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+    V: Visitor<'d>,
+{
+    let len = d.as_decoder().begin_var_len_seq()?;
+    if len == 0 {
+        // there will be no last entry to finish after
+        d.as_decoder().finish_seq()?;
     }
+    v.visit_map(MapDecoder {
+        decoder: d,
+        remaining: len,
+        _p: PhantomData,
+    })
 }
 
 macro_rules! leaf_methods {
@@ -136,162 +213,233 @@ macro_rules! leaf_methods {
         $deserialize:ident, $visit:ident, $decode:ident;
     )*)=>{$(
         fn $deserialize<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-            v.$visit(self.$decode()?)
+            v.$visit(self.as_decoder().$decode()?)
         }
     )*};
 }
 
-impl<'a, 'b, 'c, 'd, R: Read> Deserializer<'d> for &'c mut Decoder<'a, 'b, R> {
-    type Error = Error;
+/// Deserializer methods other than those for strs and bytes, which are the
+/// same for `Decoder` and `BorrowDecoder`.
+macro_rules! shared_methods {
+    ()=>{
+        fn deserialize_any<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            match self.as_decoder().need()? {
+                &Schema::Scalar(st) => match st {
+                    ScalarType::U8 => self.deserialize_u8(v),
+                    ScalarType::U16 => self.deserialize_u16(v),
+                    ScalarType::U32 => self.deserialize_u32(v),
+                    ScalarType::U64 => self.deserialize_u64(v),
+                    ScalarType::U128 => self.deserialize_u128(v),
+                    ScalarType::I8 => self.deserialize_i8(v),
+                    ScalarType::I16 => self.deserialize_i16(v),
+                    ScalarType::I32 => self.deserialize_i32(v),
+                    ScalarType::I64 => self.deserialize_i64(v),
+                    ScalarType::I128 => self.deserialize_i128(v),
+                    ScalarType::F32 => self.deserialize_f32(v),
+                    ScalarType::F64 => self.deserialize_f64(v),
+                    ScalarType::Char => self.deserialize_char(v),
+                    ScalarType::Bool => self.deserialize_bool(v),
+                }
+                &Schema::Str => self.deserialize_str(v),
+                &Schema::Bytes => self.deserialize_bytes(v),
+                &Schema::Unit => self.deserialize_unit(v),
+                &Schema::Option(_) => self.deserialize_option(v),
+                &Schema::Seq(_) => deserialize_seq_like(self, v, None),
+                &Schema::Tuple(_) => deserialize_seq_like(self, v, None),
+                &Schema::Struct(
+                    ref fields,
+                ) => inner_deserialize_struct(self, fields, v),
+                &Schema::Enum(
+                    ref variants,
+                ) => inner_deserialize_enum(self, variants, v),
+                &Schema::Recurse(_) => unreachable!(),
+            }
+        }
 
-    fn deserialize_any<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        match self.need()? {
-            &Schema::Scalar(st) => match st {
-                ScalarType::U8 => self.deserialize_u8(v),
-                ScalarType::U16 => self.deserialize_u16(v),
-                ScalarType::U32 => self.deserialize_u32(v),
-                ScalarType::U64 => self.deserialize_u64(v),
-                ScalarType::U128 => self.deserialize_u128(v),
-                ScalarType::I8 => self.deserialize_i8(v),
-                ScalarType::I16 => self.deserialize_i16(v),
-                ScalarType::I32 => self.deserialize_i32(v),
-                ScalarType::I64 => self.deserialize_i64(v),
-                ScalarType::I128 => self.deserialize_i128(v),
-                ScalarType::F32 => self.deserialize_f32(v),
-                ScalarType::F64 => self.deserialize_f64(v),
-                ScalarType::Char => self.deserialize_char(v),
-                ScalarType::Bool => self.deserialize_bool(v),
+        leaf_methods!(
+            deserialize_bool, visit_bool, decode_bool;
+            deserialize_i8, visit_i8, decode_i8;
+            deserialize_i16, visit_i16, decode_i16;
+            deserialize_i32, visit_i32, decode_i32;
+            deserialize_i64, visit_i64, decode_i64;
+            deserialize_i128, visit_i128, decode_i128;
+            deserialize_u8, visit_u8, decode_u8;
+            deserialize_u16, visit_u16, decode_u16;
+            deserialize_u32, visit_u32, decode_u32;
+            deserialize_u64, visit_u64, decode_u64;
+            deserialize_u128, visit_u128, decode_u128;
+            deserialize_f32, visit_f32, decode_f32;
+            deserialize_f64, visit_f64, decode_f64;
+            deserialize_char, visit_char, decode_char;
+        );
+
+        fn deserialize_option<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            match self.as_decoder().begin_option()? {
+                false => v.visit_none(),
+                true => v.visit_some(self)
             }
-            &Schema::Str => self.deserialize_str(v),
-            &Schema::Bytes => self.deserialize_bytes(v),
-            &Schema::Unit => self.deserialize_unit(v),
-            &Schema::Option(_) => self.deserialize_option(v),
-            &Schema::Seq(_) => self.deserialize_seq_like(v, None),
-            &Schema::Tuple(_) => self.deserialize_seq_like(v, None),
-            &Schema::Struct(
-                ref fields,
-            ) => self.inner_deserialize_struct(fields, v),
-            &Schema::Enum(
-                ref variants,
-            ) => self.inner_deserialize_enum(variants, v),
-            &Schema::Recurse(_) => unreachable!(),
         }
-    }
 
-    leaf_methods!(
-        deserialize_bool, visit_bool, decode_bool;
-        deserialize_i8, visit_i8, decode_i8;
-        deserialize_i16, visit_i16, decode_i16;
-        deserialize_i32, visit_i32, decode_i32;
-        deserialize_i64, visit_i64, decode_i64;
-        deserialize_i128, visit_i128, decode_i128;
-        deserialize_u8, visit_u8, decode_u8;
-        deserialize_u16, visit_u16, decode_u16;
-        deserialize_u32, visit_u32, decode_u32;
-        deserialize_u64, visit_u64, decode_u64;
-        deserialize_u128, visit_u128, decode_u128;
-        deserialize_f32, visit_f32, decode_f32;
-        deserialize_f64, visit_f64, decode_f64;
-        deserialize_char, visit_char, decode_char;
-        deserialize_str, visit_string, decode_str;
-        deserialize_string, visit_string, decode_str;
-        deserialize_bytes, visit_byte_buf, decode_bytes;
-        deserialize_byte_buf, visit_byte_buf, decode_bytes;
-    );
+        fn deserialize_unit<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            self.as_decoder().decode_unit()?;
+            v.visit_unit()
+        }
 
-    fn deserialize_option<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        match self.begin_option()? {
-            false => v.visit_none(),
-            true => v.visit_some(self)
+        fn deserialize_unit_struct<V: Visitor<'d>>(
+            self,
+            _name: &'static str,
+            v: V,
+        ) -> Result<V::Value> {
+            self.as_decoder().decode_unit()?;
+            v.visit_unit()
         }
-    }
 
-    fn deserialize_unit<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        self.decode_unit()?;
-        v.visit_unit()
-    }
+        fn deserialize_newtype_struct<V: Visitor<'d>>(
+            self,
+            _name: &'static str,
+            v: V,
+        ) -> Result<V::Value> {
+            v.visit_newtype_struct(self)
+        }
 
-    fn deserialize_unit_struct<V: Visitor<'d>>(
-        self,
-        _name: &'static str,
-        v: V,
-    ) -> Result<V::Value> {
-        self.decode_unit()?;
-        v.visit_unit()
-    }
+        fn deserialize_seq<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            deserialize_seq_like(self, v, None)
+        }
 
-    fn deserialize_newtype_struct<V: Visitor<'d>>(
-        self,
-        _name: &'static str,
-        v: V,
-    ) -> Result<V::Value> {
-        v.visit_newtype_struct(self)
-    }
+        fn deserialize_tuple<V: Visitor<'d>>(
+            self,
+            got_len: usize,
+            v: V,
+        ) -> Result<V::Value> {
+            deserialize_seq_like(self, v, Some(got_len))
+        }
 
-    fn deserialize_seq<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        self.deserialize_seq_like(v, None)
-    }
+        fn deserialize_tuple_struct<V: Visitor<'d>>(
+            self,
+            _name: &'static str,
+            got_len: usize,
+            v: V,
+        ) -> Result<V::Value> {
+            deserialize_seq_like(self, v, Some(got_len))
+        }
 
-    fn deserialize_tuple<V: Visitor<'d>>(
-        self,
-        got_len: usize,
-        v: V,
-    ) -> Result<V::Value> {
-        self.deserialize_seq_like(v, Some(got_len))
-    }
+        fn deserialize_map<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            deserialize_map(self, v)
+        }
 
-    fn deserialize_tuple_struct<V: Visitor<'d>>(
-        self,
-        _name: &'static str,
-        got_len: usize,
-        v: V,
-    ) -> Result<V::Value> {
-        self.deserialize_seq_like(v, Some(got_len))
-    }
+        fn deserialize_struct<V: Visitor<'d>>(
+            self,
+            _name: &'static str,
+            fields: &'static [&'static str],
+            v: V,
+        ) -> Result<V::Value> {
+            inner_deserialize_struct(self, fields, v)
+        }
+
+        fn deserialize_enum<V: Visitor<'d>>(
+            self,
+            _name: &'static str,
+            variants: &'static [&'static str],
+            v: V,
+        ) -> Result<V::Value> {
+            inner_deserialize_enum(self, variants, v)
+        }
+
+        fn deserialize_identifier<V: Visitor<'d>>(self, _v: V) -> Result<V::Value> {
+            Err(error!(
+                ApiUsage,
+                Some(self.as_decoder().coder_state()),
+                "deserialize_identifier directly on Decoder",
+            ))
+        }
+
+        fn deserialize_ignored_any<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+            self.as_decoder().skip()?;
+            v.visit_unit()
+        }
+
+        fn is_human_readable(&self) -> bool {
+            false
+        }
+    };
+}
+
+impl<'a, 'b, 'c, 'd, R: Read> Deserializer<'d> for &'c mut Decoder<'a, 'b, R> {
+    type Error = Error;
 
-    fn deserialize_map<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        let len = self.begin_var_len_seq()?;
-        v.visit_map(MapDecoder {
-            decoder: self,
-            remaining: len,
-        })
+    shared_methods!();
+
+    leaf_methods!(
+        deserialize_str, visit_string, decode_str;
+        deserialize_string, visit_string, decode_str;
+    );
+
+    fn deserialize_bytes<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        match decode_u8_seq(self)? {
+            Some(buf) => v.visit_byte_buf(buf),
+            None => v.visit_byte_buf(self.decode_bytes()?),
+        }
     }
 
-    fn deserialize_struct<V: Visitor<'d>>(
-        self,
-        _name: &'static str,
-        fields: &'static [&'static str],
-        v: V,
-    ) -> Result<V::Value> {
-        self.inner_deserialize_struct(fields, v)
+    fn deserialize_byte_buf<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        self.deserialize_bytes(v)
     }
+}
 
-    fn deserialize_enum<V: Visitor<'d>>(
-        self,
-        _name: &'static str,
-        variants: &'static [&'static str],
-        v: V,
-    ) -> Result<V::Value> {
-        self.inner_deserialize_enum(variants, v)
+impl<'a, 'b, 'c, 'd> Deserializer<'d> for &'c mut BorrowDecoder<'a, 'b, 'd> {
+    type Error = Error;
+
+    shared_methods!();
+
+    fn deserialize_str<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        match cow_bytes_into_str(self.decode_cow(true)?) {
+            Cow::Borrowed(s) => v.visit_borrowed_str(s),
+            Cow::Owned(s) => v.visit_string(s),
+        }
     }
 
-    fn deserialize_identifier<V: Visitor<'d>>(self, _v: V) -> Result<V::Value> {
-        Err(error!(
-            ApiUsage,
-            Some(self.coder_state()),
-            "deserialize_identifier directly on Decoder",
-        ))
+    fn deserialize_string<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        self.deserialize_str(v)
     }
 
-    fn deserialize_ignored_any<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
-        self.deserialize_any(v)
+    fn deserialize_bytes<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        if let Some(buf) = decode_u8_seq(self)? {
+            return v.visit_byte_buf(buf);
+        }
+        match self.decode_cow(false)? {
+            Cow::Borrowed(b) => v.visit_borrowed_bytes(b),
+            Cow::Owned(b) => v.visit_byte_buf(b),
+        }
     }
 
-    fn is_human_readable(&self) -> bool {
-        false
+    fn deserialize_byte_buf<V: Visitor<'d>>(self, v: V) -> Result<V::Value> {
+        self.deserialize_bytes(v)
     }
 }
 
+/// If a seq of u8 is needed, decode it in bulk. This is so that serde_bytes
+/// can be used with the schema of a `Vec<u8>` or `[u8; N]`.
+fn decode_u8_seq<R: Read>(decoder: &mut Decoder<R>) -> Result<Option<Vec<u8>>> {
+    let len =
+        match decoder.need() {
+            Ok(&Schema::Seq(SeqSchema { len, ref inner }))
+                if **inner == Schema::Scalar(ScalarType::U8) => len,
+            _ => return Ok(None),
+        };
+    let len =
+        match len {
+            Some(len) => {
+                decoder.begin_fixed_len_seq(len)?;
+                len
+            }
+            None => decoder.begin_var_len_seq()?,
+        };
+    let mut buf = Vec::new();
+    decoder.decode_u8_seq_elems(len, &mut buf)?;
+    decoder.finish_seq()?;
+    Ok(Some(buf))
+}
+
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 enum SeqLike {
     Seq,
@@ -299,13 +447,19 @@ enum SeqLike {
     Unit,
 }
 
-struct SeqDecoder<'a, 'b, 'c, R> {
-    decoder: &'c mut Decoder<'a, 'b, R>,
+struct SeqDecoder<'a, 'b, 'c, D> {
+    decoder: &'c mut D,
     remaining: usize,
     seq_like: SeqLike,
+    _p: PhantomData<&'b mut CoderState<'a>>,
 }
 
-impl<'a, 'b, 'c, 'd, R: Read> SeqAccess<'d> for SeqDecoder<'a, 'b, 'c, R> {
+impl<'a, 'b, 'c, 'd, D> SeqAccess<'d> for SeqDecoder<'a, 'b, 'c, D>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+{
     type Error = Error;
 
     fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
@@ -315,21 +469,23 @@ impl<'a, 'b, 'c, 'd, R: Read> SeqAccess<'d> for SeqDecoder<'a, 'b, 'c, R> {
         if self.remaining > 0 {
             self.remaining -= 1;
 
+            let decoder = self.decoder.as_decoder();
             match self.seq_like {
-                SeqLike::Seq => self.decoder.begin_seq_elem()?,
-                SeqLike::Tuple => self.decoder.begin_tuple_elem()?,
+                SeqLike::Seq => decoder.begin_seq_elem()?,
+                SeqLike::Tuple => decoder.begin_tuple_elem()?,
                 SeqLike::Unit => bail!(
                     Other,
-                    Some(self.decoder.coder_state()),
+                    Some(decoder.coder_state()),
                     "deserialize element from unit as seq-like",
                 ),
             };
             let value = seed.deserialize(&mut *self.decoder)?;
 
             if self.remaining == 0 {
+                let decoder = self.decoder.as_decoder();
                 match self.seq_like {
-                    SeqLike::Seq => self.decoder.finish_seq()?,
-                    SeqLike::Tuple => self.decoder.finish_tuple()?,
+                    SeqLike::Seq => decoder.finish_seq()?,
+                    SeqLike::Tuple => decoder.finish_tuple()?,
                     SeqLike::Unit => unreachable!(),
                 }
             }
@@ -341,12 +497,18 @@ impl<'a, 'b, 'c, 'd, R: Read> SeqAccess<'d> for SeqDecoder<'a, 'b, 'c, R> {
     }
 }
 
-struct MapDecoder<'a, 'b, 'c, R> {
-    decoder: &'c mut Decoder<'a, 'b, R>,
+struct MapDecoder<'a, 'b, 'c, D> {
+    decoder: &'c mut D,
     remaining: usize,
+    _p: PhantomData<&'b mut CoderState<'a>>,
 }
 
-impl<'a, 'b, 'c, 'd, R: Read> MapAccess<'d> for MapDecoder<'a, 'b, 'c, R> {
+impl<'a, 'b, 'c, 'd, D> MapAccess<'d> for MapDecoder<'a, 'b, 'c, D>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+{
     type Error = Error;
 
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
@@ -356,9 +518,10 @@ impl<'a, 'b, 'c, 'd, R: Read> MapAccess<'d> for MapDecoder<'a, 'b, 'c, R> {
         if self.remaining > 0 {
             self.remaining -= 1;
 
-            self.decoder.begin_seq_elem()?;
-            self.decoder.begin_tuple()?;
-            self.decoder.begin_tuple_elem()?;
+            let decoder = self.decoder.as_decoder();
+            decoder.begin_seq_elem()?;
+            decoder.begin_tuple()?;
+            decoder.begin_tuple_elem()?;
             Ok(Some(seed.deserialize(&mut *self.decoder)?))
         } else {
             Ok(None)
@@ -369,12 +532,12 @@ impl<'a, 'b, 'c, 'd, R: Read> MapAccess<'d> for MapDecoder<'a, 'b, 'c, R> {
     where
         V: DeserializeSeed<'d>,
     {
-        self.decoder.begin_tuple_elem()?;
+        self.decoder.as_decoder().begin_tuple_elem()?;
         let value = seed.deserialize(&mut *self.decoder)?;
-        self.decoder.finish_tuple()?;
+        self.decoder.as_decoder().finish_tuple()?;
 
         if self.remaining == 0 {
-            self.decoder.finish_seq()?;
+            self.decoder.as_decoder().finish_seq()?;
         }
 
         Ok(value)
@@ -397,16 +560,20 @@ impl AsName for EnumSchemaVariant {
     fn as_name(&self) -> &str { &self.name }
 }
 
-struct StructDecoder<'a, 'b, 'c, 'n, R, N> {
-    decoder: &'c mut Decoder<'a, 'b, R>,
+struct StructDecoder<'a, 'b, 'c, 'n, D, N> {
+    decoder: &'c mut D,
     remaining: &'n [N],
+    _p: PhantomData<&'b mut CoderState<'a>>,
 }
 
 impl<
     'a, 'b, 'c, 'n, 'd,
-    R: Read,
+    D: AsDecoder<'a, 'b>,
     N: AsName,
-> MapAccess<'d> for StructDecoder<'a, 'b, 'c, 'n, R, N>
+> MapAccess<'d> for StructDecoder<'a, 'b, 'c, 'n, D, N>
+where
+    'a: 'b,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
 {
     type Error = Error;
 
@@ -414,10 +581,10 @@ impl<
     where
         K: DeserializeSeed<'d>,
     {
-        if let Some(next) = self.remaining.get(0) {
+        if let Some(next) = self.remaining.first() {
             self.remaining = &self.remaining[1..];
 
-            self.decoder.begin_struct_field(next.as_name())?;
+            self.decoder.as_decoder().begin_struct_field(next.as_name())?;
             Ok(Some(seed.deserialize(
                 StrDeserializer::<Error>::new(next.as_name())
             )?))
@@ -433,21 +600,28 @@ impl<
         let value = seed.deserialize(&mut *self.decoder)?;
 
         if self.remaining.is_empty() {
-            self.decoder.finish_struct()?;
+            self.decoder.as_decoder().finish_struct()?;
         }
 
         Ok(value)
     }
 }
 
-struct EnumDecoder<'a, 'b, 'c, R> {
+struct EnumDecoder<'a, 'b, 'c, D> {
     ord: usize,
-    decoder: &'c mut Decoder<'a, 'b, R>,
+    decoder: &'c mut D,
+    _p: PhantomData<&'b mut CoderState<'a>>,
 }
 
-impl<'a, 'b, 'c, 'd, R: Read> EnumAccess<'d> for EnumDecoder<'a, 'b, 'c, R> {
+impl<'a, 'b, 'c, 'd, D> EnumAccess<'d> for EnumDecoder<'a, 'b, 'c, D>
+where
+    'a: 'b,
+    D: AsDecoder<'a, 'b>,
+    for<'x> &'x mut D: Deserializer<'d, Error = Error>,
+    &'c mut D: VariantAccess<'d, Error = Error>,
+{
     type Error = Error;
-    type Variant = &'c mut Decoder<'a, 'b, R>;
+    type Variant = &'c mut D;
 
     fn variant_seed<V: DeserializeSeed<'d>>(
         self,
@@ -461,33 +635,45 @@ impl<'a, 'b, 'c, 'd, R: Read> EnumAccess<'d> for EnumDecoder<'a, 'b, 'c, R> {
     }
 }
 
-impl<'a, 'b, 'c, 'd, R: Read> VariantAccess<'d> for &'c mut Decoder<'a, 'b, R> {
-    type Error = Error;
+/// VariantAccess implementation, which is the same for `Decoder` and
+/// `BorrowDecoder`.
+macro_rules! variant_access_methods {
+    ()=>{
+        type Error = Error;
 
-    fn unit_variant(self) -> Result<()> {
-        self.decode_unit()
-    }
+        fn unit_variant(self) -> Result<()> {
+            self.as_decoder().decode_unit()
+        }
 
-    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
-    where
-        T: DeserializeSeed<'d>,
-    {
-        seed.deserialize(self)
-    }
+        fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value>
+        where
+            T: DeserializeSeed<'d>,
+        {
+            seed.deserialize(self)
+        }
 
-    fn tuple_variant<V: Visitor<'d>>(
-        self,
-        len: usize,
-        visitor: V,
-    ) -> Result<V::Value> {
-        self.deserialize_tuple(len, visitor)
-    }
+        fn tuple_variant<V: Visitor<'d>>(
+            self,
+            len: usize,
+            visitor: V,
+        ) -> Result<V::Value> {
+            self.deserialize_tuple(len, visitor)
+        }
 
-    fn struct_variant<V: Visitor<'d>>(
-        self,
-        fields: &'static [&'static str],
-        visitor: V,
-    ) -> Result<V::Value> {
-        self.inner_deserialize_struct(fields, visitor)
-    }
+        fn struct_variant<V: Visitor<'d>>(
+            self,
+            fields: &'static [&'static str],
+            visitor: V,
+        ) -> Result<V::Value> {
+            inner_deserialize_struct(self, fields, visitor)
+        }
+    };
+}
+
+impl<'a, 'b, 'c, 'd, R: Read> VariantAccess<'d> for &'c mut Decoder<'a, 'b, R> {
+    variant_access_methods!();
+}
+
+impl<'a, 'b, 'c, 'd> VariantAccess<'d> for &'c mut BorrowDecoder<'a, 'b, 'd> {
+    variant_access_methods!();
 }
@@ -1,5 +1,6 @@
 //! Glue between this library and serde. Makes `&mut Encoder` implement
-//! `serde::Serializer`. Some notes on how translations occur:
+//! `serde::Serializer`, and `&mut Decoder` and `&mut BorrowDecoder` implement
+//! `serde::Deserializer`. Some notes on how translations occur:
 //!
 //! - unit structs and unit variants are encoded simply as unit
 //! - newtype structs and newtype variants are encoded simply as the inner
@@ -11,6 +12,14 @@
 //! - a map is encoded as a var len seq of (key, value) tuples
 //! - when asked to "skip a struct field", it tries encoding a none value for
 //!   that field
+//! - when asked to deserialize an ignored value, it skips over the value
+//!   without materializing it
+//! - `BorrowDecoder` visits strs and bytes as borrowed from the input slice,
+//!   so `&str`, `&[u8]`, and `#[serde(borrow)]` fields decode without
+//!   allocating
+//! - `Value` serializes with interned field and variant names, see the `value`
+//!   submodule
 
 pub mod ser;
 pub mod de;
+pub mod value;
@@ -8,6 +8,7 @@ use crate::{
     },
     schema::{
         Schema,
+        ScalarType,
         SeqSchema,
     },
     Encoder,
@@ -115,9 +116,27 @@ impl<'a, 'b, 'c, W: Write> Serializer for &'c mut Encoder<'a, 'b, W> {
         serialize_f64(f64), encode_f64;
         serialize_char(char), encode_char;
         serialize_str(&str), encode_str;
-        serialize_bytes(&[u8]), encode_bytes;
     );
 
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        // so that serde_bytes can be used with the schema of a `Vec<u8>` or
+        // `[u8; N]`, which is a seq of u8
+        match self.need() {
+            Ok(&Schema::Seq(SeqSchema { len, ref inner }))
+                if **inner == Schema::Scalar(ScalarType::U8) =>
+            {
+                if len.is_some() {
+                    self.begin_fixed_len_seq(v.len())?;
+                } else {
+                    self.begin_var_len_seq(v.len())?;
+                }
+                self.encode_u8_seq_elems(v)?;
+                self.finish_seq()
+            }
+            _ => self.encode_bytes(v),
+        }
+    }
+
     fn serialize_none(self) -> Result<()> {
         self.encode_none()
     }
@@ -0,0 +1,531 @@
+//! Serde implementations for `Value`.
+//!
+//! Serde requires struct field names and enum variant names to be
+//! `&'static str`, which those of a `Value` are not. As such, serializing a
+//! `Value` interns them, leaking one copy of each distinct name for the life
+//! of the process. Structs serialize as structs and enums as newtype
+//! variants, so a `Value` can be serialized with `Encoder` as well as to
+//! self-describing formats. Deserializing them instead uses `deserialize_any`,
+//! and so requires a self-describing format, or `Decoder`.
+
+use crate::{
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+        EnumSchemaVariant,
+    },
+    value::{
+        Value,
+        ScalarValue,
+        StructValueField,
+        EnumValue,
+    },
+};
+use std::{
+    collections::HashSet,
+    fmt::{self, Formatter},
+    sync::Mutex,
+};
+use serde::{
+    ser::{
+        Serialize,
+        Serializer,
+        SerializeSeq,
+        SerializeTuple,
+        SerializeStruct,
+    },
+    de::{
+        Deserializer,
+        DeserializeSeed,
+        Visitor,
+        SeqAccess,
+        MapAccess,
+        EnumAccess,
+        VariantAccess,
+        Error,
+        Unexpected,
+    },
+};
+
+
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self {
+            &Value::Scalar(scalar) => scalar.serialize(s),
+            &Value::Str(ref string) => s.serialize_str(string),
+            &Value::Bytes(ref bytes) => s.serialize_bytes(bytes),
+            &Value::Unit => s.serialize_unit(),
+            &Value::Option(None) => s.serialize_none(),
+            &Value::Option(Some(ref inner)) => s.serialize_some(inner),
+            &Value::FixedLenSeq(ref elems) | &Value::Tuple(ref elems) => {
+                let mut s = s.serialize_tuple(elems.len())?;
+                for elem in elems {
+                    s.serialize_element(elem)?;
+                }
+                s.end()
+            }
+            &Value::VarLenSeq(ref elems) => {
+                let mut s = s.serialize_seq(Some(elems.len()))?;
+                for elem in elems {
+                    s.serialize_element(elem)?;
+                }
+                s.end()
+            }
+            &Value::Struct(ref fields) => {
+                let mut s = s.serialize_struct("", fields.len())?;
+                for field in fields {
+                    s.serialize_field(intern(&field.name), &field.value)?;
+                }
+                s.end()
+            }
+            &Value::Enum(ref enum_value) => s.serialize_newtype_variant(
+                "",
+                enum_value.variant_ord as u32,
+                intern(&enum_value.variant_name),
+                &enum_value.value,
+            ),
+        }
+    }
+}
+
+/// Get a `&'static str` equal to `name`, leaking it the first time.
+fn intern(name: &str) -> &'static str {
+    static NAMES: Mutex<Option<HashSet<&'static str>>> = Mutex::new(None);
+
+    let mut names = NAMES.lock().unwrap_or_else(|e| e.into_inner());
+    let names = names.get_or_insert_with(HashSet::new);
+    match names.get(name) {
+        Some(&interned) => interned,
+        None => {
+            let interned: &'static str = Box::leak(name.into());
+            names.insert(interned);
+            interned
+        }
+    }
+}
+
+impl Serialize for ScalarValue {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match *self {
+            ScalarValue::U8(n) => s.serialize_u8(n),
+            ScalarValue::U16(n) => s.serialize_u16(n),
+            ScalarValue::U32(n) => s.serialize_u32(n),
+            ScalarValue::U64(n) => s.serialize_u64(n),
+            ScalarValue::U128(n) => s.serialize_u128(n),
+            ScalarValue::I8(n) => s.serialize_i8(n),
+            ScalarValue::I16(n) => s.serialize_i16(n),
+            ScalarValue::I32(n) => s.serialize_i32(n),
+            ScalarValue::I64(n) => s.serialize_i64(n),
+            ScalarValue::I128(n) => s.serialize_i128(n),
+            ScalarValue::F32(n) => s.serialize_f32(n),
+            ScalarValue::F64(n) => s.serialize_f64(n),
+            ScalarValue::Char(c) => s.serialize_char(c),
+            ScalarValue::Bool(b) => s.serialize_bool(b),
+        }
+    }
+}
+
+/// Deserializes a `Value` of the given schema, from any serde format. Values
+/// are converted to the schema's types where they fit, such as a JSON number
+/// into any int type it's in range of.
+#[derive(Debug, Copy, Clone)]
+pub struct ValueSeed<'s> {
+    schema: &'s Schema,
+}
+
+impl<'s> ValueSeed<'s> {
+    pub fn new(schema: &'s Schema) -> Self {
+        ValueSeed { schema }
+    }
+}
+
+impl<'s, 'de> DeserializeSeed<'de> for ValueSeed<'s> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        SchemaSeed {
+            schema: self.schema,
+            ancestors: None,
+        }.deserialize(d)
+    }
+}
+
+/// Linked stack of the schemas being deserialized, for resolving recursion.
+struct Ancestors<'s, 'p> {
+    schema: &'s Schema,
+    parent: Option<&'p Ancestors<'s, 'p>>,
+}
+
+/// Seed for a value of `schema`, possibly `Recurse`, nested within
+/// `ancestors`.
+#[derive(Copy, Clone)]
+struct SchemaSeed<'s, 'p> {
+    schema: &'s Schema,
+    ancestors: Option<&'p Ancestors<'s, 'p>>,
+}
+
+impl<'s, 'p> SchemaSeed<'s, 'p> {
+    fn resolve(self) -> Option<&'s Schema> {
+        match self.schema {
+            &Schema::Recurse(n) => {
+                let mut ancestors = self.ancestors;
+                for _ in 1..n {
+                    ancestors = ancestors?.parent;
+                }
+                ancestors.filter(|_| n > 0).map(|ancestors| ancestors.schema)
+            }
+            schema => Some(schema),
+        }
+    }
+}
+
+impl<'s, 'p, 'de> DeserializeSeed<'de> for SchemaSeed<'s, 'p> {
+    type Value = Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        let schema = self
+            .resolve()
+            .ok_or_else(|| D::Error::custom("illegal recurse in schema"))?;
+        let ancestors = Ancestors {
+            schema,
+            parent: self.ancestors,
+        };
+        let v = ValueVisitor {
+            schema,
+            ancestors: &ancestors,
+        };
+        match schema {
+            &Schema::Scalar(st) => match st {
+                ScalarType::U8 => d.deserialize_u8(v),
+                ScalarType::U16 => d.deserialize_u16(v),
+                ScalarType::U32 => d.deserialize_u32(v),
+                ScalarType::U64 => d.deserialize_u64(v),
+                ScalarType::U128 => d.deserialize_u128(v),
+                ScalarType::I8 => d.deserialize_i8(v),
+                ScalarType::I16 => d.deserialize_i16(v),
+                ScalarType::I32 => d.deserialize_i32(v),
+                ScalarType::I64 => d.deserialize_i64(v),
+                ScalarType::I128 => d.deserialize_i128(v),
+                ScalarType::F32 => d.deserialize_f32(v),
+                ScalarType::F64 => d.deserialize_f64(v),
+                ScalarType::Char => d.deserialize_char(v),
+                ScalarType::Bool => d.deserialize_bool(v),
+            },
+            &Schema::Str => d.deserialize_string(v),
+            &Schema::Bytes => d.deserialize_byte_buf(v),
+            &Schema::Unit => d.deserialize_unit(v),
+            &Schema::Option(_) => d.deserialize_option(v),
+            &Schema::Seq(SeqSchema { len: Some(len), .. }) => d.deserialize_tuple(len, v),
+            &Schema::Seq(SeqSchema { len: None, .. }) => d.deserialize_seq(v),
+            &Schema::Tuple(ref inners) => d.deserialize_tuple(inners.len(), v),
+            &Schema::Struct(_) | &Schema::Enum(_) => d.deserialize_any(v),
+            &Schema::Recurse(_) => unreachable!(),
+        }
+    }
+}
+
+struct ValueVisitor<'s, 'p> {
+    schema: &'s Schema,
+    ancestors: &'p Ancestors<'s, 'p>,
+}
+
+impl<'s, 'p> ValueVisitor<'s, 'p> {
+    fn seed(&self, schema: &'s Schema) -> SchemaSeed<'s, 'p> {
+        SchemaSeed {
+            schema,
+            ancestors: Some(self.ancestors),
+        }
+    }
+
+    fn int<E: Error>(self, n: i128, unexp: Unexpected) -> Result<Value, E> {
+        let scalar =
+            match self.schema {
+                &Schema::Scalar(st) => match st {
+                    ScalarType::U8 => n.try_into().ok().map(ScalarValue::U8),
+                    ScalarType::U16 => n.try_into().ok().map(ScalarValue::U16),
+                    ScalarType::U32 => n.try_into().ok().map(ScalarValue::U32),
+                    ScalarType::U64 => n.try_into().ok().map(ScalarValue::U64),
+                    ScalarType::U128 => n.try_into().ok().map(ScalarValue::U128),
+                    ScalarType::I8 => n.try_into().ok().map(ScalarValue::I8),
+                    ScalarType::I16 => n.try_into().ok().map(ScalarValue::I16),
+                    ScalarType::I32 => n.try_into().ok().map(ScalarValue::I32),
+                    ScalarType::I64 => n.try_into().ok().map(ScalarValue::I64),
+                    ScalarType::I128 => Some(ScalarValue::I128(n)),
+                    ScalarType::F32 => Some(ScalarValue::F32(n as f32)),
+                    ScalarType::F64 => Some(ScalarValue::F64(n as f64)),
+                    _ => None,
+                },
+                _ => None,
+            };
+        scalar
+            .map(Value::Scalar)
+            .ok_or_else(|| E::invalid_value(unexp, &self))
+    }
+}
+
+impl<'s, 'p, 'de> Visitor<'de> for ValueVisitor<'s, 'p> {
+    type Value = Value;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        match self.schema {
+            &Schema::Scalar(st) => f.write_str(st.display_str()),
+            schema => f.write_str(schema.non_recursive_display_str()),
+        }
+    }
+
+    fn visit_bool<E: Error>(self, b: bool) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Scalar(ScalarType::Bool) => Ok(Value::Scalar(ScalarValue::Bool(b))),
+            _ => Err(E::invalid_type(Unexpected::Bool(b), &self)),
+        }
+    }
+
+    fn visit_i64<E: Error>(self, n: i64) -> Result<Value, E> {
+        self.int(n as i128, Unexpected::Signed(n))
+    }
+
+    fn visit_i128<E: Error>(self, n: i128) -> Result<Value, E> {
+        self.int(n, Unexpected::Other("i128"))
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<Value, E> {
+        self.int(n as i128, Unexpected::Unsigned(n))
+    }
+
+    fn visit_u128<E: Error>(self, n: u128) -> Result<Value, E> {
+        match i128::try_from(n) {
+            Ok(n) => self.int(n, Unexpected::Other("u128")),
+            Err(_) if self.schema == &Schema::Scalar(ScalarType::U128) => {
+                Ok(Value::Scalar(ScalarValue::U128(n)))
+            }
+            Err(_) => Err(E::invalid_value(Unexpected::Other("u128"), &self)),
+        }
+    }
+
+    fn visit_f32<E: Error>(self, n: f32) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Scalar(ScalarType::F32) => Ok(Value::Scalar(ScalarValue::F32(n))),
+            _ => self.visit_f64(n as f64),
+        }
+    }
+
+    fn visit_f64<E: Error>(self, n: f64) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Scalar(ScalarType::F32) => Ok(Value::Scalar(ScalarValue::F32(n as f32))),
+            &Schema::Scalar(ScalarType::F64) => Ok(Value::Scalar(ScalarValue::F64(n))),
+            _ => Err(E::invalid_type(Unexpected::Float(n), &self)),
+        }
+    }
+
+    fn visit_char<E: Error>(self, c: char) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Scalar(ScalarType::Char) => Ok(Value::Scalar(ScalarValue::Char(c))),
+            &Schema::Str => Ok(Value::Str(c.to_string())),
+            _ => Err(E::invalid_type(Unexpected::Char(c), &self)),
+        }
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<Value, E> {
+        self.visit_string(s.to_owned())
+    }
+
+    fn visit_string<E: Error>(self, s: String) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Str => Ok(Value::Str(s)),
+            &Schema::Scalar(ScalarType::Char) => {
+                let mut chars = s.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Ok(Value::Scalar(ScalarValue::Char(c))),
+                    _ => Err(E::invalid_value(Unexpected::Str(&s), &self)),
+                }
+            }
+            _ => Err(E::invalid_type(Unexpected::Str(&s), &self)),
+        }
+    }
+
+    fn visit_bytes<E: Error>(self, b: &[u8]) -> Result<Value, E> {
+        self.visit_byte_buf(b.to_vec())
+    }
+
+    fn visit_byte_buf<E: Error>(self, b: Vec<u8>) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Bytes => Ok(Value::Bytes(b)),
+            _ => Err(E::invalid_type(Unexpected::Bytes(&b), &self)),
+        }
+    }
+
+    fn visit_unit<E: Error>(self) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Unit => Ok(Value::Unit),
+            &Schema::Option(_) => Ok(Value::Option(None)),
+            _ => Err(E::invalid_type(Unexpected::Unit, &self)),
+        }
+    }
+
+    fn visit_none<E: Error>(self) -> Result<Value, E> {
+        match self.schema {
+            &Schema::Option(_) => Ok(Value::Option(None)),
+            _ => Err(E::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, d: D) -> Result<Value, D::Error> {
+        match self.schema {
+            &Schema::Option(ref inner) => Ok(Value::Option(Some(Box::new(
+                self.seed(inner).deserialize(d)?
+            )))),
+            _ => Err(D::Error::invalid_type(Unexpected::Option, &self)),
+        }
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Value, A::Error> {
+        match self.schema {
+            &Schema::Seq(SeqSchema { len, ref inner }) => {
+                // don't trust size hint for preallocation, it may be malicious
+                let mut elems = Vec::with_capacity(
+                    len.or(seq.size_hint()).unwrap_or(0).min(1024)
+                );
+                while let Some(elem) = seq.next_element_seed(self.seed(inner))? {
+                    elems.push(elem);
+                }
+                match len {
+                    Some(len) if elems.len() != len => {
+                        Err(A::Error::invalid_length(elems.len(), &self))
+                    }
+                    Some(_) => Ok(Value::FixedLenSeq(elems)),
+                    None => Ok(Value::VarLenSeq(elems)),
+                }
+            }
+            &Schema::Tuple(ref inners) => {
+                let mut elems = Vec::with_capacity(inners.len());
+                for inner in inners {
+                    let elem = seq
+                        .next_element_seed(self.seed(inner))?
+                        .ok_or_else(|| A::Error::invalid_length(elems.len(), &self))?;
+                    elems.push(elem);
+                }
+                if seq.next_element::<serde::de::IgnoredAny>()?.is_some() {
+                    return Err(A::Error::invalid_length(inners.len() + 1, &self));
+                }
+                Ok(Value::Tuple(elems))
+            }
+            &Schema::Bytes => {
+                let mut bytes = Vec::new();
+                while let Some(byte) = seq.next_element::<u8>()? {
+                    bytes.push(byte);
+                }
+                Ok(Value::Bytes(bytes))
+            }
+            _ => Err(A::Error::invalid_type(Unexpected::Seq, &self)),
+        }
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Value, A::Error> {
+        match self.schema {
+            &Schema::Struct(ref schema_fields) => {
+                let mut values = schema_fields.iter().map(|_| None).collect::<Vec<_>>();
+                while let Some(name) = map.next_key::<String>()? {
+                    let i = schema_fields
+                        .iter()
+                        .position(|field| field.name == name)
+                        .ok_or_else(|| A::Error::custom(format_args!(
+                            "unknown field {:?}",
+                            name,
+                        )))?;
+                    if values[i].is_some() {
+                        return Err(A::Error::custom(format_args!(
+                            "duplicate field {:?}",
+                            name,
+                        )));
+                    }
+                    values[i] = Some(map.next_value_seed(self.seed(&schema_fields[i].inner))?);
+                }
+                schema_fields
+                    .iter()
+                    .zip(values)
+                    .map(|(field, value)| Ok(StructValueField {
+                        name: field.name.clone(),
+                        value: value.ok_or_else(|| A::Error::custom(format_args!(
+                            "missing field {:?}",
+                            field.name,
+                        )))?,
+                    }))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(Value::Struct)
+            }
+            &Schema::Enum(ref variants) => {
+                let name = map
+                    .next_key::<String>()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &"map with one entry"))?;
+                let variant_ord = variants
+                    .iter()
+                    .position(|variant| variant.name == name)
+                    .ok_or_else(|| A::Error::custom(format_args!(
+                        "unknown variant {:?}",
+                        name,
+                    )))?;
+                let value = map.next_value_seed(self.seed(&variants[variant_ord].inner))?;
+                if map.next_key::<serde::de::IgnoredAny>()?.is_some() {
+                    return Err(A::Error::invalid_length(2, &"map with one entry"));
+                }
+                Ok(Value::Enum(EnumValue {
+                    variant_ord,
+                    variant_name: name,
+                    value: Box::new(value),
+                }))
+            }
+            _ => Err(A::Error::invalid_type(Unexpected::Map, &self)),
+        }
+    }
+
+    fn visit_enum<A: EnumAccess<'de>>(self, data: A) -> Result<Value, A::Error> {
+        let variants =
+            match self.schema {
+                &Schema::Enum(ref variants) => variants,
+                _ => return Err(A::Error::invalid_type(Unexpected::Enum, &self)),
+            };
+        let (variant_ord, access) = data.variant_seed(VariantSeed(variants))?;
+        let variant = &variants[variant_ord];
+        let value = access.newtype_variant_seed(self.seed(&variant.inner))?;
+        Ok(Value::Enum(EnumValue {
+            variant_ord,
+            variant_name: variant.name.clone(),
+            value: Box::new(value),
+        }))
+    }
+}
+
+/// Deserializes an enum variant identifier, by ordinal or name, into its
+/// ordinal.
+struct VariantSeed<'s>(&'s [EnumSchemaVariant]);
+
+impl<'s, 'de> DeserializeSeed<'de> for VariantSeed<'s> {
+    type Value = usize;
+
+    fn deserialize<D: Deserializer<'de>>(self, d: D) -> Result<usize, D::Error> {
+        d.deserialize_identifier(self)
+    }
+}
+
+impl<'s, 'de> Visitor<'de> for VariantSeed<'s> {
+    type Value = usize;
+
+    fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("variant identifier")
+    }
+
+    fn visit_u64<E: Error>(self, n: u64) -> Result<usize, E> {
+        usize::try_from(n)
+            .ok()
+            .filter(|&ord| ord < self.0.len())
+            .ok_or_else(|| E::invalid_value(Unexpected::Unsigned(n), &self))
+    }
+
+    fn visit_str<E: Error>(self, s: &str) -> Result<usize, E> {
+        self.0
+            .iter()
+            .position(|variant| variant.name == s)
+            .ok_or_else(|| E::custom(format_args!("unknown variant {:?}", s)))
+    }
+}
@@ -0,0 +1,42 @@
+//! Computing encoded sizes without writing.
+
+use std::io::{
+    self,
+    Write,
+};
+
+
+/// `std::io::Write` which discards the bytes written to it and just counts
+/// them. Encoding a value into an `Encoder` wrapping this runs all the same
+/// schema checks as encoding it for real, and gives its exact encoded size,
+/// for pre-sizing buffers or writing length prefixes.
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub struct SizeCounter {
+    len: usize,
+}
+
+impl SizeCounter {
+    pub fn new() -> Self {
+        SizeCounter::default()
+    }
+
+    /// Number of bytes written so far.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Write for SizeCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.len += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! Schema-driven skipping over encoded values, without materializing them.
+
+use crate::{
+    error::{
+        Result,
+        Error,
+        ErrorKind,
+        error,
+        bail,
+    },
+    limits::{
+        DecodeLimits,
+        LimitExceeded,
+    },
+    schema::{
+        Schema,
+        ScalarType,
+        SeqSchema,
+    },
+    var_len::{
+        read_var_len_uint,
+        read_var_len_sint,
+        read_ord,
+    },
+};
+use std::io::{
+    self,
+    Read,
+};
+
+
+/// Read and discard one value of `schema` from `read`, validating only as
+/// much as is needed to find its end: option somenesses and enum ordinals are
+/// validated, but bools and chars aren't. `ancestors` is the stack of schemas
+/// above it, with the root first, for resolving recursion. Fails if the value
+/// exceeds `limits`, the same as decoding it would, except for
+/// `max_total_bytes`, which is up to `read` to enforce.
+pub(crate) fn skip_raw<'a>(
+    schema: &'a Schema,
+    ancestors: &mut Vec<&'a Schema>,
+    limits: &DecodeLimits,
+    read: &mut dyn Read,
+) -> Result<()> {
+    let schema = schema
+        .resolve(ancestors)
+        .ok_or_else(|| error!(IllegalSchema, None, "unresolvable recurse"))?;
+    if let Some(size) = schema.fixed_size() {
+        return discard(read, size);
+    }
+    limits.check_depth(ancestors.len() + 1).map_err(limit_exceeded)?;
+    ancestors.push(schema);
+    let result = skip_resolved(schema, ancestors, limits, read);
+    ancestors.pop();
+    result
+}
+
+fn limit_exceeded(limit: LimitExceeded) -> Error {
+    Error::new(ErrorKind::LimitExceeded, limit, None)
+}
+
+fn skip_resolved<'a>(
+    schema: &'a Schema,
+    ancestors: &mut Vec<&'a Schema>,
+    limits: &DecodeLimits,
+    read: &mut dyn Read,
+) -> Result<()> {
+    match schema {
+        &Schema::Scalar(st) => match st {
+            ScalarType::U8 | ScalarType::I8 | ScalarType::Bool => discard(read, 1)?,
+            ScalarType::U16 | ScalarType::I16 => discard(read, 2)?,
+            ScalarType::F32 => discard(read, 4)?,
+            ScalarType::F64 => discard(read, 8)?,
+            ScalarType::U32
+            | ScalarType::U64
+            | ScalarType::U128
+            | ScalarType::Char => {
+                read_var_len_uint(read)?;
+            }
+            ScalarType::I32 | ScalarType::I64 | ScalarType::I128 => {
+                read_var_len_sint(read)?;
+            }
+        },
+        &Schema::Str | &Schema::Bytes => {
+            let len = read_len(read)?;
+            limits.check_str_bytes_len(len).map_err(limit_exceeded)?;
+            discard(read, len)?;
+        }
+        &Schema::Unit => (),
+        &Schema::Option(ref inner) => {
+            let mut buf = [0];
+            read.read_exact(&mut buf)?;
+            match buf[0] {
+                0 => (),
+                1 => skip_raw(inner, ancestors, limits, read)?,
+                n => bail!(
+                    MalformedData,
+                    None,
+                    "{} is not a valid option someness",
+                    n,
+                ),
+            }
+        }
+        &Schema::Seq(SeqSchema { len, ref inner }) => {
+            let len =
+                match len {
+                    Some(len) => len,
+                    None => {
+                        let len = read_len(read)?;
+                        limits.check_seq_len(len).map_err(limit_exceeded)?;
+                        len
+                    }
+                };
+            let inner_size = inner
+                .resolve(ancestors)
+                .and_then(Schema::fixed_size);
+            if let Some(inner_size) = inner_size {
+                // skip fixed-size elements in bulk
+                let size = len
+                    .checked_mul(inner_size)
+                    .ok_or_else(|| error!(
+                        PlatformLimits,
+                        None,
+                        "seq of {} elems of size {} overflows usize",
+                        len,
+                        inner_size,
+                    ))?;
+                discard(read, size)?;
+            } else {
+                for _ in 0..len {
+                    skip_raw(inner, ancestors, limits, read)?;
+                }
+            }
+        }
+        &Schema::Tuple(ref inners) => {
+            for inner in inners {
+                skip_raw(inner, ancestors, limits, read)?;
+            }
+        }
+        &Schema::Struct(ref fields) => {
+            for field in fields {
+                skip_raw(&field.inner, ancestors, limits, read)?;
+            }
+        }
+        &Schema::Enum(ref variants) => {
+            let ord = read_ord(read, variants.len())?;
+            skip_raw(&variants[ord].inner, ancestors, limits, read)?;
+        }
+        &Schema::Recurse(_) => unreachable!(),
+    }
+    Ok(())
+}
+
+impl Schema {
+    /// If every value of this schema is encoded in the same number of bytes,
+    /// that number of bytes. Conservatively returns `None` for anything
+    /// containing recursion.
+    pub(crate) fn fixed_size(&self) -> Option<usize> {
+        match self {
+            &Schema::Scalar(st) => match st {
+                ScalarType::U8 | ScalarType::I8 | ScalarType::Bool => Some(1),
+                ScalarType::U16 | ScalarType::I16 => Some(2),
+                ScalarType::F32 => Some(4),
+                ScalarType::F64 => Some(8),
+                _ => None,
+            },
+            &Schema::Unit => Some(0),
+            &Schema::Seq(SeqSchema { len: Some(len), ref inner }) => inner
+                .fixed_size()
+                .and_then(|size| size.checked_mul(len)),
+            &Schema::Tuple(ref inners) => inners
+                .iter()
+                .try_fold(0usize, |sum, inner| sum.checked_add(inner.fixed_size()?)),
+            &Schema::Struct(ref fields) => fields
+                .iter()
+                .try_fold(0usize, |sum, field| sum.checked_add(field.inner.fixed_size()?)),
+            // with several variants, the ordinal has to be read to check it's
+            // in range, but with one it's encoded in 0 bytes
+            &Schema::Enum(ref variants) if variants.len() == 1 => variants[0].inner.fixed_size(),
+            _ => None,
+        }
+    }
+}
+
+/// Read a varlen-encoded usize.
+pub(crate) fn read_len(read: &mut dyn Read) -> Result<usize> {
+    let n = read_var_len_uint(read)?;
+    usize::try_from(n)
+        .map_err(|_| error!(
+            PlatformLimits,
+            None,
+            "{} out of range for a usize",
+            n,
+        ))
+}
+
+/// Read and discard `len` bytes.
+pub(crate) fn discard(read: &mut dyn Read, len: usize) -> Result<()> {
+    let skipped = io::copy(&mut read.take(len as u64), &mut io::sink())?;
+    if skipped < len as u64 {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+/// Read exactly `len` bytes onto the end of `buf`. Grows `buf` only as bytes
+/// actually arrive, so a bogus len can't cause a huge allocation.
+pub(crate) fn read_exact_onto(
+    read: &mut dyn Read,
+    buf: &mut Vec<u8>,
+    len: usize,
+) -> Result<()> {
+    let start = buf.len();
+    read.take(len as u64).read_to_end(buf)?;
+    if buf.len() - start < len {
+        return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into());
+    }
+    Ok(())
+}
+
+/// Read adapter which appends everything read through it to a buffer.
+pub(crate) struct Tee<'r, 'c> {
+    pub read: &'r mut dyn Read,
+    pub capture: &'c mut Vec<u8>,
+}
+
+impl<'r, 'c> Read for Tee<'r, 'c> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.read.read(buf)?;
+        self.capture.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
@@ -0,0 +1,696 @@
+//! Textual schema language, for writing schemas in config files.
+
+use crate::{
+    schema::{
+        Schema,
+        ScalarType,
+    },
+    named::{
+        NamedSchema,
+        NamedType,
+        NamedSchemaError,
+    },
+};
+use std::{
+    collections::{
+        BTreeMap,
+        HashMap,
+    },
+    fmt::{self, Formatter, Display, Write},
+    str::FromStr,
+};
+
+
+/// Error from parsing a schema text. Lines and columns start at 1.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SchemaParseError {
+    pub line: usize,
+    pub col: usize,
+    pub message: String,
+}
+
+impl Display for SchemaParseError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for SchemaParseError {}
+
+impl Schema {
+    /// Parse a schema text.
+    ///
+    /// A schema text is a sequence of type definitions and exactly one root:
+    ///
+    /// ```text
+    /// # comments run to the end of the line
+    /// root seq(varlen)(Tree)
+    ///
+    /// type Tree = enum {
+    ///     Branch(struct {
+    ///         value: u32,
+    ///         left: Tree,
+    ///         right: Tree,
+    ///     }),
+    ///     Leaf(u32),
+    /// }
+    /// ```
+    ///
+    /// Types are written like in the `schema!` macro:
+    ///
+    /// - `u8` through `u128`, `i8` through `i128`, `f32`, `f64`, `char`, `bool`
+    /// - `str`, `bytes`, `unit`
+    /// - `option(T)`
+    /// - `seq(varlen)(T)`, or `seq(N)(T)` for fixed length `N`
+    /// - `tuple { T, ... }`
+    /// - `struct { name: T, ... }`
+    /// - `enum { Name(T), ... }`
+    /// - `recurse(N)`, for a raw `Schema::Recurse`
+    ///
+    /// A type may also be the name of a defined type, which is substituted in.
+    /// Referring to a type from within its own definition becomes a
+    /// `Schema::Recurse` back up to it, or where a recurse can't reach, the
+    /// definition substituted in again (see `NamedSchema::to_schema`). Type,
+    /// field, and variant names which aren't identifiers can be written as
+    /// double-quoted strings.
+    ///
+    /// `to_text` prints a canonical form, in which types which are recursed
+    /// to are defined with generated names `T1`, `T2`, and so on.
+    pub fn parse_text(src: &str) -> Result<Schema, SchemaParseError> {
+        let (named, positions) = parse(src)?;
+        named.to_schema().map_err(|e| {
+            let (line, col) =
+                match &e {
+                    &NamedSchemaError::UndefinedRef(ref name) => positions.refs[name],
+                    &NamedSchemaError::RefWithoutNesting(ref name) => positions.defs[name],
+                    &NamedSchemaError::Inexpressible(ref name) => positions.defs[name],
+                };
+            SchemaParseError {
+                line,
+                col,
+                message: e.to_string(),
+            }
+        })
+    }
+
+    /// Print as a canonical schema text, which `parse_text` parses back to
+    /// an equal schema.
+    pub fn to_text(&self) -> String {
+        self.to_named().to_text()
+    }
+}
+
+impl FromStr for Schema {
+    type Err = SchemaParseError;
+
+    fn from_str(src: &str) -> Result<Self, Self::Err> {
+        Schema::parse_text(src)
+    }
+}
+
+impl NamedSchema {
+    /// Parse a schema text, as described in `Schema::parse_text`, keeping
+    /// the definitions as they're written. Errors if a type is referred to
+    /// but not defined.
+    pub fn parse_text(src: &str) -> Result<NamedSchema, SchemaParseError> {
+        parse(src).map(|(named, _)| named)
+    }
+
+    /// Print as a schema text, with the root followed by the definitions in
+    /// name order.
+    pub fn to_text(&self) -> String {
+        let mut out = String::new();
+        out.push_str("root ");
+        print(&self.root, 0, &mut out);
+        out.push('\n');
+        for (name, def) in &self.defs {
+            out.push_str("\ntype ");
+            write_type_name(&mut out, name);
+            out.push_str(" = ");
+            print(def, 0, &mut out);
+            out.push('\n');
+        }
+        out
+    }
+}
+
+/// Positions in the source of definitions, and of the first ref to each name.
+struct Positions {
+    defs: HashMap<String, (usize, usize)>,
+    refs: HashMap<String, (usize, usize)>,
+}
+
+fn parse(src: &str) -> Result<(NamedSchema, Positions), SchemaParseError> {
+    let tokens = lex(src)?;
+    let mut parser = Parser {
+        tokens: &tokens,
+        i: 0,
+        depth: 0,
+        positions: Positions {
+            defs: HashMap::new(),
+            refs: HashMap::new(),
+        },
+    };
+    let named = parser.parse_file()?;
+    let positions = parser.positions;
+
+    let mut undefined = positions.refs
+        .iter()
+        .filter(|&(name, _)| !named.defs.contains_key(name))
+        .map(|(name, &pos)| (pos, name))
+        .collect::<Vec<_>>();
+    undefined.sort();
+    if let Some(&((line, col), name)) = undefined.first() {
+        return err(line, col, NamedSchemaError::UndefinedRef(name.clone()).to_string());
+    }
+    Ok((named, positions))
+}
+
+
+// ==== lexing ====
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Int(usize),
+    Punct(char),
+    Eof,
+}
+
+impl Display for Tok {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &Tok::Ident(ref s) => write!(f, "`{}`", s),
+            &Tok::Str(ref s) => write!(f, "{:?}", s),
+            &Tok::Int(n) => write!(f, "`{}`", n),
+            &Tok::Punct(c) => write!(f, "`{}`", c),
+            &Tok::Eof => f.write_str("end of input"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    line: usize,
+    col: usize,
+}
+
+fn err<T>(line: usize, col: usize, message: String) -> Result<T, SchemaParseError> {
+    Err(SchemaParseError { line, col, message })
+}
+
+fn lex(src: &str) -> Result<Vec<Token>, SchemaParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = src.chars().peekable();
+    let mut line = 1;
+    let mut col = 1;
+
+    macro_rules! bump {
+        ()=>{{
+            let c = chars.next();
+            if c == Some('\n') {
+                line += 1;
+                col = 1;
+            } else if c.is_some() {
+                col += 1;
+            }
+            c
+        }};
+    }
+
+    while let Some(&c) = chars.peek() {
+        let (start_line, start_col) = (line, col);
+        let tok =
+            if c.is_whitespace() {
+                bump!();
+                continue;
+            } else if c == '#' {
+                while !matches!(chars.peek(), None | Some('\n')) {
+                    bump!();
+                }
+                continue;
+            } else if c.is_ascii_alphabetic() || c == '_' {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' {
+                        s.push(c);
+                        bump!();
+                    } else {
+                        break;
+                    }
+                }
+                Tok::Ident(s)
+            } else if c.is_ascii_digit() {
+                let mut s = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_digit() {
+                        s.push(c);
+                        bump!();
+                    } else {
+                        break;
+                    }
+                }
+                match s.parse() {
+                    Ok(n) => Tok::Int(n),
+                    Err(_) => return err(
+                        start_line,
+                        start_col,
+                        format!("integer {} too large", s),
+                    ),
+                }
+            } else if c == '"' {
+                bump!();
+                let mut s = String::new();
+                loop {
+                    let (esc_line, esc_col) = (line, col);
+                    match bump!() {
+                        None => return err(
+                            start_line,
+                            start_col,
+                            "unterminated string".into(),
+                        ),
+                        Some('"') => break,
+                        Some('\\') => match bump!() {
+                            Some('"') => s.push('"'),
+                            Some('\'') => s.push('\''),
+                            Some('\\') => s.push('\\'),
+                            Some('n') => s.push('\n'),
+                            Some('r') => s.push('\r'),
+                            Some('t') => s.push('\t'),
+                            Some('0') => s.push('\0'),
+                            Some('u') => {
+                                let mut hex = String::new();
+                                if bump!() != Some('{') {
+                                    return err(
+                                        esc_line,
+                                        esc_col,
+                                        "expected `{` in unicode escape".into(),
+                                    );
+                                }
+                                loop {
+                                    match bump!() {
+                                        Some('}') => break,
+                                        Some(c) if c.is_ascii_hexdigit() => hex.push(c),
+                                        _ => return err(
+                                            esc_line,
+                                            esc_col,
+                                            "malformed unicode escape".into(),
+                                        ),
+                                    }
+                                }
+                                match u32::from_str_radix(&hex, 16)
+                                    .ok()
+                                    .and_then(char::from_u32)
+                                {
+                                    Some(c) => s.push(c),
+                                    None => return err(
+                                        esc_line,
+                                        esc_col,
+                                        format!("invalid unicode escape {:?}", hex),
+                                    ),
+                                }
+                            }
+                            _ => return err(
+                                esc_line,
+                                esc_col,
+                                "unknown escape".into(),
+                            ),
+                        },
+                        Some(c) => s.push(c),
+                    }
+                }
+                Tok::Str(s)
+            } else if "{}(),:=".contains(c) {
+                bump!();
+                Tok::Punct(c)
+            } else {
+                return err(
+                    start_line,
+                    start_col,
+                    format!("unexpected character {:?}", c),
+                );
+            };
+        tokens.push(Token {
+            tok,
+            line: start_line,
+            col: start_col,
+        });
+    }
+    tokens.push(Token {
+        tok: Tok::Eof,
+        line,
+        col,
+    });
+    Ok(tokens)
+}
+
+
+// ==== parsing ====
+
+const KEYWORDS: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128",
+    "i8", "i16", "i32", "i64", "i128",
+    "f32", "f64", "char", "bool",
+    "str", "bytes", "unit",
+    "option", "seq", "varlen", "tuple", "struct", "enum", "recurse",
+    "type", "root",
+];
+
+/// Max nesting depth of types, so that parsing a malicious text fails rather
+/// than overflowing the stack.
+const MAX_DEPTH: usize = 256;
+
+fn scalar_type(ident: &str) -> Option<ScalarType> {
+    Some(match ident {
+        "u8" => ScalarType::U8,
+        "u16" => ScalarType::U16,
+        "u32" => ScalarType::U32,
+        "u64" => ScalarType::U64,
+        "u128" => ScalarType::U128,
+        "i8" => ScalarType::I8,
+        "i16" => ScalarType::I16,
+        "i32" => ScalarType::I32,
+        "i64" => ScalarType::I64,
+        "i128" => ScalarType::I128,
+        "f32" => ScalarType::F32,
+        "f64" => ScalarType::F64,
+        "char" => ScalarType::Char,
+        "bool" => ScalarType::Bool,
+        _ => return None,
+    })
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    i: usize,
+    /// Number of types being parsed which the next type is nested within.
+    depth: usize,
+    positions: Positions,
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> &'t Token {
+        &self.tokens[self.i]
+    }
+
+    fn next(&mut self) -> &'t Token {
+        let token = &self.tokens[self.i];
+        if self.i + 1 < self.tokens.len() {
+            self.i += 1;
+        }
+        token
+    }
+
+    fn unexpected<T>(&self, token: &Token, expected: &str) -> Result<T, SchemaParseError> {
+        err(
+            token.line,
+            token.col,
+            format!("expected {}, found {}", expected, token.tok),
+        )
+    }
+
+    fn expect_punct(&mut self, c: char) -> Result<(), SchemaParseError> {
+        let token = self.next();
+        if token.tok == Tok::Punct(c) {
+            Ok(())
+        } else {
+            self.unexpected(token, &format!("`{}`", c))
+        }
+    }
+
+    fn eat_punct(&mut self, c: char) -> bool {
+        if self.peek().tok == Tok::Punct(c) {
+            self.next();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_file(&mut self) -> Result<NamedSchema, SchemaParseError> {
+        let mut defs = BTreeMap::new();
+        let mut root = None;
+        loop {
+            let token = self.next();
+            match &token.tok {
+                &Tok::Ident(ref kw) if kw == "type" => {
+                    let name_token = self.next();
+                    let name =
+                        match &name_token.tok {
+                            &Tok::Ident(ref name) if !KEYWORDS.contains(&&name[..]) => name,
+                            &Tok::Ident(ref name) => return err(
+                                name_token.line,
+                                name_token.col,
+                                format!("cannot define type with reserved name `{}`", name),
+                            ),
+                            &Tok::Str(ref name) => name,
+                            _ => return self.unexpected(name_token, "type name"),
+                        };
+                    self.expect_punct('=')?;
+                    let def = self.parse_type()?;
+                    if defs.insert(name.clone(), def).is_some() {
+                        return err(
+                            name_token.line,
+                            name_token.col,
+                            format!("type `{}` defined more than once", name),
+                        );
+                    }
+                    self.positions.defs.insert(name.clone(), (name_token.line, name_token.col));
+                }
+                &Tok::Ident(ref kw) if kw == "root" => {
+                    if root.is_some() {
+                        return err(token.line, token.col, "multiple roots".into());
+                    }
+                    root = Some(self.parse_type()?);
+                }
+                &Tok::Eof => break,
+                _ => return self.unexpected(token, "`type` or `root`"),
+            }
+        }
+        match root {
+            Some(root) => Ok(NamedSchema { defs, root }),
+            None => {
+                let token = self.peek();
+                err(token.line, token.col, "missing `root`".into())
+            }
+        }
+    }
+
+    fn parse_int(&mut self) -> Result<usize, SchemaParseError> {
+        let token = self.next();
+        match token.tok {
+            Tok::Int(n) => Ok(n),
+            _ => self.unexpected(token, "integer"),
+        }
+    }
+
+    fn parse_name(&mut self) -> Result<String, SchemaParseError> {
+        let token = self.next();
+        match &token.tok {
+            &Tok::Ident(ref s) | &Tok::Str(ref s) => Ok(s.clone()),
+            _ => self.unexpected(token, "name"),
+        }
+    }
+
+    /// Parse comma separated items within braces.
+    fn parse_braced<T, F>(&mut self, mut item: F) -> Result<Vec<T>, SchemaParseError>
+    where
+        F: FnMut(&mut Self) -> Result<T, SchemaParseError>,
+    {
+        self.expect_punct('{')?;
+        let mut items = Vec::new();
+        while !self.eat_punct('}') {
+            items.push(item(self)?);
+            if !self.eat_punct(',') {
+                self.expect_punct('}')?;
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Parse a ref to the type with this name.
+    fn parse_ref(&mut self, name: &str, token: &Token) -> NamedType {
+        self.positions.refs
+            .entry(name.to_owned())
+            .or_insert((token.line, token.col));
+        NamedType::Ref(name.to_owned())
+    }
+
+    fn parse_type(&mut self) -> Result<NamedType, SchemaParseError> {
+        if self.depth >= MAX_DEPTH {
+            let token = self.peek();
+            return err(
+                token.line,
+                token.col,
+                format!("types nested deeper than {}", MAX_DEPTH),
+            );
+        }
+        self.depth += 1;
+        let result = self.parse_type_inner();
+        self.depth -= 1;
+        result
+    }
+
+    fn parse_type_inner(&mut self) -> Result<NamedType, SchemaParseError> {
+        let token = self.next();
+        let ident =
+            match &token.tok {
+                &Tok::Ident(ref ident) => ident,
+                &Tok::Str(ref name) => return Ok(self.parse_ref(name, token)),
+                _ => return self.unexpected(token, "type"),
+            };
+        if let Some(scalar_type) = scalar_type(ident) {
+            return Ok(NamedType::Scalar(scalar_type));
+        }
+        Ok(match &ident[..] {
+            "str" => NamedType::Str,
+            "bytes" => NamedType::Bytes,
+            "unit" => NamedType::Unit,
+            "option" => {
+                self.expect_punct('(')?;
+                let inner = self.parse_type()?;
+                self.expect_punct(')')?;
+                NamedType::Option(Box::new(inner))
+            }
+            "seq" => {
+                self.expect_punct('(')?;
+                let len =
+                    if self.peek().tok == Tok::Ident("varlen".into()) {
+                        self.next();
+                        None
+                    } else {
+                        Some(self.parse_int()?)
+                    };
+                self.expect_punct(')')?;
+                self.expect_punct('(')?;
+                let inner = self.parse_type()?;
+                self.expect_punct(')')?;
+                NamedType::Seq {
+                    len,
+                    inner: Box::new(inner),
+                }
+            }
+            "tuple" => NamedType::Tuple(self.parse_braced(Self::parse_type)?),
+            "struct" => NamedType::Struct(self.parse_braced(|p| {
+                let name = p.parse_name()?;
+                p.expect_punct(':')?;
+                Ok((name, p.parse_type()?))
+            })?),
+            "enum" => NamedType::Enum(self.parse_braced(|p| {
+                let name = p.parse_name()?;
+                p.expect_punct('(')?;
+                let inner = p.parse_type()?;
+                p.expect_punct(')')?;
+                Ok((name, inner))
+            })?),
+            "recurse" => {
+                self.expect_punct('(')?;
+                let n = self.parse_int()?;
+                self.expect_punct(')')?;
+                NamedType::Recurse(n)
+            }
+            _ if KEYWORDS.contains(&&ident[..]) => {
+                return self.unexpected(token, "type");
+            }
+            _ => self.parse_ref(ident, token),
+        })
+    }
+}
+
+
+// ==== printing ====
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn write_name(out: &mut String, name: &str) {
+    if is_ident(name) {
+        out.push_str(name);
+    } else {
+        write!(out, "{:?}", name).unwrap();
+    }
+}
+
+/// Like `write_name`, but also quotes keywords, which would otherwise parse
+/// as something other than a type name.
+fn write_type_name(out: &mut String, name: &str) {
+    if is_ident(name) && !KEYWORDS.contains(&name) {
+        out.push_str(name);
+    } else {
+        write!(out, "{:?}", name).unwrap();
+    }
+}
+
+fn print(named_type: &NamedType, indent: usize, out: &mut String) {
+    macro_rules! items {
+        ($items:expr, |$item:pat_param| $print_item:expr)=>{{
+            if $items.is_empty() {
+                out.push_str(" {}");
+            } else {
+                out.push_str(" {\n");
+                for $item in $items {
+                    push_indent(out, indent + 1);
+                    $print_item;
+                    out.push_str(",\n");
+                }
+                push_indent(out, indent);
+                out.push('}');
+            }
+        }};
+    }
+
+    match named_type {
+        &NamedType::Scalar(scalar_type) => out.push_str(scalar_type.display_str()),
+        &NamedType::Str => out.push_str("str"),
+        &NamedType::Bytes => out.push_str("bytes"),
+        &NamedType::Unit => out.push_str("unit"),
+        &NamedType::Option(ref inner) => {
+            out.push_str("option(");
+            print(inner, indent, out);
+            out.push(')');
+        }
+        &NamedType::Seq { len, ref inner } => {
+            match len {
+                Some(len) => write!(out, "seq({})(", len).unwrap(),
+                None => out.push_str("seq(varlen)("),
+            }
+            print(inner, indent, out);
+            out.push(')');
+        }
+        &NamedType::Tuple(ref inners) => {
+            out.push_str("tuple");
+            items!(inners, |inner| print(inner, indent + 1, out));
+        }
+        &NamedType::Struct(ref fields) => {
+            out.push_str("struct");
+            items!(fields, |&(ref name, ref inner)| {
+                write_name(out, name);
+                out.push_str(": ");
+                print(inner, indent + 1, out)
+            });
+        }
+        &NamedType::Enum(ref variants) => {
+            out.push_str("enum");
+            items!(variants, |&(ref name, ref inner)| {
+                write_name(out, name);
+                out.push('(');
+                print(inner, indent + 1, out);
+                out.push(')')
+            });
+        }
+        &NamedType::Recurse(level) => write!(out, "recurse({})", level).unwrap(),
+        &NamedType::Ref(ref name) => write_type_name(out, name),
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    for _ in 0..indent {
+        out.push_str("    ");
+    }
+}
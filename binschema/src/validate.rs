@@ -0,0 +1,360 @@
+//! Up-front validation of schemas, so that illegal schemas can be rejected
+//! before any coding starts.
+
+use crate::{
+    schema::{
+        Schema,
+        SeqSchema,
+    },
+    path::{
+        Path,
+        PathSegment,
+    },
+};
+use std::{
+    collections::{
+        HashMap,
+        HashSet,
+    },
+    fmt::{self, Formatter, Display},
+};
+
+
+/// Problem found with a schema by `Schema::validate`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct SchemaIssue {
+    /// Path to the problematic node within the schema.
+    pub path: Path,
+    pub kind: SchemaIssueKind,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum SchemaIssueKind {
+    /// `Recurse(0)`, which would resolve to itself forever.
+    ZeroLevelRecurse,
+    /// Recurse more levels up than the recurse has ancestors.
+    RecursePastRoot {
+        level: usize,
+        depth: usize,
+    },
+    /// Struct with multiple fields of the same name.
+    DuplicateFieldName(String),
+    /// Enum with multiple variants of the same name.
+    DuplicateVariantName(String),
+    /// Enum with no variants, which cannot be coded.
+    UninhabitedEnum,
+    /// Recurse to a schema which has no finite values, because every value
+    /// of it would have to contain another value of it. For example, a
+    /// struct with a field that recurses to that struct.
+    InfiniteRecursion,
+}
+
+impl Display for SchemaIssueKind {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &SchemaIssueKind::ZeroLevelRecurse => f.write_str("recurse of level 0"),
+            &SchemaIssueKind::RecursePastRoot { level, depth } => write!(
+                f,
+                "recurse of level {} past root, at depth {}",
+                level,
+                depth,
+            ),
+            &SchemaIssueKind::DuplicateFieldName(ref name) => write!(
+                f,
+                "duplicate struct field name {:?}",
+                name,
+            ),
+            &SchemaIssueKind::DuplicateVariantName(ref name) => write!(
+                f,
+                "duplicate enum variant name {:?}",
+                name,
+            ),
+            &SchemaIssueKind::UninhabitedEnum => f.write_str("enum with no variants"),
+            &SchemaIssueKind::InfiniteRecursion => f.write_str(
+                "recursion with no base case",
+            ),
+        }
+    }
+}
+
+impl Display for SchemaIssue {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.kind)
+    }
+}
+
+impl std::error::Error for SchemaIssue {}
+
+impl Schema {
+    /// Walk the schema once, and report every reason it is illegal or
+    /// useless. A schema which validates successfully will never cause a
+    /// `ErrorKind::IllegalSchema` error.
+    pub fn validate(&self) -> Result<(), Vec<SchemaIssue>> {
+        let mut walk = Walk {
+            nodes: Vec::new(),
+            issues: Vec::new(),
+            max_level: 0,
+        };
+        walk.walk(self, &mut Path::new(), 0);
+        let Walk { nodes, mut issues, max_level } = walk;
+
+        for recurse in find_infinite_recursion(&nodes, max_level) {
+            issues.push(SchemaIssue {
+                path: nodes[recurse].path.clone(),
+                kind: SchemaIssueKind::InfiniteRecursion,
+            });
+        }
+
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(issues)
+        }
+    }
+}
+
+/// Node of the schema tree, flattened.
+struct Node {
+    path: Path,
+    rule: Rule,
+    /// Child node indices.
+    children: Vec<usize>,
+}
+
+/// How whether a node has any finite values is determined.
+#[derive(Copy, Clone)]
+enum Rule {
+    /// Always has finite values. Also used for nodes which are already
+    /// reported as illegal some other way, to avoid cascading reports.
+    Always,
+    /// Has finite values if all children do.
+    All,
+    /// Has finite values if any child does.
+    Any,
+    /// Valid recurse to the given level.
+    Recurse(usize),
+}
+
+struct Walk {
+    nodes: Vec<Node>,
+    issues: Vec<SchemaIssue>,
+    max_level: usize,
+}
+
+impl Walk {
+    fn issue(&mut self, path: &Path, kind: SchemaIssueKind) {
+        self.issues.push(SchemaIssue {
+            path: path.clone(),
+            kind,
+        });
+    }
+
+    /// Flatten `schema` into `self.nodes`, returning its index and reporting
+    /// issues detectable without resolving recursion.
+    fn walk(
+        &mut self,
+        schema: &Schema,
+        path: &mut Path,
+        depth: usize,
+    ) -> usize {
+        let idx = self.nodes.len();
+        let rule =
+            match schema {
+                &Schema::Scalar(_)
+                | &Schema::Str
+                | &Schema::Bytes
+                | &Schema::Unit
+                | &Schema::Option(_)
+                | &Schema::Seq(SeqSchema { len: None, .. })
+                | &Schema::Seq(SeqSchema { len: Some(0), .. }) => Rule::Always,
+                &Schema::Seq(_) | &Schema::Tuple(_) | &Schema::Struct(_) => Rule::All,
+                &Schema::Enum(ref variants) => {
+                    if variants.is_empty() {
+                        self.issue(path, SchemaIssueKind::UninhabitedEnum);
+                        Rule::Always
+                    } else {
+                        Rule::Any
+                    }
+                }
+                &Schema::Recurse(0) => {
+                    self.issue(path, SchemaIssueKind::ZeroLevelRecurse);
+                    Rule::Always
+                }
+                &Schema::Recurse(level) => {
+                    if level > depth {
+                        self.issue(path, SchemaIssueKind::RecursePastRoot {
+                            level,
+                            depth,
+                        });
+                        Rule::Always
+                    } else {
+                        self.max_level = usize::max(self.max_level, level);
+                        Rule::Recurse(level)
+                    }
+                }
+            };
+        self.nodes.push(Node {
+            path: path.clone(),
+            rule,
+            children: Vec::new(),
+        });
+
+        let mut children = Vec::new();
+        match schema {
+            &Schema::Option(ref inner) => {
+                path.push(PathSegment::Some);
+                children.push(self.walk(inner, path, depth + 1));
+                path.pop();
+            }
+            &Schema::Seq(SeqSchema { ref inner, .. }) => {
+                path.push(PathSegment::SeqElem(None));
+                children.push(self.walk(inner, path, depth + 1));
+                path.pop();
+            }
+            &Schema::Tuple(ref inners) => {
+                for (i, inner) in inners.iter().enumerate() {
+                    path.push(PathSegment::TupleElem(i));
+                    children.push(self.walk(inner, path, depth + 1));
+                    path.pop();
+                }
+            }
+            &Schema::Struct(ref fields) => {
+                let mut names = HashSet::new();
+                for field in fields {
+                    if !names.insert(&field.name) {
+                        self.issue(
+                            path,
+                            SchemaIssueKind::DuplicateFieldName(field.name.clone()),
+                        );
+                    }
+                    path.push(PathSegment::Field(field.name.clone()));
+                    children.push(self.walk(&field.inner, path, depth + 1));
+                    path.pop();
+                }
+            }
+            &Schema::Enum(ref variants) => {
+                let mut names = HashSet::new();
+                for variant in variants {
+                    if !names.insert(&variant.name) {
+                        self.issue(
+                            path,
+                            SchemaIssueKind::DuplicateVariantName(variant.name.clone()),
+                        );
+                    }
+                    path.push(PathSegment::Variant(variant.name.clone()));
+                    children.push(self.walk(&variant.inner, path, depth + 1));
+                    path.pop();
+                }
+            }
+            _ => (),
+        }
+
+        self.nodes[idx].children = children;
+        idx
+    }
+}
+
+/// Find the indices of recurse nodes which recurse to something with no
+/// finite values.
+///
+/// A recurse resolves relative to the stack of schemas being coded, not
+/// relative to the tree, so what a node means can depend on how it was
+/// reached. Thus, this explores states of (node, last `max_level` ancestors
+/// on the coding stack), which is finite, and then computes a least fixed
+/// point of which states have finite values.
+fn find_infinite_recursion(nodes: &[Node], max_level: usize) -> Vec<usize> {
+    if max_level == 0 {
+        return Vec::new();
+    }
+
+    // explore states
+    struct State {
+        node: usize,
+        /// State indices of children, or of the recurse target.
+        children: Vec<usize>,
+    }
+    let mut states: Vec<State> = Vec::new();
+    let mut state_idxs: HashMap<(usize, Vec<usize>), usize> = HashMap::new();
+    let mut to_explore = Vec::new();
+
+    let mut get_state =
+        |node: usize,
+         mut ctx: Vec<usize>,
+         states: &mut Vec<State>,
+         to_explore: &mut Vec<(usize, Vec<usize>)>| -> usize
+        {
+            if ctx.len() > max_level {
+                ctx.drain(..ctx.len() - max_level);
+            }
+            *state_idxs
+                .entry((node, ctx.clone()))
+                .or_insert_with(|| {
+                    states.push(State { node, children: Vec::new() });
+                    to_explore.push((states.len() - 1, ctx));
+                    states.len() - 1
+                })
+        };
+
+    get_state(0, Vec::new(), &mut states, &mut to_explore);
+    while let Some((state, ctx)) = to_explore.pop() {
+        let node = states[state].node;
+        let mut children = Vec::new();
+        if let Rule::Recurse(level) = nodes[node].rule {
+            // ctx is the coding stack above this recurse node, and the target
+            // gets pushed on top of it, so the target's context is all of ctx.
+            // ctx is only cut down to max_level, so it always reaches back to
+            // the target, but an underflow would just mean an illegal recurse
+            // the walk already reported, so gets no children.
+            if let Some(target_pos) = ctx.len().checked_sub(level) {
+                let target = ctx[target_pos];
+                children.push(get_state(target, ctx, &mut states, &mut to_explore));
+            }
+        } else {
+            let mut child_ctx = ctx;
+            child_ctx.push(node);
+            for &child in &nodes[node].children {
+                children.push(get_state(
+                    child,
+                    child_ctx.clone(),
+                    &mut states,
+                    &mut to_explore,
+                ));
+            }
+        }
+        states[state].children = children;
+    }
+
+    // least fixed point
+    let mut finite = vec![false; states.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (i, state) in states.iter().enumerate() {
+            if finite[i] {
+                continue;
+            }
+            let is_finite =
+                match nodes[state.node].rule {
+                    Rule::Always => true,
+                    Rule::All => state.children.iter().all(|&c| finite[c]),
+                    Rule::Any => state.children.iter().any(|&c| finite[c]),
+                    Rule::Recurse(_) => state.children.iter().all(|&c| finite[c]),
+                };
+            if is_finite {
+                finite[i] = true;
+                changed = true;
+            }
+        }
+    }
+
+    let mut recurses = states.iter()
+        .enumerate()
+        .filter(|&(i, state)|
+            matches!(nodes[state.node].rule, Rule::Recurse(_)) && !finite[i]
+        )
+        .map(|(_, state)| state.node)
+        .collect::<Vec<_>>();
+    recurses.sort();
+    recurses.dedup();
+    recurses
+}
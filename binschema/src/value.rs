@@ -2,7 +2,10 @@
 //! to `serde_json::Value`.
 
 use crate::{
-    error::Result,
+    error::{
+        Result,
+        error,
+    },
     schema::{
         Schema,
         ScalarType,
@@ -10,13 +13,45 @@ use crate::{
     },
     Encoder,
     Decoder,
+    CoderState,
+    CoderStateAlloc,
+    SizeCounter,
+};
+use std::{
+    fmt::{self, Formatter, Display},
+    io::{
+        Write,
+        Read,
+    },
+};
+#[cfg(feature = "tokio")]
+use crate::{
+    AsyncEncoder,
+    AsyncDecoder,
+};
+#[cfg(feature = "tokio")]
+use std::{
+    future::Future,
+    pin::Pin,
 };
-use std::io::{
-    Write,
-    Read,
+#[cfg(feature = "tokio")]
+use tokio::io::{
+    AsyncWrite,
+    AsyncRead,
 };
 
+pub use crate::{
+    serde::value::ValueSeed,
+    check::{
+        Mismatch,
+        MismatchKind,
+    },
+};
 
+
+/// Any value in the data model. Implements `serde::Serialize`, and can be
+/// deserialized given its schema with `ValueSeed`. `Value::check` checks it
+/// against a schema without encoding it.
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum Value {
     Scalar(ScalarValue),
@@ -109,6 +144,16 @@ impl Value {
         }
     }
 
+    /// Number of bytes this would take to encode with the given schema, or
+    /// the error that encoding it would fail with.
+    pub fn encoded_len(&self, schema: &Schema) -> Result<usize> {
+        let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+        let mut counter = SizeCounter::new();
+        self.encode_to(&mut Encoder::new(&mut coder, &mut counter))?;
+        coder.is_finished_or_err()?;
+        Ok(counter.len())
+    }
+
     pub fn decode_from<R: Read>(d: &mut Decoder<R>) -> Result<Self> {
         Ok(match d.need()? {
             &Schema::Scalar(scalar_type) =>
@@ -145,7 +190,8 @@ impl Value {
                 inner: _,
             }) => {
                 let len = d.begin_var_len_seq()?;
-                let mut elems = Vec::with_capacity(len);
+                // don't trust len for preallocation, it may be malicious
+                let mut elems = Vec::with_capacity(usize::min(len, 1024));
                 for _ in 0..len {
                     d.begin_seq_elem()?;
                     elems.push(Value::decode_from(d)?);
@@ -178,8 +224,15 @@ impl Value {
             }
             &Schema::Enum(ref variants) => {
                 let variant_ord = d.begin_enum()?;
-                // TODO: avoid panicking
-                let variant = &variants[variant_ord];
+                let variant = variants
+                    .get(variant_ord)
+                    .ok_or_else(|| error!(
+                        MalformedData,
+                        Some(d.coder_state()),
+                        "variant ordinal {} out of range for {} variants",
+                        variant_ord,
+                        variants.len(),
+                    ))?;
                 d.begin_enum_variant(&variant.name)?;
                 let inner = Value::decode_from(d)?;
                 Value::Enum(EnumValue {
@@ -193,7 +246,231 @@ impl Value {
     }
 }
 
+#[cfg(feature = "tokio")]
+impl Value {
+    /// Async version of `encode_to`.
+    pub fn encode_to_async<'f, W>(
+        &'f self,
+        e: &'f mut AsyncEncoder<'_, '_, W>,
+    ) -> Pin<Box<dyn Future<Output = Result<()>> + 'f>>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        Box::pin(async move {
+            match self {
+                &Value::Scalar(s) => s.encode_to_async(e).await,
+                &Value::Str(ref s) => e.encode_str(s).await,
+                &Value::Bytes(ref b) => e.encode_bytes(b).await,
+                &Value::Unit => e.encode_unit(),
+                &Value::Option(None) => e.encode_none().await,
+                &Value::Option(Some(ref value)) => {
+                    e.begin_some().await?;
+                    value.encode_to_async(e).await
+                }
+                &Value::FixedLenSeq(ref elems) => {
+                    e.begin_fixed_len_seq(elems.len())?;
+                    for elem in elems {
+                        e.begin_seq_elem()?;
+                        elem.encode_to_async(e).await?;
+                    }
+                    e.finish_seq()
+                }
+                &Value::VarLenSeq(ref elems) => {
+                    e.begin_var_len_seq(elems.len()).await?;
+                    for elem in elems {
+                        e.begin_seq_elem()?;
+                        elem.encode_to_async(e).await?;
+                    }
+                    e.finish_seq()
+                }
+                &Value::Tuple(ref elems) => {
+                    e.begin_tuple()?;
+                    for elem in elems {
+                        e.begin_tuple_elem()?;
+                        elem.encode_to_async(e).await?;
+                    }
+                    e.finish_tuple()
+                }
+                &Value::Struct(ref fields) => {
+                    e.begin_struct()?;
+                    for field in fields {
+                        e.begin_struct_field(&field.name)?;
+                        field.value.encode_to_async(e).await?;
+                    }
+                    e.finish_struct()
+                }
+                &Value::Enum(EnumValue {
+                    variant_ord,
+                    ref variant_name,
+                    ref value,
+                }) => {
+                    e.begin_enum(variant_ord, variant_name).await?;
+                    value.encode_to_async(e).await
+                }
+            }
+        })
+    }
+
+    /// Async version of `decode_from`.
+    pub fn decode_from_async<'f, 'a: 'f, R>(
+        d: &'f mut AsyncDecoder<'a, '_, R>,
+    ) -> Pin<Box<dyn Future<Output = Result<Self>> + 'f>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        Box::pin(async move {
+            Ok(match d.need()? {
+                &Schema::Scalar(scalar_type) => Value::Scalar(
+                    ScalarValue::decode_from_async(d, scalar_type).await?
+                ),
+                &Schema::Str => Value::Str(d.decode_str().await?),
+                &Schema::Bytes => Value::Bytes(d.decode_bytes().await?),
+                &Schema::Unit => {
+                    d.decode_unit()?;
+                    Value::Unit
+                }
+                &Schema::Option(_) => {
+                    if d.begin_option().await? {
+                        let inner = Value::decode_from_async(d).await?;
+                        Value::Option(Some(Box::new(inner)))
+                    } else {
+                        Value::Option(None)
+                    }
+                }
+                &Schema::Seq(SeqSchema {
+                    len: Some(len),
+                    inner: _,
+                }) => {
+                    d.begin_fixed_len_seq(len)?;
+                    let mut elems = Vec::with_capacity(len);
+                    for _ in 0..len {
+                        d.begin_seq_elem()?;
+                        elems.push(Value::decode_from_async(d).await?);
+                    }
+                    d.finish_seq()?;
+                    Value::FixedLenSeq(elems)
+                }
+                &Schema::Seq(SeqSchema {
+                    len: None,
+                    inner: _,
+                }) => {
+                    let len = d.begin_var_len_seq().await?;
+                    // don't trust len for preallocation, it may be malicious
+                    let mut elems = Vec::with_capacity(usize::min(len, 1024));
+                    for _ in 0..len {
+                        d.begin_seq_elem()?;
+                        elems.push(Value::decode_from_async(d).await?);
+                    }
+                    d.finish_seq()?;
+                    Value::VarLenSeq(elems)
+                }
+                &Schema::Tuple(ref inner_schemas) => {
+                    d.begin_tuple()?;
+                    let mut elems = Vec::with_capacity(inner_schemas.len());
+                    for _ in 0..inner_schemas.len() {
+                        d.begin_tuple_elem()?;
+                        elems.push(Value::decode_from_async(d).await?);
+                    }
+                    d.finish_tuple()?;
+                    Value::Tuple(elems)
+                }
+                &Schema::Struct(ref schema_fields) => {
+                    d.begin_struct()?;
+                    let mut fields = Vec::with_capacity(schema_fields.len());
+                    for schema_field in schema_fields {
+                        d.begin_struct_field(&schema_field.name)?;
+                        fields.push(StructValueField {
+                            name: schema_field.name.clone(),
+                            value: Value::decode_from_async(d).await?,
+                        });
+                    }
+                    d.finish_struct()?;
+                    Value::Struct(fields)
+                }
+                &Schema::Enum(ref variants) => {
+                    let variant_ord = d.begin_enum().await?;
+                    let variant = variants
+                        .get(variant_ord)
+                        .ok_or_else(|| error!(
+                            MalformedData,
+                            Some(d.coder_state()),
+                            "variant ordinal {} out of range for {} variants",
+                            variant_ord,
+                            variants.len(),
+                        ))?;
+                    d.begin_enum_variant(&variant.name)?;
+                    let inner = Value::decode_from_async(d).await?;
+                    Value::Enum(EnumValue {
+                        variant_ord,
+                        variant_name: variant.name.clone(),
+                        value: Box::new(inner),
+                    })
+                }
+                &Schema::Recurse(_) => unreachable!(),
+            })
+        })
+    }
+}
+
 impl ScalarValue {
+    pub fn scalar_type(self) -> ScalarType {
+        match self {
+            ScalarValue::U8(_) => ScalarType::U8,
+            ScalarValue::U16(_) => ScalarType::U16,
+            ScalarValue::U32(_) => ScalarType::U32,
+            ScalarValue::U64(_) => ScalarType::U64,
+            ScalarValue::U128(_) => ScalarType::U128,
+            ScalarValue::I8(_) => ScalarType::I8,
+            ScalarValue::I16(_) => ScalarType::I16,
+            ScalarValue::I32(_) => ScalarType::I32,
+            ScalarValue::I64(_) => ScalarType::I64,
+            ScalarValue::I128(_) => ScalarType::I128,
+            ScalarValue::F32(_) => ScalarType::F32,
+            ScalarValue::F64(_) => ScalarType::F64,
+            ScalarValue::Char(_) => ScalarType::Char,
+            ScalarValue::Bool(_) => ScalarType::Bool,
+        }
+    }
+
+    /// Convert to a wider scalar type, if `self.scalar_type().widens_to(to)`.
+    pub fn widen(self, to: ScalarType) -> Option<Self> {
+        let from = self.scalar_type();
+        if from == to {
+            return Some(self);
+        }
+        if !from.widens_to(to) {
+            return None;
+        }
+        // all values that can be widened from are representable as both of
+        // these
+        let (int, float) =
+            match self {
+                ScalarValue::U8(n) => (n as i128, n as f64),
+                ScalarValue::U16(n) => (n as i128, n as f64),
+                ScalarValue::U32(n) => (n as i128, n as f64),
+                ScalarValue::U64(n) => (n as i128, n as f64),
+                ScalarValue::I8(n) => (n as i128, n as f64),
+                ScalarValue::I16(n) => (n as i128, n as f64),
+                ScalarValue::I32(n) => (n as i128, n as f64),
+                ScalarValue::I64(n) => (n as i128, n as f64),
+                ScalarValue::F32(n) => (0, n as f64),
+                _ => unreachable!(),
+            };
+        Some(match to {
+            ScalarType::U16 => ScalarValue::U16(int as u16),
+            ScalarType::U32 => ScalarValue::U32(int as u32),
+            ScalarType::U64 => ScalarValue::U64(int as u64),
+            ScalarType::U128 => ScalarValue::U128(int as u128),
+            ScalarType::I16 => ScalarValue::I16(int as i16),
+            ScalarType::I32 => ScalarValue::I32(int as i32),
+            ScalarType::I64 => ScalarValue::I64(int as i64),
+            ScalarType::I128 => ScalarValue::I128(int),
+            ScalarType::F32 => ScalarValue::F32(float as f32),
+            ScalarType::F64 => ScalarValue::F64(float),
+            _ => unreachable!(),
+        })
+    }
+
     pub fn encode_to<W: Write>(self, e: &mut Encoder<W>) -> Result<()> {
         match self {
             ScalarValue::U8(n) => e.encode_u8(n),
@@ -235,3 +512,77 @@ impl ScalarValue {
         })
     }
 }
+
+impl Display for ScalarValue {
+    /// Formats as the scalar type and value, such as `u32 = 5`.
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            &ScalarValue::U8(n) => write!(f, "u8 = {}", n),
+            &ScalarValue::U16(n) => write!(f, "u16 = {}", n),
+            &ScalarValue::U32(n) => write!(f, "u32 = {}", n),
+            &ScalarValue::U64(n) => write!(f, "u64 = {}", n),
+            &ScalarValue::U128(n) => write!(f, "u128 = {}", n),
+            &ScalarValue::I8(n) => write!(f, "i8 = {}", n),
+            &ScalarValue::I16(n) => write!(f, "i16 = {}", n),
+            &ScalarValue::I32(n) => write!(f, "i32 = {}", n),
+            &ScalarValue::I64(n) => write!(f, "i64 = {}", n),
+            &ScalarValue::I128(n) => write!(f, "i128 = {}", n),
+            &ScalarValue::F32(n) => write!(f, "f32 = {:?}", n),
+            &ScalarValue::F64(n) => write!(f, "f64 = {:?}", n),
+            &ScalarValue::Char(c) => write!(f, "char = {:?}", c),
+            &ScalarValue::Bool(b) => write!(f, "bool = {}", b),
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl ScalarValue {
+    /// Async version of `encode_to`.
+    pub async fn encode_to_async<W>(self, e: &mut AsyncEncoder<'_, '_, W>) -> Result<()>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            ScalarValue::U8(n) => e.encode_u8(n).await,
+            ScalarValue::U16(n) => e.encode_u16(n).await,
+            ScalarValue::U32(n) => e.encode_u32(n).await,
+            ScalarValue::U64(n) => e.encode_u64(n).await,
+            ScalarValue::U128(n) => e.encode_u128(n).await,
+            ScalarValue::I8(n) => e.encode_i8(n).await,
+            ScalarValue::I16(n) => e.encode_i16(n).await,
+            ScalarValue::I32(n) => e.encode_i32(n).await,
+            ScalarValue::I64(n) => e.encode_i64(n).await,
+            ScalarValue::I128(n) => e.encode_i128(n).await,
+            ScalarValue::F32(n) => e.encode_f32(n).await,
+            ScalarValue::F64(n) => e.encode_f64(n).await,
+            ScalarValue::Char(c) => e.encode_char(c).await,
+            ScalarValue::Bool(b) => e.encode_bool(b).await,
+        }
+    }
+
+    /// Async version of `decode_from`.
+    pub async fn decode_from_async<R>(
+        d: &mut AsyncDecoder<'_, '_, R>,
+        scalar_type: ScalarType,
+    ) -> Result<Self>
+    where
+        R: AsyncRead + Unpin,
+    {
+        Ok(match scalar_type {
+            ScalarType::U8 => ScalarValue::U8(d.decode_u8().await?),
+            ScalarType::U16 => ScalarValue::U16(d.decode_u16().await?),
+            ScalarType::U32 => ScalarValue::U32(d.decode_u32().await?),
+            ScalarType::U64 => ScalarValue::U64(d.decode_u64().await?),
+            ScalarType::U128 => ScalarValue::U128(d.decode_u128().await?),
+            ScalarType::I8 => ScalarValue::I8(d.decode_i8().await?),
+            ScalarType::I16 => ScalarValue::I16(d.decode_i16().await?),
+            ScalarType::I32 => ScalarValue::I32(d.decode_i32().await?),
+            ScalarType::I64 => ScalarValue::I64(d.decode_i64().await?),
+            ScalarType::I128 => ScalarValue::I128(d.decode_i128().await?),
+            ScalarType::F32 => ScalarValue::F32(d.decode_f32().await?),
+            ScalarType::F64 => ScalarValue::F64(d.decode_f64().await?),
+            ScalarType::Char => ScalarValue::Char(d.decode_char().await?),
+            ScalarType::Bool => ScalarValue::Bool(d.decode_bool().await?),
+        })
+    }
+}
@@ -24,7 +24,7 @@ macro_rules! ensure {
 
 /// Number of bytes needed to encode an ordinal based on max ordinal
 /// value.
-fn ord_byte_len(max_ord: usize) -> usize {
+pub(crate) fn ord_byte_len(max_ord: usize) -> usize {
     let mut mask = !0;
     let mut bytes = 0;
 
@@ -43,7 +43,7 @@ pub fn write_ord<W>(
     num_variants: usize,
 ) -> Result<()>
 where
-    W: Write,
+    W: Write + ?Sized,
 {
     debug_assert!(ord < num_variants, "enum ord out of bounds");
     // if the ord is greater than 2^64... congratulations, future man, on
@@ -60,7 +60,7 @@ pub fn read_ord<R>(
     num_variants: usize,
 ) -> Result<usize>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
     ensure!(num_variants > 0, "malformed data, presence of uninhabited enum");
     let mut all_bytes = [0; 8];
@@ -89,7 +89,7 @@ pub fn write_var_len_uint<W>(
     mut n: u128,
 ) -> Result<()>
 where
-    W: Write,
+    W: Write + ?Sized,
 {
     let mut more = true;
     while more {
@@ -107,7 +107,7 @@ pub fn read_var_len_uint<R>(
     read: &mut R,
 ) -> Result<u128>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
     let mut n: u128 = 0;
     let mut shift = 0;
@@ -139,7 +139,7 @@ pub fn write_var_len_sint<W>(
     mut n: i128,
 ) -> Result<()>
 where
-    W: Write,
+    W: Write + ?Sized,
 {
     let neg = n < 0;
     if neg {
@@ -169,7 +169,7 @@ pub fn read_var_len_sint<R>(
     read: &mut R,
 ) -> Result<i128>
 where
-    R: Read,
+    R: Read + ?Sized,
 {
     let mut n: i128 = 0;
     
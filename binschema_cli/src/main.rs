@@ -0,0 +1,387 @@
+//! Command-line tool for inspecting and producing binschema data, so that
+//! doing so doesn't require writing a program around `Decoder`.
+//!
+//! Schema files may be either schema texts, see `Schema::parse_text`, or
+//! schemas encoded with binschema itself, under the schema of `Schema`.
+
+use binschema::{
+    *,
+    value::Value,
+    json::{
+        bytes_to_json,
+        json_to_bytes,
+    },
+};
+use serde::{
+    Serialize,
+    Deserialize,
+};
+use std::{
+    env,
+    fmt::Write as _,
+    fs,
+    io::{
+        self,
+        Read,
+        Write,
+    },
+    process,
+};
+
+
+const USAGE: &str = "\
+usage:
+    binschema schema <schema> [--text | --binary]
+        print a schema in tree form, as a schema text, or binschema-encoded
+    binschema decode <schema> <message> [--tree]
+        decode a message and print it as JSON, or as an annotated tree
+    binschema dump <schema> <message>
+        print a hex dump of a message, annotated with what each byte means
+    binschema encode <schema> <json> [-o <out>]
+        encode a JSON value into a message, written to <out> or stdout
+    binschema validate <schema> [<message>]
+        check that a schema is legal, and that a message is valid for it
+    binschema diff <old schema> <new schema>
+        print the changes between two schemas
+
+files may be `-` for stdin. validate and dump exit with 1 if invalid, and diff
+exits with 1 if there are breaking changes.";
+
+type Result<T> = std::result::Result<T, Box<dyn std::error::Error>>;
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match run(&args) {
+        Ok(code) => process::exit(code),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(2);
+        }
+    }
+}
+
+/// Split args into positional args and flags, erroring on unknown flags.
+/// Flags in `with_value` take the next arg as their value.
+fn parse_args<'a>(
+    args: &'a [String],
+    flags: &[&str],
+    with_value: &[&str],
+) -> Result<(Vec<&'a str>, Vec<(&'a str, Option<&'a str>)>)> {
+    let mut positional = Vec::new();
+    let mut found = Vec::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with('-') && arg != "-" {
+            if flags.contains(&&arg[..]) {
+                found.push((&arg[..], None));
+            } else if with_value.contains(&&arg[..]) {
+                let value = args
+                    .next()
+                    .ok_or_else(|| format!("{} needs a value", arg))?;
+                found.push((&arg[..], Some(&value[..])));
+            } else {
+                return Err(format!("unknown flag {}\n\n{}", arg, USAGE).into());
+            }
+        } else {
+            positional.push(&arg[..]);
+        }
+    }
+    Ok((positional, found))
+}
+
+fn run(args: &[String]) -> Result<i32> {
+    let (command, args) =
+        match args.split_first() {
+            Some((command, args)) => (&command[..], args),
+            None => {
+                eprintln!("{}", USAGE);
+                return Ok(2);
+            }
+        };
+    match command {
+        "schema" => {
+            let (paths, flags) = parse_args(args, &["--text", "--binary"], &[])?;
+            let [path] = positional::<1>(&paths)?;
+            let schema = load_schema(path)?;
+            match flags.last() {
+                None => println!("{}", schema.pretty_fmt()),
+                Some(&("--text", _)) => print!("{}", schema.to_text()),
+                Some(_) => io::stdout().write_all(&encode_schema(&schema)?)?,
+            }
+            Ok(0)
+        }
+        "decode" => {
+            let (paths, flags) = parse_args(args, &["--tree"], &[])?;
+            let [schema_path, message_path] = positional::<2>(&paths)?;
+            let schema = load_schema(schema_path)?;
+            let message = read_input(message_path)?;
+            if flags.is_empty() {
+                println!("{}", bytes_to_json(&schema, &message)?);
+            } else {
+                let value = decode(&schema, &message)?;
+                let mut lines = Vec::new();
+                tree_fmt(&value, 0, &mut lines);
+                println!("{}", lines.join("\n"));
+            }
+            Ok(0)
+        }
+        "dump" => {
+            let (paths, _) = parse_args(args, &[], &[])?;
+            let [schema_path, message_path] = positional::<2>(&paths)?;
+            let schema = load_schema(schema_path)?;
+            let dump = hex_dump(&schema, &read_input(message_path)?);
+            print!("{}", dump);
+            // trailing bytes make a message invalid, as for validate
+            let is_valid = dump.error.is_none() && dump.end == dump.bytes.len();
+            Ok(if is_valid { 0 } else { 1 })
+        }
+        "encode" => {
+            let (paths, flags) = parse_args(args, &[], &["-o"])?;
+            let [schema_path, json_path] = positional::<2>(&paths)?;
+            let schema = load_schema(schema_path)?;
+            let json = String::from_utf8(read_input(json_path)?)?;
+            let message = json_to_bytes(&schema, &json)?;
+            match flags.first() {
+                Some(&(_, Some(out))) => fs::write(out, message)?,
+                _ => io::stdout().write_all(&message)?,
+            }
+            Ok(0)
+        }
+        "validate" => {
+            let (paths, _) = parse_args(args, &[], &[])?;
+            let (schema_path, message_path) =
+                match &paths[..] {
+                    &[schema_path] => (schema_path, None),
+                    &[schema_path, message_path] => (schema_path, Some(message_path)),
+                    _ => return Err(format!("expected 1 or 2 files\n\n{}", USAGE).into()),
+                };
+            let schema = load_schema(schema_path)?;
+            if let Err(issues) = schema.validate() {
+                for issue in issues {
+                    println!("schema issue: {}", issue);
+                }
+                return Ok(1);
+            }
+            if let Some(message_path) = message_path {
+                if let Err(e) = decode(&schema, &read_input(message_path)?) {
+                    println!("invalid message: {}", e);
+                    return Ok(1);
+                }
+            }
+            println!("ok");
+            Ok(0)
+        }
+        "diff" => {
+            let (paths, _) = parse_args(args, &[], &[])?;
+            let [old_path, new_path] = positional::<2>(&paths)?;
+            let diff = diff_schemas(&load_schema(old_path)?, &load_schema(new_path)?);
+            if diff.is_empty() {
+                println!("no changes");
+            } else {
+                println!("{}", diff.pretty_fmt());
+            }
+            Ok(if diff.is_breaking() { 1 } else { 0 })
+        }
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            Ok(0)
+        }
+        _ => Err(format!("unknown command {:?}\n\n{}", command, USAGE).into()),
+    }
+}
+
+fn positional<'a, const N: usize>(paths: &[&'a str]) -> Result<[&'a str; N]> {
+    <[&str; N]>::try_from(paths)
+        .map_err(|_| format!("expected {} files\n\n{}", N, USAGE).into())
+}
+
+fn read_input(path: &str) -> Result<Vec<u8>> {
+    if path == "-" {
+        let mut buf = Vec::new();
+        io::stdin().read_to_end(&mut buf)?;
+        Ok(buf)
+    } else {
+        fs::read(path).map_err(|e| format!("{}: {}", path, e).into())
+    }
+}
+
+/// Load a schema file, either as binschema-encoded schema or as a schema
+/// text.
+fn load_schema(path: &str) -> Result<Schema> {
+    let bytes = read_input(path)?;
+    let binary_err =
+        match decode_schema(&bytes) {
+            Ok(schema) => return Ok(schema),
+            Err(e) => e,
+        };
+    match std::str::from_utf8(&bytes) {
+        Ok(text) => Schema::parse_text(text)
+            .map_err(|e| format!("{}: {}", path, e).into()),
+        Err(_) => Err(format!("{}: {}", path, binary_err).into()),
+    }
+}
+
+fn decode_schema(bytes: &[u8]) -> Result<Schema> {
+    let schema_schema = Schema::schema(Default::default());
+    let mut coder = CoderState::new(&schema_schema, CoderStateAlloc::new(), None);
+    let mut read = bytes;
+    let schema = Schema::deserialize(&mut Decoder::new(&mut coder, &mut read))?;
+    coder.is_finished_or_err()?;
+    if !read.is_empty() {
+        return Err(format!("{} trailing bytes after schema", read.len()).into());
+    }
+    Ok(schema)
+}
+
+fn encode_schema(schema: &Schema) -> Result<Vec<u8>> {
+    let schema_schema = Schema::schema(Default::default());
+    let mut coder = CoderState::new(&schema_schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    schema.serialize(&mut Encoder::new(&mut coder, &mut buf))?;
+    coder.is_finished_or_err()?;
+    Ok(buf)
+}
+
+fn decode(schema: &Schema, message: &[u8]) -> Result<Value> {
+    let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+    let mut read = message;
+    let value = Value::decode_from(&mut Decoder::new(&mut coder, &mut read))?;
+    coder.is_finished_or_err()?;
+    if !read.is_empty() {
+        return Err(format!("{} trailing bytes after message", read.len()).into());
+    }
+    Ok(value)
+}
+
+/// Format a value as a tree, in the style of `Schema::pretty_fmt`.
+fn tree_fmt(value: &Value, indent: usize, lines: &mut Vec<String>) {
+    let mut line = "    ".repeat(indent);
+    line.push_str("- ");
+    let label_indent = format!("{}  ", "    ".repeat(indent));
+    match value {
+        &Value::Scalar(scalar) => {
+            write!(line, "{}", scalar).unwrap();
+            lines.push(line);
+        }
+        &Value::Str(ref s) => {
+            write!(line, "str = {:?}", s).unwrap();
+            lines.push(line);
+        }
+        &Value::Bytes(ref b) => {
+            line.push_str("bytes = ");
+            for byte in b {
+                write!(line, "{:02x}", byte).unwrap();
+            }
+            lines.push(line);
+        }
+        &Value::Unit => {
+            line.push_str("unit");
+            lines.push(line);
+        }
+        &Value::Option(None) => {
+            line.push_str("option: none");
+            lines.push(line);
+        }
+        &Value::Option(Some(ref inner)) => {
+            line.push_str("option: some");
+            lines.push(line);
+            tree_fmt(inner, indent + 1, lines);
+        }
+        &Value::FixedLenSeq(ref elems) | &Value::VarLenSeq(ref elems) => {
+            match value {
+                &Value::FixedLenSeq(_) => write!(line, "seq (length = {})", elems.len()),
+                _ => write!(line, "seq (variable length, length = {})", elems.len()),
+            }.unwrap();
+            lines.push(line);
+            for (i, elem) in elems.iter().enumerate() {
+                lines.push(format!("{}element {}:", label_indent, i));
+                tree_fmt(elem, indent + 1, lines);
+            }
+        }
+        &Value::Tuple(ref elems) => {
+            line.push_str("tuple");
+            lines.push(line);
+            for (i, elem) in elems.iter().enumerate() {
+                lines.push(format!("{}element {}:", label_indent, i));
+                tree_fmt(elem, indent + 1, lines);
+            }
+        }
+        &Value::Struct(ref fields) => {
+            line.push_str("struct");
+            lines.push(line);
+            for (i, field) in fields.iter().enumerate() {
+                lines.push(format!(
+                    "{}field {} (name = {:?}):",
+                    label_indent,
+                    i,
+                    field.name,
+                ));
+                tree_fmt(&field.value, indent + 1, lines);
+            }
+        }
+        &Value::Enum(ref enum_value) => {
+            line.push_str("enum");
+            lines.push(line);
+            lines.push(format!(
+                "{}variant {} (name = {:?}):",
+                label_indent,
+                enum_value.variant_ord,
+                enum_value.variant_name,
+            ));
+            tree_fmt(&enum_value.value, indent + 1, lines);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn run_with(args: &[&str]) -> i32 {
+        let args = args.iter().map(|&arg| arg.to_owned()).collect::<Vec<_>>();
+        run(&args).unwrap()
+    }
+
+    #[test]
+    fn smoke_test() {
+        let dir = env::temp_dir().join(format!("binschema_cli_test_{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = |name: &str, contents: &str| -> PathBuf {
+            let path = dir.join(name);
+            fs::write(&path, contents).unwrap();
+            path
+        };
+        let schema = file("schema.txt", "
+            root struct {
+                id: u32,
+                tags: seq(varlen)(str),
+                parent: option(recurse(2)),
+            }
+        ");
+        let json = file("message.json", r#"
+            { "id": 7, "tags": ["a", "b"], "parent": { "id": 3, "tags": [], "parent": null } }
+        "#);
+        let message = dir.join("message.bin");
+        let schema = schema.to_str().unwrap();
+        let message = message.to_str().unwrap();
+
+        assert_eq!(run_with(&["schema", schema]), 0);
+        assert_eq!(run_with(&["schema", schema, "--text"]), 0);
+        assert_eq!(run_with(&["encode", schema, json.to_str().unwrap(), "-o", message]), 0);
+        assert_eq!(run_with(&["decode", schema, message]), 0);
+        assert_eq!(run_with(&["decode", schema, message, "--tree"]), 0);
+        assert_eq!(run_with(&["dump", schema, message]), 0);
+        assert_eq!(run_with(&["validate", schema, message]), 0);
+        assert_eq!(run_with(&["diff", schema, schema]), 0);
+
+        // validate and dump agree that trailing bytes are invalid
+        let mut bytes = fs::read(message).unwrap();
+        bytes.push(0);
+        fs::write(message, bytes).unwrap();
+        assert_eq!(run_with(&["validate", schema, message]), 1);
+        assert_eq!(run_with(&["dump", schema, message]), 1);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,136 @@
+//! Derive of `Decode`.
+
+use crate::{
+    schema_attrs::Override,
+    CodedField,
+    CodedFields,
+    Shape,
+    Plan,
+    coding_plan,
+    bounded_generics,
+};
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{
+    DeriveInput,
+    Result,
+};
+use quote::quote;
+
+
+fn field_decode(field: &CodedField) -> TokenStream2 {
+    let binding = &field.binding;
+    let field_ty = &field.field.ty;
+    let decode =
+        match (&field.schema_attrs.schema, &field.schema_attrs.fixed_len) {
+            (&Some((_, Override::Bytes)), _) => quote! {
+                ::core::convert::From::from(decoder.decode_bytes()?)
+            },
+            (_, &Some(ref len)) => quote! {
+                {
+                    let len: usize = #len;
+                    decoder.begin_fixed_len_seq(len)?;
+                    let elems = (0..len)
+                        .map(|_| {
+                            decoder.begin_seq_elem()?;
+                            ::binschema::Decode::decode(decoder)
+                        })
+                        .collect::<::binschema::error::Result<#field_ty>>()?;
+                    decoder.finish_seq()?;
+                    elems
+                }
+            },
+            _ => quote! {
+                ::binschema::Decode::decode(decoder)?
+            },
+        };
+    quote! {
+        let #binding: #field_ty = #decode;
+    }
+}
+
+fn fields_decode(fields: &CodedFields) -> TokenStream2 {
+    match &fields.shape {
+        &Shape::Unit => quote! {
+            decoder.decode_unit()?;
+        },
+        &Shape::Newtype(ref field) => field_decode(field),
+        &Shape::Tuple(ref coded) => {
+            let inner = coded.iter().map(field_decode);
+            quote! {
+                decoder.begin_tuple()?;
+                #(
+                    decoder.begin_tuple_elem()?;
+                    #inner
+                )*
+                decoder.finish_tuple()?;
+            }
+        }
+        &Shape::Struct(ref coded) => {
+            let names = coded.iter().map(|field| &field.name);
+            let inner = coded.iter().map(field_decode);
+            quote! {
+                decoder.begin_struct()?;
+                #(
+                    decoder.begin_struct_field(#names)?;
+                    #inner
+                )*
+                decoder.finish_struct()?;
+            }
+        }
+    }
+}
+
+pub fn decode(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = bounded_generics(&input.generics, quote! { ::binschema::Decode });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body =
+        match &coding_plan(input, "Decode")? {
+            &Plan::Struct(ref fields) => {
+                let decode = fields_decode(fields);
+                let construct = fields.construct();
+                quote! {
+                    #decode
+                    ::core::result::Result::Ok(Self #construct)
+                }
+            }
+            &Plan::Enum { ref variants, .. } => {
+                let arms = variants.iter().enumerate().map(|(ord, variant)| {
+                    let ident = &variant.variant.ident;
+                    let variant_name = &variant.name;
+                    let decode = fields_decode(&variant.fields);
+                    let construct = variant.fields.construct();
+                    quote! {
+                        #ord => {
+                            decoder.begin_enum_variant(#variant_name)?;
+                            #decode
+                            ::core::result::Result::Ok(Self::#ident #construct)
+                        }
+                    }
+                });
+                let msg = format!("variant ordinal {{}} out of range for {}", name);
+                quote! {
+                    match decoder.begin_enum()? {
+                        #( #arms )*
+                        ord => ::core::result::Result::Err(
+                            ::binschema::error::Error::new(
+                                ::binschema::error::ErrorKind::SchemaNonConformance,
+                                ::std::format!(#msg, ord),
+                                ::core::option::Option::Some(decoder.coder_state()),
+                            )
+                        ),
+                    }
+                }
+            }
+        };
+
+    Ok(quote! {
+        impl #impl_generics ::binschema::Decode for #name #ty_generics #where_clause {
+            fn decode<__R: ::std::io::Read>(
+                decoder: &mut ::binschema::Decoder<__R>,
+            ) -> ::binschema::error::Result<Self> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,149 @@
+//! Derive of `Encode`.
+
+use crate::{
+    schema_attrs::Override,
+    CodedField,
+    CodedFields,
+    Shape,
+    Plan,
+    coding_plan,
+    bounded_generics,
+};
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{
+    DeriveInput,
+    Result,
+};
+use quote::quote;
+
+
+fn field_encode(field: &CodedField) -> TokenStream2 {
+    let binding = &field.binding;
+    let encode =
+        match (&field.schema_attrs.schema, &field.schema_attrs.fixed_len) {
+            (&Some((_, Override::Bytes)), _) => quote! {
+                encoder.encode_bytes(::core::convert::AsRef::<[u8]>::as_ref(#binding))?;
+            },
+            (_, &Some(_)) => quote! {
+                let elems = ::core::iter::IntoIterator::into_iter(#binding);
+                encoder.begin_fixed_len_seq(::core::iter::ExactSizeIterator::len(&elems))?;
+                for elem in elems {
+                    encoder.begin_seq_elem()?;
+                    ::binschema::Encode::encode(elem, encoder)?;
+                }
+                encoder.finish_seq()?;
+            },
+            _ => quote! {
+                ::binschema::Encode::encode(#binding, encoder)?;
+            },
+        };
+    match &field.serde_attrs.skip_serializing_if {
+        &Some(ref predicate) => quote! {
+            if #predicate(#binding) {
+                encoder.encode_none()?;
+            } else {
+                #encode
+            }
+        },
+        &None => quote! {
+            {
+                #encode
+            }
+        },
+    }
+}
+
+fn fields_encode(fields: &CodedFields) -> TokenStream2 {
+    match &fields.shape {
+        &Shape::Unit => quote! {
+            encoder.encode_unit()?;
+        },
+        &Shape::Newtype(ref field) => field_encode(field),
+        &Shape::Tuple(ref coded) => {
+            let inner = coded.iter().map(field_encode);
+            quote! {
+                encoder.begin_tuple()?;
+                #(
+                    encoder.begin_tuple_elem()?;
+                    #inner
+                )*
+                encoder.finish_tuple()?;
+            }
+        }
+        &Shape::Struct(ref coded) => {
+            let names = coded.iter().map(|field| &field.name);
+            let inner = coded.iter().map(field_encode);
+            quote! {
+                encoder.begin_struct()?;
+                #(
+                    encoder.begin_struct_field(#names)?;
+                    #inner
+                )*
+                encoder.finish_struct()?;
+            }
+        }
+    }
+}
+
+pub fn encode(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = bounded_generics(&input.generics, quote! { ::binschema::Encode });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let body =
+        match &coding_plan(input, "Encode")? {
+            &Plan::Struct(ref fields) => {
+                let pattern = fields.pattern();
+                let encode = fields_encode(fields);
+                quote! {
+                    let Self #pattern = *self;
+                    #encode
+                    ::core::result::Result::Ok(())
+                }
+            }
+            &Plan::Enum { ref variants, ref skipped } => {
+                let arms = variants.iter().enumerate().map(|(ord, variant)| {
+                    let ident = &variant.variant.ident;
+                    let variant_name = &variant.name;
+                    let pattern = variant.fields.pattern();
+                    let encode = fields_encode(&variant.fields);
+                    quote! {
+                        Self::#ident #pattern => {
+                            encoder.begin_enum(#ord, #variant_name)?;
+                            #encode
+                            ::core::result::Result::Ok(())
+                        }
+                    }
+                });
+                let skipped_arms = skipped.iter().map(|variant| {
+                    let ident = &variant.ident;
+                    let msg = format!("cannot encode skipped variant {}", ident);
+                    quote! {
+                        Self::#ident { .. } => ::core::result::Result::Err(
+                            ::binschema::error::Error::new(
+                                ::binschema::error::ErrorKind::SchemaNonConformance,
+                                #msg,
+                                ::core::option::Option::Some(encoder.coder_state()),
+                            )
+                        ),
+                    }
+                });
+                quote! {
+                    match *self {
+                        #( #arms )*
+                        #( #skipped_arms )*
+                    }
+                }
+            }
+        };
+
+    Ok(quote! {
+        impl #impl_generics ::binschema::Encode for #name #ty_generics #where_clause {
+            fn encode<__W: ::std::io::Write>(
+                &self,
+                encoder: &mut ::binschema::Encoder<__W>,
+            ) -> ::binschema::error::Result<()> {
+                #body
+            }
+        }
+    })
+}
@@ -0,0 +1,152 @@
+//! Derive of `KnownSchema`.
+
+use crate::{
+    serde_attrs::{
+        SerdeAttrs,
+        AttrsOf,
+    },
+    schema_attrs::{
+        SchemaAttrs,
+        Override,
+    },
+    CodedField,
+    CodedFields,
+    Shape,
+    Plan,
+    plan,
+    bounded_generics,
+};
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{
+    DeriveInput,
+    Error,
+    Result,
+};
+use quote::{
+    quote,
+    format_ident,
+};
+
+
+fn field_schema(field: &CodedField) -> TokenStream2 {
+    let field_ty = &field.field.ty;
+    field.schema_attrs.schema(
+        quote! {
+            <#field_ty as ::binschema::KnownSchema>::schema(stack)
+        },
+        &format_ident!("stack"),
+    )
+}
+
+/// `layer` is whether to push a none layer onto the recurse stack for the
+/// fields, which is needed unless the fields are the type's own schema and so
+/// already have its type layer.
+fn fields_schema(fields: &CodedFields, layer: bool) -> TokenStream2 {
+    let stack =
+        if layer {
+            quote! {
+                let stack = stack.with_none_layer();
+            }
+        } else {
+            quote! {}
+        };
+    match &fields.shape {
+        &Shape::Unit => quote! {
+            ::binschema::Schema::Unit
+        },
+        &Shape::Newtype(ref field) => field_schema(field),
+        &Shape::Tuple(ref coded) => {
+            let inner = coded.iter().map(field_schema);
+            quote! {
+                {
+                    #stack
+                    ::binschema::Schema::Tuple(::std::vec![#( #inner, )*])
+                }
+            }
+        }
+        &Shape::Struct(ref coded) => {
+            let inner = coded.iter().map(|field| {
+                let field_name = &field.name;
+                let field_schema = field_schema(field);
+                quote! {
+                    ::binschema::StructSchemaField {
+                        name: ::std::string::String::from(#field_name),
+                        inner: #field_schema,
+                    }
+                }
+            });
+            quote! {
+                {
+                    #stack
+                    ::binschema::Schema::Struct(::std::vec![#( #inner, )*])
+                }
+            }
+        }
+    }
+}
+
+pub fn known_schema(input: &DeriveInput) -> Result<TokenStream2> {
+    let name = &input.ident;
+    let generics = bounded_generics(&input.generics, quote! { ::binschema::KnownSchema });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let attrs = SerdeAttrs::parse(&input.attrs, AttrsOf::Container)?;
+    let schema_attrs = SchemaAttrs::parse(&input.attrs)?;
+    let body =
+        match &schema_attrs.schema {
+            &Some((span, Override::Recurse(_))) => return Err(Error::new(
+                span,
+                "recurse can only be used on fields and variants",
+            )),
+            // the type is not its own schema, so gets no layer
+            &Some(_) => schema_attrs.schema(quote! {}, &format_ident!("parent_stack")),
+            &None => {
+                let schema = schema_attrs.schema(
+                    derived_schema(&plan(input, &attrs, "KnownSchema")?),
+                    &format_ident!("stack"),
+                );
+                quote! {
+                    if let Some(s) = parent_stack.parent_recurse::<Self>() {
+                        return s;
+                    }
+                    #[allow(unused_variables)]
+                    let stack = parent_stack.with_type_layer::<Self>();
+                    #schema
+                }
+            }
+        };
+
+    Ok(quote! {
+        impl #impl_generics ::binschema::KnownSchema for #name #ty_generics #where_clause {
+            fn schema(
+                parent_stack: ::binschema::RecurseStack,
+            ) -> ::binschema::Schema {
+                #body
+            }
+        }
+    })
+}
+
+/// Schema of a type without a type-level `#[schema(...)]` override.
+fn derived_schema(plan: &Plan) -> TokenStream2 {
+    match plan {
+        &Plan::Struct(ref fields) => fields_schema(fields, false),
+        &Plan::Enum { ref variants, .. } => {
+            let inner = variants.iter().map(|variant| {
+                let variant_name = &variant.name;
+                let variant_schema = variant.schema_attrs.schema(
+                    fields_schema(&variant.fields, true),
+                    &format_ident!("stack"),
+                );
+                quote! {
+                    ::binschema::EnumSchemaVariant {
+                        name: ::std::string::String::from(#variant_name),
+                        inner: #variant_schema,
+                    }
+                }
+            });
+            quote! {
+                ::binschema::Schema::Enum(::std::vec![#( #inner, )*])
+            }
+        }
+    }
+}
@@ -1,147 +1,427 @@
+// the `&Pattern(ref x)` match style is used throughout
+#![allow(clippy::match_ref_pats, clippy::needless_borrowed_reference)]
 
+mod serde_attrs;
+mod schema_attrs;
+mod known_schema;
+mod encode;
+mod decode;
+
+use crate::{
+    serde_attrs::{
+        SerdeAttrs,
+        AttrsOf,
+        RenameRule,
+    },
+    schema_attrs::{
+        SchemaAttrs,
+        Override,
+    },
+};
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
 use syn::{
-    token::Comma,
-    punctuated::Punctuated,
+    ext::IdentExt,
+    spanned::Spanned,
     parse_macro_input,
     DeriveInput,
     Data,
     DataStruct,
     Fields,
-    FieldsNamed,
-    FieldsUnnamed,
     Field,
     DataEnum,
-    Meta,
-    Lit,
+    Variant,
+    Generics,
+    Member,
+    Ident,
+    Type,
+    TypePath,
+    Path,
+    Error,
+    Result,
+    parse_quote,
+};
+use quote::{
+    quote,
+    format_ident,
 };
-use quote::quote;
-
-fn field_schema(field: &Field) -> TokenStream2 {
-    if let Some(attr) = field.attrs
-        .iter()
-        .find(|attr| attr.path.is_ident("schema"))
-    {
-        // attribute mode
-        // the only attribute we currently support is recurse
-        let meta = attr.parse_args::<Meta>()
-            .expect("attribute failed to parse");
-        let meta =
-            match meta {
-                Meta::NameValue(meta) => meta,
-                _ => panic!("attribute must be name/value style"),
-            };
-        assert!(meta.path.is_ident("recurse"), "unsupported attribute name");
-        let n =
-            match meta.lit {
-                Lit::Int(int) => int,
-                _ => panic!("recurse level must be int"),
-            };
+
+
+/// Field which is coded, rather than skipped.
+struct CodedField<'a> {
+    field: &'a Field,
+    member: Member,
+    /// Variable the field's value is bound to in generated code.
+    binding: Ident,
+    /// Name of the field in the schema, if struct-like.
+    name: String,
+    serde_attrs: SerdeAttrs,
+    schema_attrs: SchemaAttrs,
+}
+
+/// What a struct or variant's fields are coded as.
+enum Shape<'a> {
+    Unit,
+    Newtype(Box<CodedField<'a>>),
+    Tuple(Vec<CodedField<'a>>),
+    Struct(Vec<CodedField<'a>>),
+}
+
+/// The fields of a struct or variant.
+struct CodedFields<'a> {
+    shape: Shape<'a>,
+    /// Fields which aren't coded, and so are decoded as their default, with
+    /// the function from `#[serde(default = "path")]` if any.
+    skipped: Vec<(Member, Option<Path>)>,
+}
+
+impl<'a> CodedFields<'a> {
+    fn coded(&self) -> &[CodedField<'a>] {
+        match &self.shape {
+            &Shape::Unit => &[],
+            &Shape::Newtype(ref field) => std::slice::from_ref(field),
+            &Shape::Tuple(ref fields) | &Shape::Struct(ref fields) => fields,
+        }
+    }
+
+    /// Braced pattern binding the coded fields by reference.
+    fn pattern(&self) -> TokenStream2 {
+        let members = self.coded().iter().map(|field| &field.member);
+        let bindings = self.coded().iter().map(|field| &field.binding);
         quote! {
-            recurse(#n)
+            { #( #members: ref #bindings, )* .. }
         }
-    } else {
-        // not attributes
-        let field_ty = &field.ty;
+    }
+
+    /// Braced expression constructing the fields from their bindings.
+    fn construct(&self) -> TokenStream2 {
+        let members = self.coded().iter().map(|field| &field.member);
+        let bindings = self.coded().iter().map(|field| &field.binding);
+        let skipped = self.skipped.iter().map(|&(ref member, ref default)| {
+            match default {
+                &Some(ref path) => quote! { #member: #path(), },
+                &None => quote! { #member: ::core::default::Default::default(), },
+            }
+        });
         quote! {
-            %<#field_ty as ::binschema::KnownSchema>::schema(stack)
+            {
+                #( #members: #bindings, )*
+                #( #skipped )*
+            }
         }
     }
 }
 
-fn fields_schema(fields: &Fields) -> TokenStream2 {
-    match fields {
-        &Fields::Named(FieldsNamed { ref named, .. }) => {
-            // struct-like
-            let inner = named.iter()
-                .map(|field| {
-                    let field_name = field.ident.as_ref().unwrap();
-                    let inner = field_schema(field);
-                    quote! {
-                        (#field_name: #inner)
+/// Variant which is coded, rather than skipped.
+struct CodedVariant<'a> {
+    variant: &'a Variant,
+    name: String,
+    fields: CodedFields<'a>,
+    schema_attrs: SchemaAttrs,
+}
+
+/// How a type is coded, if it doesn't have a type-level `#[schema(...)]`
+/// override.
+enum Plan<'a> {
+    Struct(CodedFields<'a>),
+    Enum {
+        /// In order of ordinal.
+        variants: Vec<CodedVariant<'a>>,
+        /// Variants which can't be coded, which are all after the coded ones.
+        skipped: Vec<&'a Variant>,
+    },
+}
+
+fn coded_field(
+    field: &Field,
+    index: usize,
+    name: String,
+    serde_attrs: SerdeAttrs,
+) -> Result<CodedField<'_>> {
+    let schema_attrs = SchemaAttrs::parse(&field.attrs)?;
+    if schema_attrs.schema.is_none() {
+        if let Some(ref e) = serde_attrs.custom {
+            return Err(e.clone());
+        }
+    }
+    Ok(CodedField {
+        field,
+        member: member(field, index),
+        binding: format_ident!("field{}", index),
+        name,
+        serde_attrs,
+        schema_attrs,
+    })
+}
+
+fn member(field: &Field, index: usize) -> Member {
+    match &field.ident {
+        &Some(ref ident) => Member::Named(ident.clone()),
+        &None => Member::Unnamed(index.into()),
+    }
+}
+
+/// Whether a type is spelled as `Option<...>`. Serde encodes fields skipped by
+/// `skip_serializing_if` as none, so they have to be options.
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        &Type::Path(TypePath { ref path, .. }) => path.segments
+            .last()
+            .map(|segment| segment.ident == "Option")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+/// `rename_all` is applied to the names of named fields.
+fn coded_fields(fields: &Fields, rename_all: Option<RenameRule>) -> Result<CodedFields<'_>> {
+    let mut skipped = Vec::new();
+    let shape =
+        match fields {
+            &Fields::Named(ref named) => {
+                // struct-like
+                let mut coded = Vec::new();
+                for (i, field) in named.named.iter().enumerate() {
+                    let attrs = SerdeAttrs::parse(&field.attrs, AttrsOf::Field)?;
+                    if attrs.skip {
+                        skipped.push((member(field, i), attrs.default));
+                        continue;
                     }
-                })
-                .collect::<Punctuated<_, Comma>>();
-            quote! {
-                %{
-                    let stack = stack.with_none_layer();
-                    ::binschema::schema!(
-                        struct { #inner }
-                    )
+                    if attrs.skip_serializing_if.is_some() && !is_option(&field.ty) {
+                        return Err(Error::new(
+                            field.ty.span(),
+                            "binschema: skip_serializing_if is only supported on \
+                            Option fields, which are encoded as none when skipped",
+                        ));
+                    }
+                    let ident = field.ident.as_ref().unwrap().unraw().to_string();
+                    let name = attrs.rename
+                        .clone()
+                        .or_else(|| rename_all.map(|rule| rule.apply_to_field(&ident)))
+                        .unwrap_or(ident);
+                    coded.push(coded_field(field, i, name, attrs)?);
                 }
+                Shape::Struct(coded)
             }
+            &Fields::Unnamed(ref unnamed) => {
+                let unnamed = &unnamed.unnamed;
+                if unnamed.is_empty() {
+                    // 0-tuple... just treat it as unit!
+                    Shape::Unit
+                } else if unnamed.len() == 1 {
+                    // newtype (1-tuple)
+                    let attrs = SerdeAttrs::parse(&unnamed[0].attrs, AttrsOf::Field)?;
+                    if attrs.skip || attrs.skip_serializing_if.is_some() {
+                        return Err(Error::new(
+                            unnamed[0].span(),
+                            "binschema: cannot skip the field of a newtype",
+                        ));
+                    }
+                    Shape::Newtype(Box::new(coded_field(&unnamed[0], 0, String::new(), attrs)?))
+                } else {
+                    // normal tuple
+                    let mut coded = Vec::new();
+                    for (i, field) in unnamed.iter().enumerate() {
+                        let attrs = SerdeAttrs::parse(&field.attrs, AttrsOf::Field)?;
+                        if attrs.skip_serializing_if.is_some() {
+                            return Err(Error::new(
+                                field.span(),
+                                "binschema: cannot conditionally skip a tuple field",
+                            ));
+                        }
+                        if attrs.skip {
+                            skipped.push((member(field, i), attrs.default));
+                        } else {
+                            coded.push(coded_field(field, i, String::new(), attrs)?);
+                        }
+                    }
+                    Shape::Tuple(coded)
+                }
+            }
+            &Fields::Unit => Shape::Unit,
+        };
+    Ok(CodedFields { shape, skipped })
+}
+
+/// Fields of a struct with `#[serde(transparent)]`, which is coded as its one
+/// non-skipped field.
+fn transparent_fields<'a>(
+    input: &DeriveInput,
+    fields: &'a Fields,
+) -> Result<CodedFields<'a>> {
+    let mut coded = Vec::new();
+    let mut skipped = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let attrs = SerdeAttrs::parse(&field.attrs, AttrsOf::Field)?;
+        if attrs.skip {
+            skipped.push((member(field, i), attrs.default));
+        } else {
+            coded.push((i, field, attrs));
+        }
+    }
+    if coded.len() != 1 {
+        return Err(Error::new(
+            input.ident.span(),
+            "binschema: transparent struct must have exactly one non-skipped field",
+        ));
+    }
+    let (i, field, attrs) = coded.pop().unwrap();
+    Ok(CodedFields {
+        shape: Shape::Newtype(Box::new(coded_field(field, i, String::new(), attrs)?)),
+        skipped,
+    })
+}
+
+/// How a type without a type-level `#[schema(...)]` override is coded.
+/// `derive` is the name of the trait being derived.
+fn plan<'a>(
+    input: &'a DeriveInput,
+    attrs: &SerdeAttrs,
+    derive: &str,
+) -> Result<Plan<'a>> {
+    if let Some(e) = attrs.custom.clone() {
+        return Err(e);
+    }
+    Ok(match &input.data {
+        &Data::Struct(DataStruct { ref fields, .. }) if attrs.transparent => {
+            Plan::Struct(transparent_fields(input, fields)?)
         },
-        Fields::Unnamed(FieldsUnnamed { ref unnamed, .. }) => {
-            if unnamed.len() == 0 {
-                // 0-tuple... just treat it as unit!
-                quote! {
-                    unit
+        &Data::Struct(DataStruct { ref fields, .. }) => {
+            Plan::Struct(coded_fields(fields, attrs.rename_all)?)
+        },
+        &Data::Enum(DataEnum { ref variants, .. }) => {
+            let mut coded = Vec::new();
+            let mut skipped = Vec::new();
+            for variant in variants {
+                let variant_attrs = SerdeAttrs::parse(&variant.attrs, AttrsOf::Variant)?;
+                // serde numbers variants including skipped ones when
+                // serializing but excluding them when deserializing, so the
+                // ordinals only agree if skipped variants are at the end
+                if variant_attrs.skip {
+                    skipped.push(variant);
+                    continue;
+                } else if let Some(skipped) = skipped.first() {
+                    return Err(Error::new(
+                        skipped.ident.span(),
+                        "binschema: skipped variants must come after all other variants",
+                    ));
                 }
-            } else if unnamed.len() == 1 {
-                // newtype (1-tuple)
-                field_schema(&unnamed[0])
-            } else {
-                // normal tuple
-                let inner = unnamed.iter()
-                    .map(field_schema)
-                    .map(|field_schema| quote! {
-                        (#field_schema)
-                    })
-                    .collect::<Punctuated<_, Comma>>();
-                quote! {
-                %{
-                    let stack = stack.with_none_layer();
-                    ::binschema::schema!(
-                        tuple { #inner }
-                    )
+                let ident = variant.ident.unraw().to_string();
+                let name = variant_attrs.rename
+                    .clone()
+                    .or_else(|| attrs.rename_all.map(|rule| rule.apply_to_variant(&ident)))
+                    .unwrap_or(ident);
+                let schema_attrs = SchemaAttrs::parse(&variant.attrs)?;
+                if schema_attrs.schema.is_none() {
+                    if let Some(e) = variant_attrs.custom {
+                        return Err(e);
+                    }
                 }
+                let fields = coded_fields(
+                    &variant.fields,
+                    variant_attrs.rename_all.or(attrs.rename_all_fields),
+                )?;
+                coded.push(CodedVariant { variant, name, fields, schema_attrs });
             }
-            }
+            Plan::Enum { variants: coded, skipped }
         },
-        &Fields::Unit => {
-            quote! {
-                unit
+        &Data::Union(_) => return Err(Error::new(
+            input.ident.span(),
+            format!("cannot derive {} on a union", derive),
+        )),
+    })
+}
+
+/// How a type is coded by the `Encode` and `Decode` derives, which code the
+/// type as it's spelled, so can't follow `#[schema(...)]` overrides which
+/// make it coded as something else. `recurse` only affects the schema, so is
+/// fine.
+fn coding_plan<'a>(input: &'a DeriveInput, derive: &str) -> Result<Plan<'a>> {
+    let attrs = SerdeAttrs::parse(&input.attrs, AttrsOf::Container)?;
+    let schema_attrs = SchemaAttrs::parse(&input.attrs)?;
+    if let Some((span, _)) = schema_attrs.schema {
+        return Err(Error::new(
+            span,
+            format!("binschema: cannot derive {} with a type-level schema override", derive),
+        ));
+    }
+    if let Some(ref len) = schema_attrs.fixed_len {
+        return Err(Error::new(
+            len.span(),
+            format!("binschema: cannot derive {} with a type-level fixed_len", derive),
+        ));
+    }
+    let plan = plan(input, &attrs, derive)?;
+    let mut fields = Vec::new();
+    match &plan {
+        &Plan::Struct(ref coded) => fields.extend(coded.coded()),
+        &Plan::Enum { ref variants, .. } => for variant in variants {
+            match &variant.schema_attrs.schema {
+                &None | &Some((_, Override::Recurse(_))) => (),
+                &Some((span, _)) => return Err(Error::new(
+                    span,
+                    format!(
+                        "binschema: cannot derive {} with a variant-level schema \
+                        override other than recurse",
+                        derive,
+                    ),
+                )),
+            }
+            if let Some(ref len) = variant.schema_attrs.fixed_len {
+                return Err(Error::new(
+                    len.span(),
+                    format!("binschema: cannot derive {} with a variant-level fixed_len", derive),
+                ));
             }
+            fields.extend(variant.fields.coded());
         },
     }
+    for field in fields {
+        if let &Some((span, Override::With(_) | Override::As(_))) = &field.schema_attrs.schema {
+            return Err(Error::new(
+                span,
+                format!("binschema: cannot derive {} with a with or as schema override", derive),
+            ));
+        }
+    }
+    Ok(plan)
+}
+
+/// The type's generics, with `bound` added to each type parameter.
+fn bounded_generics(generics: &Generics, bound: TokenStream2) -> Generics {
+    let mut generics = generics.clone();
+    let bounded = generics.type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for ident in bounded {
+        where_clause.predicates.push(parse_quote! {
+            #ident: #bound
+        });
+    }
+    generics
 }
 
 #[proc_macro_derive(KnownSchema, attributes(schema))]
 pub fn derive_known_schema(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
+    known_schema::known_schema(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
 
-    let name = &input.ident;
-    let schema = match &input.data {
-        &Data::Struct(DataStruct { ref fields, .. }) => fields_schema(fields),
-        &Data::Enum(DataEnum { ref variants, .. }) => {
-            let inner = variants.iter()
-                .map(|variant| {
-                    let variant_name = &variant.ident;
-                    let inner = fields_schema(&variant.fields);
-                    quote! {
-                        #variant_name(#inner)
-                    }
-                })
-                .collect::<Punctuated<_, Comma>>();
-            quote! {
-                enum { #inner }
-            }
-        },
-        &Data::Union(_) => panic!("cannot derive KnownSchema on a union"),
-    };
-    
-    quote! {
-        impl ::binschema::KnownSchema for #name {
-            fn schema(
-                parent_stack: ::binschema::RecurseStack,
-            ) -> ::binschema::Schema {
-                if let Some(s) = parent_stack.parent_recurse::<Self>() {
-                    return s;
-                }
-                let stack = parent_stack.with_type_layer::<Self>();
-                ::binschema::schema!(#schema)
-            }
-        }
-    }.into()
+#[proc_macro_derive(Encode, attributes(schema))]
+pub fn derive_encode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    encode::encode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+#[proc_macro_derive(Decode, attributes(schema))]
+pub fn derive_decode(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    decode::decode(&input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
 }
@@ -0,0 +1,158 @@
+//! Parsing of `#[schema(...)]` attributes, which override the derived schema
+//! of a field or a whole type.
+
+use proc_macro2::{
+    TokenStream as TokenStream2,
+    Span,
+};
+use syn::{
+    parse::{
+        Parse,
+        ParseStream,
+    },
+    punctuated::Punctuated,
+    token::Comma,
+    Token,
+    Attribute,
+    Ident,
+    LitInt,
+    Path,
+    Type,
+    Expr,
+    Error,
+    Result,
+};
+use quote::quote;
+
+
+/// Schema to use instead of the one the type would have.
+pub enum Override {
+    /// `recurse = N`, a recurse to the Nth ancestor.
+    Recurse(LitInt),
+    /// `with = path`, a fn with the signature of `KnownSchema::schema`.
+    With(Path),
+    /// `as = Type`, the schema of another `KnownSchema` type.
+    As(Type),
+    /// `bytes`.
+    Bytes,
+}
+
+/// One item within `#[schema(...)]`.
+enum Item {
+    Override(Span, Override),
+    /// `fixed_len = N`, making the schema, which must be a seq, fixed length.
+    FixedLen(Span, Expr),
+}
+
+impl Parse for Item {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(Token![as]) {
+            let token = input.parse::<Token![as]>()?;
+            input.parse::<Token![=]>()?;
+            return Ok(Item::Override(token.span, Override::As(input.parse()?)));
+        }
+        let ident = input.parse::<Ident>()?;
+        let span = ident.span();
+        match &ident.to_string()[..] {
+            "recurse" => {
+                input.parse::<Token![=]>()?;
+                Ok(Item::Override(span, Override::Recurse(input.parse()?)))
+            }
+            "with" => {
+                input.parse::<Token![=]>()?;
+                Ok(Item::Override(span, Override::With(input.parse()?)))
+            }
+            "bytes" => Ok(Item::Override(span, Override::Bytes)),
+            "fixed_len" => {
+                input.parse::<Token![=]>()?;
+                Ok(Item::FixedLen(span, input.parse()?))
+            }
+            _ => Err(Error::new(
+                span,
+                "unknown schema attribute, expected one of recurse, with, as, bytes, \
+                fixed_len",
+            )),
+        }
+    }
+}
+
+/// The `#[schema(...)]` attributes of a type or field.
+#[derive(Default)]
+pub struct SchemaAttrs {
+    pub schema: Option<(Span, Override)>,
+    pub fixed_len: Option<Expr>,
+}
+
+impl SchemaAttrs {
+    pub fn parse(attrs: &[Attribute]) -> Result<Self> {
+        let mut parsed = SchemaAttrs::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("schema")) {
+            let items = attr
+                .parse_args_with(Punctuated::<Item, Comma>::parse_terminated)?;
+            for item in items {
+                match item {
+                    Item::Override(span, schema) => {
+                        if parsed.schema.is_some() {
+                            return Err(Error::new(
+                                span,
+                                "only one of recurse, with, as, and bytes may be used",
+                            ));
+                        }
+                        parsed.schema = Some((span, schema));
+                    }
+                    Item::FixedLen(span, len) => {
+                        if parsed.fixed_len.is_some() {
+                            return Err(Error::new(span, "duplicate fixed_len"));
+                        }
+                        parsed.fixed_len = Some(len);
+                    }
+                }
+            }
+        }
+        if let (
+            &Some((span, Override::Recurse(_) | Override::Bytes)),
+            &Some(_),
+        ) = (&parsed.schema, &parsed.fixed_len) {
+            return Err(Error::new(span, "fixed_len cannot be used with recurse or bytes"));
+        }
+        Ok(parsed)
+    }
+
+    /// Expression for the schema, given an expression for the schema it would
+    /// otherwise have, and the name of the `RecurseStack` variable in scope.
+    pub fn schema(&self, default: TokenStream2, stack: &Ident) -> TokenStream2 {
+        let schema =
+            match &self.schema {
+                &None => default,
+                &Some((_, Override::Recurse(ref n))) => quote! {
+                    ::binschema::Schema::Recurse(#n)
+                },
+                &Some((_, Override::With(ref path))) => quote! {
+                    #path(#stack)
+                },
+                &Some((_, Override::As(ref ty))) => quote! {
+                    <#ty as ::binschema::KnownSchema>::schema(#stack)
+                },
+                &Some((_, Override::Bytes)) => quote! {
+                    ::binschema::Schema::Bytes
+                },
+            };
+        match &self.fixed_len {
+            &None => schema,
+            &Some(ref len) => quote! {
+                match #schema {
+                    ::binschema::Schema::Seq(seq) => ::binschema::Schema::Seq(
+                        ::binschema::SeqSchema {
+                            len: ::core::option::Option::Some(#len),
+                            inner: seq.inner,
+                        }
+                    ),
+                    schema => ::core::panic!(
+                        "#[schema(fixed_len)] used on a non-seq schema: {:?}",
+                        schema,
+                    ),
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,311 @@
+//! Reading of the `#[serde(...)]` attributes which affect what shape a type
+//! serializes as, so that the derived schema can agree with it.
+
+use syn::{
+    spanned::Spanned,
+    Attribute,
+    Meta,
+    NestedMeta,
+    Lit,
+    Path,
+    Error,
+    Result,
+};
+
+
+/// Where an attribute is, since some attributes mean different things in
+/// different places.
+#[derive(Copy, Clone, PartialEq)]
+pub enum AttrsOf {
+    Container,
+    Variant,
+    Field,
+}
+
+/// The serde attributes of a container, variant, or field which the schema
+/// depends on.
+#[derive(Default)]
+pub struct SerdeAttrs {
+    pub rename: Option<String>,
+    pub rename_all: Option<RenameRule>,
+    pub rename_all_fields: Option<RenameRule>,
+    /// `skip` or `skip_serializing`, or for variants also
+    /// `skip_deserializing`, since serde numbers the remaining variants
+    /// differently when serializing and deserializing if either is used.
+    pub skip: bool,
+    /// Predicate with which the field is encoded as none instead of its
+    /// value.
+    pub skip_serializing_if: Option<Path>,
+    /// Function with which a skipped field is decoded instead of
+    /// `Default::default`, from `default = "path"`.
+    pub default: Option<Path>,
+    pub transparent: bool,
+    /// Error to raise if there's no `#[schema(...)]` override, if there's an
+    /// attribute which makes the shape unknown.
+    pub custom: Option<Error>,
+}
+
+/// Attributes with which the serialized shape isn't representable in a
+/// schema.
+const UNSUPPORTED: &[(&str, &str)] = &[
+    ("untagged", "untagged enums are not supported, since binschema is not self-describing"),
+    ("tag", "internally tagged enums are not supported, only externally tagged"),
+    ("content", "adjacently tagged enums are not supported, only externally tagged"),
+    ("flatten", "flattened fields are not supported"),
+];
+
+/// Attributes with which the serialized shape can't be known from the type
+/// definition, so which need a `#[schema(...)]` override.
+const CUSTOM: &[(&str, &str)] = &[
+    ("with", "the schema of a field serialized `with` a module is unknown"),
+    ("serialize_with", "the schema of a field serialized `with` a function is unknown"),
+    ("deserialize_with", "the schema of a field deserialized `with` a function is unknown"),
+    ("getter", "the schema of a field serialized through a getter is unknown"),
+    ("into", "the schema of a type serialized `into` another type is unknown"),
+    ("from", "the schema of a type deserialized `from` another type is unknown"),
+    ("try_from", "the schema of a type deserialized `from` another type is unknown"),
+];
+
+impl SerdeAttrs {
+    pub fn parse(attrs: &[Attribute], of: AttrsOf) -> Result<Self> {
+        let mut parsed = SerdeAttrs::default();
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("serde")) {
+            let list =
+                match attr.parse_meta()? {
+                    Meta::List(list) => list,
+                    meta => return Err(Error::new(meta.span(), "expected #[serde(...)]")),
+                };
+            for nested in &list.nested {
+                let meta =
+                    match nested {
+                        &NestedMeta::Meta(ref meta) => meta,
+                        &NestedMeta::Lit(ref lit) => {
+                            return Err(Error::new(lit.span(), "unexpected literal"));
+                        }
+                    };
+                let name =
+                    match meta.path().get_ident() {
+                        Some(ident) => ident.to_string(),
+                        None => continue,
+                    };
+                if let Some(&(_, msg)) = UNSUPPORTED
+                    .iter()
+                    .find(|&&(unsupported, _)| unsupported == name)
+                {
+                    return Err(Error::new(meta.span(), format!("binschema: {}", msg)));
+                }
+                // serde_bytes serializes a seq of u8 with `serialize_bytes`,
+                // which binschema accepts as the seq that its schema is
+                if name == "with" && is_serde_bytes(meta) {
+                    continue;
+                }
+                if let Some(&(_, msg)) = CUSTOM
+                    .iter()
+                    .find(|&&(custom, _)| custom == name)
+                {
+                    parsed.custom = Some(Error::new(meta.span(), format!(
+                        "binschema: {}, so it needs a #[schema(...)] attribute",
+                        msg,
+                    )));
+                }
+                match &name[..] {
+                    "rename" if of != AttrsOf::Container => {
+                        parsed.rename = Some(serialize_str(meta)?);
+                    }
+                    "rename_all" => {
+                        parsed.rename_all = Some(RenameRule::parse(meta)?);
+                    }
+                    "rename_all_fields" => {
+                        parsed.rename_all_fields = Some(RenameRule::parse(meta)?);
+                    }
+                    "skip" | "skip_serializing" => parsed.skip = true,
+                    "skip_deserializing" if of == AttrsOf::Variant => parsed.skip = true,
+                    "skip_serializing_if" => {
+                        parsed.skip_serializing_if = Some(predicate_path(meta)?);
+                    }
+                    "default" if of == AttrsOf::Field => {
+                        if let &Meta::NameValue(_) = meta {
+                            parsed.default = Some(predicate_path(meta)?);
+                        }
+                    }
+                    "transparent" => parsed.transparent = true,
+                    // other attributes don't affect the serialized shape
+                    _ => (),
+                }
+            }
+        }
+        Ok(parsed)
+    }
+}
+
+/// Get the string value of `name = "value"`, or of
+/// `name(serialize = "value", deserialize = "value")`, which must be the same
+/// both ways since binschema uses the same names both ways.
+fn serialize_str(meta: &Meta) -> Result<String> {
+    match meta {
+        &Meta::NameValue(ref nv) => lit_str(&nv.lit),
+        &Meta::List(ref list) => {
+            let mut ser = None;
+            let mut de = None;
+            for nested in &list.nested {
+                match nested {
+                    &NestedMeta::Meta(Meta::NameValue(ref nv))
+                        if nv.path.is_ident("serialize") => ser = Some(lit_str(&nv.lit)?),
+                    &NestedMeta::Meta(Meta::NameValue(ref nv))
+                        if nv.path.is_ident("deserialize") => de = Some(lit_str(&nv.lit)?),
+                    nested => return Err(Error::new(nested.span(), "unexpected attribute")),
+                }
+            }
+            match (ser, de) {
+                (Some(ser), Some(de)) if ser == de => Ok(ser),
+                _ => Err(Error::new(
+                    list.span(),
+                    "binschema: serialize and deserialize names must be the same",
+                )),
+            }
+        }
+        &Meta::Path(ref path) => Err(Error::new(path.span(), "expected a value")),
+    }
+}
+
+/// Whether this is `with = "serde_bytes"`.
+fn is_serde_bytes(meta: &Meta) -> bool {
+    match meta {
+        &Meta::NameValue(ref nv) => match &nv.lit {
+            &Lit::Str(ref s) => s.value().trim_start_matches("::") == "serde_bytes",
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// Get the path of `name = "path"`.
+fn predicate_path(meta: &Meta) -> Result<Path> {
+    match meta {
+        &Meta::NameValue(ref nv) => match &nv.lit {
+            &Lit::Str(ref s) => s.parse(),
+            lit => Err(Error::new(lit.span(), "expected a string")),
+        },
+        meta => Err(Error::new(meta.span(), "expected a value")),
+    }
+}
+
+fn lit_str(lit: &Lit) -> Result<String> {
+    match lit {
+        &Lit::Str(ref s) => Ok(s.value()),
+        lit => Err(Error::new(lit.span(), "expected a string")),
+    }
+}
+
+/// Value of `rename_all`, which works the same as in serde.
+#[derive(Copy, Clone)]
+pub enum RenameRule {
+    Lower,
+    Upper,
+    Pascal,
+    Camel,
+    Snake,
+    ScreamingSnake,
+    Kebab,
+    ScreamingKebab,
+}
+
+impl RenameRule {
+    fn parse(meta: &Meta) -> Result<Self> {
+        let s = serialize_str(meta)?;
+        Ok(match &s[..] {
+            "lowercase" => RenameRule::Lower,
+            "UPPERCASE" => RenameRule::Upper,
+            "PascalCase" => RenameRule::Pascal,
+            "camelCase" => RenameRule::Camel,
+            "snake_case" => RenameRule::Snake,
+            "SCREAMING_SNAKE_CASE" => RenameRule::ScreamingSnake,
+            "kebab-case" => RenameRule::Kebab,
+            "SCREAMING-KEBAB-CASE" => RenameRule::ScreamingKebab,
+            _ => return Err(Error::new(
+                meta.span(),
+                format!("unknown rename rule {:?}", s),
+            )),
+        })
+    }
+
+    /// Rename a variant, which is assumed to be in Pascal.
+    pub fn apply_to_variant(self, variant: &str) -> String {
+        match self {
+            RenameRule::Pascal => variant.to_owned(),
+            RenameRule::Lower => variant.to_ascii_lowercase(),
+            RenameRule::Upper => variant.to_ascii_uppercase(),
+            RenameRule::Camel => lower_first(variant),
+            RenameRule::Snake => {
+                let mut snake = String::new();
+                for (i, c) in variant.char_indices() {
+                    if i > 0 && c.is_uppercase() {
+                        snake.push('_');
+                    }
+                    snake.push(c.to_ascii_lowercase());
+                }
+                snake
+            }
+            RenameRule::ScreamingSnake => RenameRule::Snake
+                .apply_to_variant(variant)
+                .to_ascii_uppercase(),
+            RenameRule::Kebab => RenameRule::Snake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+            RenameRule::ScreamingKebab => RenameRule::ScreamingSnake
+                .apply_to_variant(variant)
+                .replace('_', "-"),
+        }
+    }
+
+    /// Rename a field, which is assumed to be in snake_case.
+    pub fn apply_to_field(self, field: &str) -> String {
+        match self {
+            RenameRule::Lower | RenameRule::Snake => field.to_owned(),
+            RenameRule::Upper | RenameRule::ScreamingSnake => field.to_ascii_uppercase(),
+            RenameRule::Pascal => {
+                let mut pascal = String::new();
+                let mut capitalize = true;
+                for c in field.chars() {
+                    if c == '_' {
+                        capitalize = true;
+                    } else if capitalize {
+                        pascal.push(c.to_ascii_uppercase());
+                        capitalize = false;
+                    } else {
+                        pascal.push(c);
+                    }
+                }
+                pascal
+            }
+            RenameRule::Camel => {
+                lower_first(&RenameRule::Pascal.apply_to_field(field))
+            }
+            RenameRule::Kebab => field.replace('_', "-"),
+            RenameRule::ScreamingKebab => field.to_ascii_uppercase().replace('_', "-"),
+        }
+    }
+}
+
+/// Lowercase the first char, which may not be ASCII.
+fn lower_first(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_ascii_lowercase().to_string() + chars.as_str(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RenameRule;
+
+    // serde_derive itself panics on these, so they can't be tested through a
+    // type which also derives Serialize
+    #[test]
+    fn camel_non_ascii_test() {
+        assert_eq!(RenameRule::Camel.apply_to_variant("Ölfass"), "Ölfass");
+        assert_eq!(RenameRule::Camel.apply_to_field("über_alles"), "überAlles");
+        assert_eq!(RenameRule::Camel.apply_to_variant(""), "");
+    }
+}
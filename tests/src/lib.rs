@@ -52,7 +52,47 @@ where
 }
 
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[cfg(test)]
+fn native_round_trip_test<T>(val: T)
+where
+    T: Debug + PartialEq + Serialize + KnownSchema + Encode + Decode,
+{
+    let schema = T::schema(Default::default());
+    let mut coder_alloc = CoderStateAlloc::new();
+
+    // encode through serde, for comparison
+    let mut serde_buf = Vec::new();
+    let mut coder = CoderState::new(&schema, coder_alloc, None);
+    let mut encoder = Encoder::new(&mut coder, &mut serde_buf);
+    val.serialize(&mut encoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    coder_alloc = coder.into_alloc();
+
+    // encode natively
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, coder_alloc, None);
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    val.encode(&mut encoder)
+        .map_err(|e| println!("{}", e))
+        .unwrap();
+    coder.is_finished_or_err().unwrap();
+    coder_alloc = coder.into_alloc();
+    assert_eq!(buf, serde_buf);
+
+    // decode natively
+    let mut coder = CoderState::new(&schema, coder_alloc, None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    let val2 = T::decode(&mut decoder)
+        .map_err(|e| println!("{}", e))
+        .unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+    assert_eq!(val, val2);
+}
+
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 pub struct Test1 {
     foo: u32,
     bar: String,
@@ -67,16 +107,16 @@ pub struct Test1 {
     h: char,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 struct Test1StructUnit;
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 struct Test1Struct0Tuple();
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 struct Test1StructNewtype(f32);
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 struct Test1Struct2Tuple(f32, f64);
 
 #[test]
@@ -96,13 +136,13 @@ fn test_1() {
     });
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 pub struct Test2Outer {
     first: Test2Inner,
     second: Test2Inner,
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 pub enum Test2Inner {
     Foo(u32),
     Bar {
@@ -127,7 +167,7 @@ fn test_2() {
     });
 }
 
-#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
 pub enum BinaryTree {
     Branch {
         value: u32,
@@ -150,7 +190,2143 @@ fn binary_tree_test() {
     });
 }
 
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub struct Empties {
+    seq: Vec<u32>,
+    strukt: EmptyStruct,
+    unit: Test1StructUnit,
+    map: HashMap<String, u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub struct EmptyStruct {}
+
+#[test]
+fn empties_test() {
+    round_trip_test(Empties {
+        seq: vec![],
+        strukt: EmptyStruct {},
+        unit: Test1StructUnit,
+        map: HashMap::new(),
+    });
+}
+
 #[test]
 fn schema_schema_test() {
     round_trip_test(Schema::schema(Default::default()));
 }
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+pub struct Page<T> {
+    items: Vec<T>,
+    next: Option<Box<Page<T>>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+pub enum Tree<K, V>
+where
+    K: Ord,
+{
+    Node {
+        key: K,
+        value: V,
+        children: Vec<Tree<K, V>>,
+    },
+    Empty,
+}
+
+#[test]
+fn generic_known_schema_test() {
+    // other instantiations of the same type don't recurse to each other
+    assert_eq!(
+        Page::<Tree<u8, Page<u32>>>::schema(Default::default()),
+        schema!(struct {
+            (items: seq(varlen)(enum {
+                Node(struct {
+                    (key: u8),
+                    (value: struct {
+                        (items: seq(varlen)(u32)),
+                        (next: option(recurse(2))),
+                    }),
+                    (children: seq(varlen)(recurse(3))),
+                }),
+                Empty(unit),
+            })),
+            (next: option(recurse(2))),
+        }),
+    );
+    round_trip_test(Page {
+        items: vec![
+            Tree::Node {
+                key: 1,
+                value: Page {
+                    items: vec![2, 3],
+                    next: Some(Box::new(Page { items: vec![4], next: None })),
+                },
+                children: vec![Tree::Empty],
+            },
+        ],
+        next: Some(Box::new(Page { items: vec![], next: None })),
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+#[serde(rename_all = "camelCase")]
+pub struct Renamed {
+    user_id: u32,
+    #[serde(rename = "type")]
+    kind: RenamedKind,
+    #[serde(skip)]
+    cache: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    note: Option<String>,
+    r#ref: Meters,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+#[serde(rename_all = "SCREAMING_SNAKE_CASE", rename_all_fields = "kebab-case")]
+pub enum RenamedKind {
+    PlainUser,
+    #[serde(rename = "admin")]
+    Admin { access_level: u8 },
+    #[serde(skip)]
+    Internal(std::cell::Cell<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+#[serde(transparent)]
+pub struct Meters {
+    value: f64,
+    #[serde(skip)]
+    _unit: (),
+}
+
+#[test]
+fn serde_attrs_test() {
+    assert_eq!(
+        Renamed::schema(Default::default()),
+        Schema::Struct(vec![
+            StructSchemaField { name: "userId".into(), inner: schema!(u32) },
+            StructSchemaField {
+                name: "type".into(),
+                inner: Schema::Enum(vec![
+                    EnumSchemaVariant { name: "PLAIN_USER".into(), inner: schema!(unit) },
+                    EnumSchemaVariant {
+                        name: "admin".into(),
+                        inner: Schema::Struct(vec![StructSchemaField {
+                            name: "access-level".into(),
+                            inner: schema!(u8),
+                        }]),
+                    },
+                ]),
+            },
+            StructSchemaField { name: "note".into(), inner: schema!(option(str)) },
+            StructSchemaField { name: "ref".into(), inner: schema!(f64) },
+        ]),
+    );
+    round_trip_test(Renamed {
+        user_id: 1,
+        kind: RenamedKind::Admin { access_level: 3 },
+        cache: None,
+        note: None,
+        r#ref: Meters { value: 2.5, _unit: () },
+    });
+    round_trip_test(Renamed {
+        user_id: 2,
+        kind: RenamedKind::PlainUser,
+        cache: None,
+        note: Some("hi".into()),
+        r#ref: Meters { value: 0.0, _unit: () },
+    });
+}
+
+macro_rules! rename_rule_types {
+    ($($rule:literal => $s:ident, $e:ident;)*)=>{$(
+        #[derive(Default, Serialize, KnownSchema)]
+        #[serde(rename_all = $rule)]
+        pub struct $s {
+            user_id: u8,
+            x: u8,
+        }
+
+        #[derive(Serialize, KnownSchema)]
+        #[serde(rename_all = $rule)]
+        pub enum $e {
+            PlainUser,
+            X,
+        }
+    )*};
+}
+
+rename_rule_types! {
+    "lowercase" => LowerStruct, LowerEnum;
+    "UPPERCASE" => UpperStruct, UpperEnum;
+    "PascalCase" => PascalStruct, PascalEnum;
+    "camelCase" => CamelStruct, CamelEnum;
+    "snake_case" => SnakeStruct, SnakeEnum;
+    "SCREAMING_SNAKE_CASE" => ScreamingSnakeStruct, ScreamingSnakeEnum;
+    "kebab-case" => KebabStruct, KebabEnum;
+    "SCREAMING-KEBAB-CASE" => ScreamingKebabStruct, ScreamingKebabEnum;
+}
+
+#[test]
+fn rename_rules_test() {
+    // schema names are the names serde serializes with
+    fn check<S, E>(variants: [E; 2])
+    where
+        S: Default + Serialize + KnownSchema,
+        E: Serialize + KnownSchema,
+    {
+        let json = serde_json::to_value(S::default()).unwrap();
+        let mut keys = json.as_object().unwrap().keys().cloned().collect::<Vec<_>>();
+        let mut names = match S::schema(Default::default()) {
+            Schema::Struct(fields) => fields
+                .into_iter()
+                .map(|field| field.name)
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        keys.sort();
+        names.sort();
+        assert_eq!(names, keys);
+
+        let names = match E::schema(Default::default()) {
+            Schema::Enum(variants) => variants
+                .into_iter()
+                .map(|variant| variant.name)
+                .collect::<Vec<_>>(),
+            _ => unreachable!(),
+        };
+        let json = variants
+            .iter()
+            .map(|variant| {
+                let json = serde_json::to_value(variant).unwrap();
+                json.as_str().unwrap().to_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, json);
+    }
+
+    check::<LowerStruct, _>([LowerEnum::PlainUser, LowerEnum::X]);
+    check::<UpperStruct, _>([UpperEnum::PlainUser, UpperEnum::X]);
+    check::<PascalStruct, _>([PascalEnum::PlainUser, PascalEnum::X]);
+    check::<CamelStruct, _>([CamelEnum::PlainUser, CamelEnum::X]);
+    check::<SnakeStruct, _>([SnakeEnum::PlainUser, SnakeEnum::X]);
+    check::<ScreamingSnakeStruct, _>([ScreamingSnakeEnum::PlainUser, ScreamingSnakeEnum::X]);
+    check::<KebabStruct, _>([KebabEnum::PlainUser, KebabEnum::X]);
+    check::<ScreamingKebabStruct, _>([ScreamingKebabEnum::PlainUser, ScreamingKebabEnum::X]);
+    assert_eq!(
+        PascalStruct::schema(Default::default()),
+        schema!(struct {
+            (UserId: u8),
+            (X: u8),
+        }),
+    );
+}
+
+#[test]
+fn derive_errors_test() {
+    // each case in ui/ is a type the derives reject, with the expected
+    // compiler output next to it
+    trybuild::TestCases::new().compile_fail("ui/*.rs");
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub struct Overridden {
+    #[serde(with = "millis")]
+    #[schema(as = u64)]
+    timeout: std::time::Duration,
+    #[serde(with = "byte_buf")]
+    #[schema(bytes)]
+    blob: Vec<u8>,
+    #[schema(fixed_len = 3)]
+    rgb: Vec<u8>,
+    #[schema(with = versioned_schema)]
+    version: (u8, String),
+    color: Color,
+}
+
+fn versioned_schema(stack: RecurseStack) -> Schema {
+    schema!(tuple { (u8), (%String::schema(stack)) })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+#[serde(from = "u32", into = "u32")]
+#[schema(as = u32)]
+pub struct Color {
+    r: u8,
+    g: u8,
+    b: u8,
+}
+
+impl From<u32> for Color {
+    fn from(n: u32) -> Self {
+        Color { r: (n >> 16) as u8, g: (n >> 8) as u8, b: n as u8 }
+    }
+}
+
+impl From<Color> for u32 {
+    fn from(c: Color) -> Self {
+        (c.r as u32) << 16 | (c.g as u32) << 8 | c.b as u32
+    }
+}
+
+mod millis {
+    use serde::{Serialize, Deserialize, Serializer, Deserializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+        (d.as_millis() as u64).serialize(s)
+    }
+
+    pub fn deserialize<'d, D: Deserializer<'d>>(d: D) -> Result<Duration, D::Error> {
+        u64::deserialize(d).map(Duration::from_millis)
+    }
+}
+
+mod byte_buf {
+    use serde::{Serializer, Deserializer, de::{Visitor, Error}};
+    use std::fmt::{self, Formatter};
+
+    pub fn serialize<S: Serializer>(b: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(b)
+    }
+
+    pub fn deserialize<'d, D: Deserializer<'d>>(d: D) -> Result<Vec<u8>, D::Error> {
+        struct ByteBufVisitor;
+
+        impl<'d> Visitor<'d> for ByteBufVisitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut Formatter) -> fmt::Result {
+                f.write_str("bytes")
+            }
+
+            fn visit_bytes<E: Error>(self, b: &[u8]) -> Result<Vec<u8>, E> {
+                Ok(b.to_vec())
+            }
+
+            fn visit_byte_buf<E: Error>(self, b: Vec<u8>) -> Result<Vec<u8>, E> {
+                Ok(b)
+            }
+        }
+
+        d.deserialize_byte_buf(ByteBufVisitor)
+    }
+}
+
+#[test]
+fn schema_attrs_test() {
+    assert_eq!(
+        Overridden::schema(Default::default()),
+        schema!(struct {
+            (timeout: u64),
+            (blob: bytes),
+            (rgb: seq(3)(u8)),
+            (version: tuple { (u8), (str) }),
+            (color: u32),
+        }),
+    );
+    round_trip_test(Overridden {
+        timeout: std::time::Duration::from_millis(1500),
+        blob: vec![1, 2, 3, 4],
+        rgb: vec![255, 0, 128],
+        version: (2, "beta".into()),
+        color: Color { r: 1, g: 2, b: 3 },
+    });
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+pub struct Native {
+    #[serde(with = "byte_buf")]
+    #[schema(bytes)]
+    blob: Vec<u8>,
+    #[schema(fixed_len = 3)]
+    rgb: Vec<u8>,
+    #[serde(skip_serializing_if = "is_zero", default)]
+    count: Option<u32>,
+    range: std::ops::Range<usize>,
+    bound: std::ops::Bound<i64>,
+    shape: NativeShape,
+    #[serde(skip, default = "default_scale")]
+    scale: u8,
+}
+
+fn is_zero(n: &Option<u32>) -> bool {
+    *n == Some(0)
+}
+
+fn default_scale() -> u8 {
+    10
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+pub enum NativeShape {
+    Point,
+    Circle(f32),
+    Line((i8, i8), (i8, i8)),
+    Rect {
+        #[serde(rename = "w")]
+        width: u16,
+        height: u16,
+    },
+    #[serde(skip)]
+    Cached(std::cell::Cell<u8>),
+}
+
+#[test]
+fn native_coding_test() {
+    native_round_trip_test(Test1 {
+        foo: 500,
+        bar: "hello world".into(),
+        baz: [7; 4],
+        a: (),
+        b: (74,),
+        c: (72, 4),
+        d: Test1StructUnit,
+        e: Test1Struct0Tuple(),
+        f: Test1StructNewtype(3.5),
+        g: Test1Struct2Tuple(4.2, 2.6),
+        h: 'f',
+    });
+    native_round_trip_test(BinaryTree::Branch {
+        value: 5,
+        left: Box::new(BinaryTree::Leaf(2)),
+        right: Box::new(BinaryTree::Leaf(7)),
+    });
+    native_round_trip_test(Page {
+        items: vec![Tree::Node { key: 1, value: "a".to_owned(), children: vec![Tree::Empty] }],
+        next: Some(Box::new(Page { items: vec![], next: None })),
+    });
+    native_round_trip_test(Renamed {
+        user_id: 1,
+        kind: RenamedKind::Admin { access_level: 3 },
+        cache: None,
+        note: Some("hi".into()),
+        r#ref: Meters { value: 2.5, _unit: () },
+    });
+    for shape in [
+        NativeShape::Point,
+        NativeShape::Circle(1.5),
+        NativeShape::Line((0, 1), (2, 3)),
+        NativeShape::Rect { width: 4, height: 5 },
+    ] {
+        native_round_trip_test(Native {
+            blob: vec![1, 2, 3, 4],
+            rgb: vec![255, 0, 128],
+            count: Some(3),
+            range: 2..10,
+            bound: std::ops::Bound::Excluded(-4),
+            shape,
+            scale: 10,
+        });
+    }
+
+    // skip_serializing_if is followed, and decodes as none, and skipped
+    // fields decode as their default
+    let schema = Native::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    let val = Native {
+        blob: vec![],
+        rgb: vec![1, 2, 3],
+        count: Some(0),
+        range: 0..0,
+        bound: std::ops::Bound::Unbounded,
+        shape: NativeShape::Point,
+        scale: 0,
+    };
+    val.encode(&mut encoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    let alloc = coder.into_alloc();
+    let mut coder = CoderState::new(&schema, alloc, None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    let decoded = Native::decode(&mut decoder).unwrap();
+    assert_eq!((decoded.count, decoded.scale), (None, 10));
+
+    // skipped variants and wrong lengths can't be encoded
+    let schema = NativeShape::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    let e = NativeShape::Cached(Default::default()).encode(&mut encoder).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+    let schema = Native::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    let e = Native { rgb: vec![1, 2], ..val }.encode(&mut encoder).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+}
+
+#[cfg(test)]
+fn serde_encode_with<T: Serialize>(schema: &Schema, val: &T) -> error::Result<Vec<u8>> {
+    let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    val.serialize(&mut encoder)?;
+    coder.is_finished_or_err()?;
+    Ok(buf)
+}
+
+#[test]
+fn range_bound_encoding_test() {
+    use std::ops::{Bound, Range};
+
+    // serde writes a range's fields as start and end, which the old schema
+    // named begin and end
+    let old = schema!(struct {
+        (begin: u32),
+        (end: u32),
+    });
+    let e = serde_encode_with(&old, &(1u32..2)).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+    let new = Range::<u32>::schema(Default::default());
+    assert_eq!(
+        serde_encode_with(&new, &(1u32..2)).unwrap(),
+        vec![1, 2],
+    );
+
+    // serde's variant order is Unbounded, Included, Excluded, which the old
+    // schema ordered Included, Excluded, Unbounded
+    let old = schema!(enum {
+        Included(u32),
+        Excluded(u32),
+        Unbounded(unit),
+    });
+    let e = serde_encode_with(&old, &Bound::Included(3u32)).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+    let new = Bound::<u32>::schema(Default::default());
+    assert_eq!(
+        serde_encode_with(&new, &Bound::Included(3u32)).unwrap(),
+        vec![1, 3],
+    );
+    native_round_trip_test(5u32..7);
+    native_round_trip_test(5u32..=7);
+    native_round_trip_test(Bound::Excluded(-4i64));
+    native_round_trip_test(Bound::<i64>::Unbounded);
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema, Encode, Decode)]
+pub struct Blobs {
+    #[serde(with = "serde_bytes")]
+    data: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    key: [u8; 4],
+    bytes: Bytes,
+    plain: [u8; 3],
+    list: Vec<u8>,
+}
+
+#[test]
+fn bytes_test() {
+    assert_eq!(
+        Blobs::schema(Default::default()),
+        schema!(struct {
+            (data: seq(varlen)(u8)),
+            (key: seq(4)(u8)),
+            (bytes: bytes),
+            (plain: seq(3)(u8)),
+            (list: seq(varlen)(u8)),
+        }),
+    );
+    let blobs = Blobs {
+        data: vec![1, 2, 3, 4, 5],
+        key: [9, 8, 7, 6],
+        bytes: Bytes(vec![0xde, 0xad]),
+        plain: [1, 1, 2],
+        list: vec![],
+    };
+    round_trip_test(blobs.clone());
+    native_round_trip_test(blobs);
+
+    // bulk coding still checks the schema and the seq len
+    let schema = schema!(seq(varlen)(u16));
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    encoder.begin_var_len_seq(2).unwrap();
+    let e = encoder.encode_u8_seq_elems(&[1, 2]).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+    let schema = schema!(seq(varlen)(u8));
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    encoder.begin_var_len_seq(2).unwrap();
+    let e = encoder.encode_u8_seq_elems(&[1, 2, 3]).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::ApiUsage);
+    encoder.encode_u8_seq_elems(&[1]).unwrap();
+    encoder.begin_seq_elem().unwrap();
+    encoder.encode_u8(2).unwrap();
+    encoder.finish_seq().unwrap();
+    coder.is_finished_or_err().unwrap();
+}
+
+#[test]
+fn validate_ok_test() {
+    Schema::schema(Default::default()).validate().unwrap();
+    BinaryTree::schema(Default::default()).validate().unwrap();
+    Test2Outer::schema(Default::default()).validate().unwrap();
+    // recurse resolving against a coding stack deeper than the schema tree
+    schema!(tuple {
+        (tuple {
+            (enum {
+                Stop(unit),
+                Go(tuple {
+                    (recurse(3)),
+                }),
+            }),
+            (option(recurse(3))),
+        }),
+    }).validate().unwrap();
+}
+
+#[test]
+fn validate_issues_test() {
+    use binschema::path::{Path, PathSegment};
+
+    let schema = schema!(struct {
+        (a: recurse(0)),
+        (b: option(recurse(5))),
+        (a: enum {}),
+        (c: struct {
+            (next: recurse(1)),
+        }),
+        (d: enum {
+            X(unit),
+            X(struct {
+                (next: option(recurse(2))),
+            }),
+        }),
+    });
+    let issues = schema.validate().unwrap_err();
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    let issues = issues.into_iter()
+        .map(|issue| (issue.path, issue.kind))
+        .collect::<Vec<_>>();
+    let field = |name: &str| PathSegment::Field(name.into());
+    assert_eq!(issues, vec![
+        (
+            Path::from(vec![field("a")]),
+            SchemaIssueKind::ZeroLevelRecurse,
+        ),
+        (
+            Path::from(vec![field("b"), PathSegment::Some]),
+            SchemaIssueKind::RecursePastRoot { level: 5, depth: 2 },
+        ),
+        (
+            Path::new(),
+            SchemaIssueKind::DuplicateFieldName("a".into()),
+        ),
+        (
+            Path::from(vec![field("a")]),
+            SchemaIssueKind::UninhabitedEnum,
+        ),
+        (
+            Path::from(vec![field("d")]),
+            SchemaIssueKind::DuplicateVariantName("X".into()),
+        ),
+        (
+            Path::from(vec![field("c"), field("next")]),
+            SchemaIssueKind::InfiniteRecursion,
+        ),
+    ]);
+}
+
+#[test]
+fn compat_test() {
+    use binschema::path::{Path, PathSegment};
+
+    let writer = schema!(struct {
+        (id: u16),
+        (name: str),
+        (removed: bool),
+        (tags: seq(4)(str)),
+        (kind: enum {
+            A(unit),
+            B(i32),
+        }),
+        (tree: enum {
+            Leaf(u8),
+            Branch(seq(2)(recurse(2))),
+        }),
+    });
+    let reader = schema!(struct {
+        (kind: enum {
+            B(i64),
+            C(unit),
+            A(unit),
+        }),
+        (name: bytes),
+        (id: option(u32)),
+        (added: option(str)),
+        (tags: seq(varlen)(str)),
+        (tree: enum {
+            Branch(seq(varlen)(recurse(2))),
+            Leaf(u32),
+        }),
+    });
+    check_compat(&writer, &reader).unwrap();
+
+    let issues = check_compat(&reader, &writer).unwrap_err();
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    let issues = issues.into_iter()
+        .map(|issue| (issue.path, issue.kind))
+        .collect::<Vec<_>>();
+    let field = |name: &str| PathSegment::Field(name.into());
+    assert_eq!(issues, vec![
+        (
+            Path::from(vec![field("id")]),
+            IncompatibilityKind::Mismatch {
+                writer: "option(..)",
+                reader: "u16",
+            },
+        ),
+        (
+            Path::from(vec![field("name")]),
+            IncompatibilityKind::Mismatch {
+                writer: "bytes",
+                reader: "str",
+            },
+        ),
+        (
+            Path::new(),
+            IncompatibilityKind::MissingField("removed".into()),
+        ),
+        (
+            Path::from(vec![field("tags")]),
+            IncompatibilityKind::SeqLen {
+                writer: None,
+                reader: Some(4),
+            },
+        ),
+        (
+            Path::from(vec![field("kind"), PathSegment::Variant("B".into())]),
+            IncompatibilityKind::Narrowed {
+                writer: ScalarType::I64,
+                reader: ScalarType::I32,
+            },
+        ),
+        (
+            Path::from(vec![field("kind")]),
+            IncompatibilityKind::MissingVariant("C".into()),
+        ),
+        (
+            Path::from(vec![field("tree"), PathSegment::Variant("Branch".into())]),
+            IncompatibilityKind::SeqLen {
+                writer: None,
+                reader: Some(2),
+            },
+        ),
+        (
+            Path::from(vec![field("tree"), PathSegment::Variant("Leaf".into())]),
+            IncompatibilityKind::Narrowed {
+                writer: ScalarType::U32,
+                reader: ScalarType::U8,
+            },
+        ),
+    ]);
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub struct Test3V1 {
+    id: u16,
+    name: String,
+    removed: (u32, Vec<String>),
+    kind: Test3KindV1,
+    tree: BinaryTree,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub enum Test3KindV1 {
+    A,
+    B(i32),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub struct Test3V2 {
+    kind: Test3KindV2,
+    tree: BinaryTree,
+    name: String,
+    id: Option<u64>,
+    added: Option<String>,
+    added_list: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, KnownSchema)]
+pub enum Test3KindV2 {
+    C,
+    B(i64),
+    A,
+}
+
+#[test]
+fn resolving_decoder_test() {
+    let writer = Test3V1::schema(Default::default());
+    let reader = Test3V2::schema(Default::default());
+    check_compat(&writer, &reader).unwrap();
+
+    let tree = BinaryTree::Branch {
+        value: 5,
+        left: Box::new(BinaryTree::Leaf(2)),
+        right: Box::new(BinaryTree::Leaf(7)),
+    };
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&writer, CoderStateAlloc::new(), None);
+    Test3V1 {
+        id: 1234,
+        name: "hello".into(),
+        removed: (7, vec!["a".into(), "b".into()]),
+        kind: Test3KindV1::B(-3),
+        tree: tree.clone(),
+    }.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    coder.is_finished_or_err().unwrap();
+
+    let expected = Test3V2 {
+        kind: Test3KindV2::B(-3),
+        tree,
+        name: "hello".into(),
+        id: Some(1234),
+        added: None,
+        added_list: Vec::new(),
+    };
+
+    // serde
+    let mut coder = CoderState::new(&reader, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new_resolving(&mut coder, &writer, &mut read);
+    let val = Test3V2::deserialize(&mut decoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+    assert_eq!(val, expected);
+
+    // value
+    let mut coder = CoderState::new(&reader, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new_resolving(&mut coder, &writer, &mut read);
+    let val = value::Value::decode_from(&mut decoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+
+    let mut expected_buf = Vec::new();
+    let mut coder = CoderState::new(&reader, CoderStateAlloc::new(), None);
+    expected.serialize(&mut Encoder::new(&mut coder, &mut expected_buf)).unwrap();
+    let mut coder = CoderState::new(&reader, CoderStateAlloc::new(), None);
+    let expected_val = value::Value::decode_from(
+        &mut Decoder::new(&mut coder, &mut expected_buf.as_slice())
+    ).unwrap();
+    assert_eq!(val, expected_val);
+}
+
+#[test]
+fn skip_test() {
+    let tree = BinaryTree::Branch {
+        value: 5,
+        left: Box::new(BinaryTree::Leaf(2)),
+        right: Box::new(BinaryTree::Leaf(7)),
+    };
+    let val = (tree, [7u16; 32], "hello".to_owned(), 42u32);
+    let schema = <(BinaryTree, [u16; 32], String, u32)>::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    val.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    coder.is_finished_or_err().unwrap();
+
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    decoder.begin_tuple().unwrap();
+    for _ in 0..3 {
+        decoder.begin_tuple_elem().unwrap();
+        decoder.skip().unwrap();
+    }
+    decoder.begin_tuple_elem().unwrap();
+    assert_eq!(decoder.decode_u32().unwrap(), 42);
+    decoder.finish_tuple().unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    let (_, _, s, n) = <(
+        serde::de::IgnoredAny,
+        serde::de::IgnoredAny,
+        String,
+        u32,
+    )>::deserialize(&mut decoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+    assert_eq!((s.as_str(), n), ("hello", 42));
+
+    // enum ordinals are checked even where the variants are all one size
+    let schema = schema!(seq(2)(enum { A(u8), B(u8) }));
+    for (buf, ok) in [([0, 1, 1, 2], true), ([0, 1, 2, 2], false)] {
+        let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+        let mut read = &buf[..];
+        let result = Decoder::new(&mut coder, &mut read).skip();
+        assert_eq!(result.is_ok(), ok, "{:?}", result);
+    }
+    let schema = schema!(seq(2)(enum { A(u16) }));
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = &[1, 2, 3, 4][..];
+    Decoder::new(&mut coder, &mut read).skip().unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+}
+
+#[test]
+fn borrow_decoder_test() {
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Borrowed<'a> {
+        name: &'a str,
+        data: &'a [u8],
+        #[serde(borrow)]
+        tags: Vec<&'a str>,
+    }
+
+    let schema = schema!(struct {
+        (name: str),
+        (data: bytes),
+        (tags: seq(varlen)(str)),
+    });
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    encoder.begin_struct().unwrap();
+    encoder.begin_struct_field("name").unwrap();
+    encoder.encode_str("hello").unwrap();
+    encoder.begin_struct_field("data").unwrap();
+    encoder.encode_bytes(&[1, 2, 3]).unwrap();
+    encoder.begin_struct_field("tags").unwrap();
+    encoder.begin_var_len_seq(2).unwrap();
+    for tag in ["a", "bc"] {
+        encoder.begin_seq_elem().unwrap();
+        encoder.encode_str(tag).unwrap();
+    }
+    encoder.finish_seq().unwrap();
+    encoder.finish_struct().unwrap();
+    coder.is_finished_or_err().unwrap();
+
+    let in_buf = |ptr: *const u8| buf.as_ptr_range().contains(&ptr);
+
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = BorrowDecoder::new(&mut coder, &mut read);
+    let val = Borrowed::deserialize(&mut decoder).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert!(read.is_empty());
+    assert_eq!(val, Borrowed {
+        name: "hello",
+        data: &[1, 2, 3],
+        tags: vec!["a", "bc"],
+    });
+    assert!(in_buf(val.name.as_ptr()));
+    assert!(in_buf(val.data.as_ptr()));
+    assert!(val.tags.iter().all(|tag| in_buf(tag.as_ptr())));
+
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = BorrowDecoder::new(&mut coder, &mut read);
+    decoder.begin_struct().unwrap();
+    decoder.begin_struct_field("name").unwrap();
+    let name = decoder.decode_borrowed_str().unwrap();
+    decoder.begin_struct_field("data").unwrap();
+    let data = decoder.decode_borrowed_bytes().unwrap();
+    assert_eq!((name, data), ("hello", &[1u8, 2, 3][..]));
+    assert!(in_buf(name.as_ptr()));
+}
+
+#[test]
+fn decode_limits_test() {
+    fn decode_with<T>(
+        schema: &Schema,
+        buf: &[u8],
+        limits: DecodeLimits,
+        f: impl FnOnce(&mut Decoder<&[u8]>) -> Result<T, error::Error>,
+    ) -> Result<T, error::Error> {
+        let mut coder = CoderState::new(schema, CoderStateAlloc::new(), None);
+        let mut read = buf;
+        let mut decoder = Decoder::new(&mut coder, &mut read);
+        decoder.set_limits(limits);
+        f(&mut decoder)
+    }
+
+    fn decode<T: for<'d> Deserialize<'d>>(
+        schema: &Schema,
+        buf: &[u8],
+        limits: DecodeLimits,
+    ) -> Result<T, error::Error> {
+        decode_with(schema, buf, limits, |d| T::deserialize(d))
+    }
+
+    // a huge len prefix with nothing after it fails without allocating
+    let bogus = [0xff, 0xff, 0xff, 0xff, 0x0f];
+    let err = decode_with(
+        &schema!(bytes),
+        &bogus,
+        Default::default(),
+        |d| d.decode_bytes(),
+    ).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::Io);
+    let limits = DecodeLimits {
+        max_str_bytes_len: Some(100),
+        ..Default::default()
+    };
+    let err = decode_with(&schema!(bytes), &bogus, limits, |d| d.decode_bytes())
+        .unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    let err = decode::<Vec<u8>>(&schema!(seq(varlen)(u8)), &bogus, DecodeLimits {
+        max_seq_len: Some(100),
+        ..Default::default()
+    }).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+
+    let schema = String::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    "hello world".serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    let limits = DecodeLimits {
+        max_total_bytes: Some(buf.len() as u64),
+        ..Default::default()
+    };
+    assert_eq!(decode::<String>(&schema, &buf, limits).unwrap(), "hello world");
+    let limits = DecodeLimits {
+        max_total_bytes: Some(buf.len() as u64 - 1),
+        ..Default::default()
+    };
+    let err = decode::<String>(&schema, &buf, limits).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+
+    // deeply nested recursion
+    let mut tree = BinaryTree::Leaf(0);
+    for i in 0..10 {
+        tree = BinaryTree::Branch {
+            value: i,
+            left: Box::new(tree),
+            right: Box::new(BinaryTree::Leaf(i)),
+        };
+    }
+    let schema = BinaryTree::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    tree.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    let limits = |max_depth| DecodeLimits {
+        max_depth: Some(max_depth),
+        ..Default::default()
+    };
+    assert_eq!(decode::<BinaryTree>(&schema, &buf, limits(100)).unwrap(), tree);
+    let err = decode::<BinaryTree>(&schema, &buf, limits(10)).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    let err = decode::<serde::de::IgnoredAny>(&schema, &buf, limits(10))
+        .unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+
+    // skipping is held to the same limits as decoding
+    let schema = schema!(seq(varlen)(u32));
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    vec![1000u32; 1000].serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    let limits = DecodeLimits {
+        max_seq_len: Some(10),
+        ..Default::default()
+    };
+    decode_with(&schema, &buf, Default::default(), |d| d.skip()).unwrap();
+    let err = decode_with(&schema, &buf, limits, |d| d.skip()).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    let err = decode_with(&schema, &buf, limits, |d| value::Value::decode_from(d))
+        .unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    let err = decode::<serde::de::IgnoredAny>(&schema, &buf, limits).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    let schema = schema!(tuple {
+        (u8),
+        (str),
+    });
+    let buf = [1, 5, b'h', b'e', b'l', b'l', b'o'];
+    let limits = DecodeLimits {
+        max_str_bytes_len: Some(4),
+        ..Default::default()
+    };
+    decode_with(&schema, &buf, Default::default(), |d| d.skip()).unwrap();
+    let err = decode_with(&schema, &buf, limits, |d| d.skip()).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+
+    // a huge len prefix of zero-width elems is skipped without spinning
+    let schema = schema!(seq(varlen)(enum {
+        A(unit),
+    }));
+    let huge = [0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x7f];
+    let limits = DecodeLimits {
+        max_seq_len: Some(100),
+        ..Default::default()
+    };
+    let err = decode_with(&schema, &huge, limits, |d| d.skip()).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::LimitExceeded);
+    decode_with(&schema, &huge, Default::default(), |d| d.skip()).unwrap();
+}
+
+#[test]
+fn async_coder_test() {
+    let tree = BinaryTree::Branch {
+        value: 7,
+        left: Box::new(BinaryTree::Leaf(1)),
+        right: Box::new(BinaryTree::Leaf(1 << 20)),
+    };
+    let schema = BinaryTree::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    tree.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let value = value::Value::decode_from(&mut Decoder::new(&mut coder, &mut read))
+        .unwrap();
+
+    let rt = tokio::runtime::Builder::new_current_thread().build().unwrap();
+    rt.block_on(async {
+        let mut buf2 = Vec::new();
+        let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+        value
+            .encode_to_async(&mut AsyncEncoder::new(&mut coder, &mut buf2))
+            .await
+            .unwrap();
+        coder.is_finished_or_err().unwrap();
+        assert_eq!(buf, buf2);
+
+        let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+        let mut read = buf2.as_slice();
+        let mut decoder = AsyncDecoder::new(&mut coder, &mut read);
+        let value2 = value::Value::decode_from_async(&mut decoder).await.unwrap();
+        coder.is_finished_or_err().unwrap();
+        assert_eq!(value, value2);
+
+        // truncated input is an error
+        let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+        let mut read = &buf2[..buf2.len() - 1];
+        let mut decoder = AsyncDecoder::new(&mut coder, &mut read);
+        assert!(value::Value::decode_from_async(&mut decoder).await.is_err());
+    });
+}
+
+#[test]
+fn push_decoder_test() {
+    #[derive(Serialize, KnownSchema)]
+    struct Msg {
+        id: u64,
+        name: String,
+        tags: Vec<Option<u8>>,
+        tree: BinaryTree,
+    }
+
+    let msg = Msg {
+        id: 1 << 40,
+        name: "hello world".into(),
+        tags: vec![Some(3), None],
+        tree: BinaryTree::Branch {
+            value: 1000,
+            left: Box::new(BinaryTree::Leaf(1)),
+            right: Box::new(BinaryTree::Leaf(2)),
+        },
+    };
+    let schema = Msg::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    msg.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+
+    // feed in chunks of the given size, with some trailing bytes
+    let mut input = buf.clone();
+    input.extend([7, 7]);
+    let decode_chunked = |chunk_size: usize| {
+        let coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+        let mut decoder = PushDecoder::new(coder);
+        let mut chunks = input.chunks(chunk_size);
+        let mut events = Vec::new();
+        let mut need_mores = 0;
+        loop {
+            match decoder.next_event().unwrap() {
+                PushStep::Event(event) => events.push(event),
+                PushStep::NeedMore => {
+                    need_mores += 1;
+                    decoder.feed(chunks.next().unwrap());
+                }
+                PushStep::Finished => break,
+            }
+        }
+        decoder.coder_state().is_finished_or_err().unwrap();
+        let remaining = decoder.remaining().len()
+            + chunks.map(|chunk| chunk.len()).sum::<usize>();
+        assert_eq!(remaining, 2);
+        (events, need_mores)
+    };
+
+    let (events, _) = decode_chunked(input.len());
+    let tree_events = [
+        PushEvent::Enum { variant_ord: 0, variant_name: "Branch" },
+        PushEvent::BeginStruct,
+        PushEvent::StructField("value"),
+        PushEvent::Scalar(value::ScalarValue::U32(1000)),
+        PushEvent::StructField("left"),
+        PushEvent::Enum { variant_ord: 1, variant_name: "Leaf" },
+        PushEvent::Scalar(value::ScalarValue::U32(1)),
+        PushEvent::StructField("right"),
+        PushEvent::Enum { variant_ord: 1, variant_name: "Leaf" },
+        PushEvent::Scalar(value::ScalarValue::U32(2)),
+        PushEvent::FinishStruct,
+    ];
+    let mut expected = vec![
+        PushEvent::BeginStruct,
+        PushEvent::StructField("id"),
+        PushEvent::Scalar(value::ScalarValue::U64(1 << 40)),
+        PushEvent::StructField("name"),
+        PushEvent::Str("hello world".into()),
+        PushEvent::StructField("tags"),
+        PushEvent::BeginSeq { len: 2 },
+        PushEvent::OptionSome,
+        PushEvent::Scalar(value::ScalarValue::U8(3)),
+        PushEvent::OptionNone,
+        PushEvent::FinishSeq,
+        PushEvent::StructField("tree"),
+    ];
+    expected.extend(tree_events);
+    expected.push(PushEvent::FinishStruct);
+    assert_eq!(events, expected);
+
+    // partial var len ints and strs are resumed
+    for chunk_size in [1, 2, 3, 5] {
+        let (chunked_events, need_mores) = decode_chunked(chunk_size);
+        assert_eq!(chunked_events, events);
+        assert!(need_mores >= buf.len() / chunk_size);
+    }
+
+    // errors still break it
+    let coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut decoder = PushDecoder::new(coder);
+    decoder.set_limits(DecodeLimits {
+        max_str_bytes_len: Some(4),
+        ..Default::default()
+    });
+    decoder.feed(&buf);
+    let mut err = None;
+    for _ in 0..events.len() {
+        if let Err(e) = decoder.next_event() {
+            err = Some(e);
+            break;
+        }
+    }
+    assert_eq!(err.unwrap().kind(), error::ErrorKind::LimitExceeded);
+    assert!(decoder.next_event().is_err());
+}
+
+#[test]
+fn encoded_len_test() {
+    let val = Test1 {
+        foo: 1 << 20,
+        bar: "hello".into(),
+        baz: [1, -2, 3, -4],
+        a: (),
+        b: (-100000,),
+        c: (i32::MIN, -1),
+        d: Test1StructUnit,
+        e: Test1Struct0Tuple(),
+        f: Test1StructNewtype(0.5),
+        g: Test1Struct2Tuple(1.0, -2.0),
+        h: '\u{10FFFF}',
+    };
+    let schema = Test1::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    val.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+
+    let mut counter = SizeCounter::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    val.serialize(&mut Encoder::new(&mut coder, &mut counter)).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert_eq!(counter.len(), buf.len());
+
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let value = value::Value::decode_from(&mut Decoder::new(&mut coder, &mut read))
+        .unwrap();
+    assert_eq!(value.encoded_len(&schema).unwrap(), buf.len());
+
+    // schema checks still apply
+    assert!(value.encoded_len(&schema!(u32)).is_err());
+}
+
+#[test]
+fn error_path_test() {
+    #[derive(Serialize, KnownSchema)]
+    struct Users<Z> {
+        users: Vec<User<Z>>,
+    }
+
+    #[derive(Serialize, KnownSchema)]
+    struct User<Z> {
+        name: String,
+        address: Option<Address<Z>>,
+    }
+
+    #[derive(Serialize, KnownSchema)]
+    struct Address<Z> {
+        zip: Z,
+    }
+
+    let user = |zip| User {
+        name: "a".into(),
+        address: Some(Address { zip }),
+    };
+    let users = Users::<u32> {
+        users: vec![user(0x41), user(0x42), user(0x43), user(0xD800), user(0x44)],
+    };
+    let schema = Users::<u32>::schema(Default::default());
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    users.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+
+    let schema = Users::<char>::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let err = value::Value::decode_from(&mut Decoder::new(&mut coder, &mut read))
+        .unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::MalformedData);
+    assert_eq!(err.path().unwrap().to_string(), ".users[3].address.Some.zip");
+    assert!(err.to_string().contains("path: .users[3].address.Some.zip"));
+    assert_eq!(err.offset(), Some(16));
+
+    // encoding
+    let schema = BinaryTree::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    encoder.begin_enum(0, "Branch").unwrap();
+    encoder.begin_struct().unwrap();
+    encoder.begin_struct_field("value").unwrap();
+    encoder.encode_u32(1).unwrap();
+    encoder.begin_struct_field("left").unwrap();
+    let err = encoder.encode_str("x").unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::SchemaNonConformance);
+    assert_eq!(err.path().unwrap().to_string(), ".Branch.left");
+    assert_eq!(coder.path().to_string(), ".Branch.left");
+}
+
+#[test]
+fn offset_test() {
+    let schema = <(u16, String, bool)>::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    let mut encoder = Encoder::new(&mut coder, &mut buf);
+    encoder.begin_tuple().unwrap();
+    encoder.begin_tuple_elem().unwrap();
+    encoder.encode_u16(7).unwrap();
+    assert_eq!(encoder.position(), 2);
+    encoder.begin_tuple_elem().unwrap();
+    encoder.encode_str("abc").unwrap();
+    assert_eq!(encoder.position(), 6);
+    encoder.begin_tuple_elem().unwrap();
+    let err = encoder.encode_u8(5).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::SchemaNonConformance);
+    assert_eq!(err.offset(), Some(6));
+    assert!(err.to_string().contains("offset: 6"), "{}", err);
+
+    // malformed data is reported at the start of the value
+    buf.push(5);
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    let err = <(u16, String, bool)>::deserialize(&mut decoder).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::MalformedData);
+    assert_eq!(err.offset(), Some(6));
+    assert_eq!(decoder.position(), 7);
+
+    // as are IO errors, which also get a path
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = &buf[..4];
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    let err = <(u16, String, bool)>::deserialize(&mut decoder).unwrap_err();
+    assert_eq!(err.kind(), error::ErrorKind::Io);
+    assert_eq!(err.offset(), Some(2));
+    assert_eq!(err.path().unwrap().to_string(), ".1");
+}
+
+#[test]
+fn schema_text_test() {
+    for schema in [
+        Test1::schema(Default::default()),
+        BinaryTree::schema(Default::default()),
+        schema!(seq(varlen)(enum {
+            A(tuple {}),
+            B(struct {}),
+            C(option(recurse(3))),
+        })),
+        schema!(struct {
+            (a: seq(3)(recurse(2))),
+            (b: recurse(5)),
+        }),
+    ] {
+        let text = schema.to_text();
+        println!("{}", text);
+        assert_eq!(Schema::parse_text(&text).unwrap(), schema);
+        assert_eq!(Schema::parse_text(&text).unwrap().to_text(), text);
+    }
+
+    let text = "
+        # a tree
+        root seq(varlen)(Tree)
+
+        type Tree = enum {
+            Branch(struct {
+                value: u32,
+                left: Tree,
+                \"right side\": Tree,
+            }),
+            Leaf(u32),
+        }
+    ";
+    let schema: Schema = text.parse().unwrap();
+    let mut branch = schema!(struct {
+        (value: u32),
+        (left: recurse(2)),
+        (right: recurse(2)),
+    });
+    if let Schema::Struct(ref mut fields) = branch {
+        fields[2].name = "right side".into();
+    }
+    assert_eq!(schema, schema!(seq(varlen)(enum {
+        Branch(%branch),
+        Leaf(u32),
+    })));
+    assert_eq!(schema.to_text(), "\
+root seq(varlen)(T1)
+
+type T1 = enum {
+    Branch(struct {
+        value: u32,
+        left: T1,
+        \"right side\": T1,
+    }),
+    Leaf(u32),
+}
+");
+
+    // mutual recursion
+    let text = "
+        root A
+        type A = struct { b: B }
+        type B = option(A)
+    ";
+    assert_eq!(
+        Schema::parse_text(text).unwrap(),
+        schema!(struct { (b: option(recurse(2))) }),
+    );
+    let text = "
+        root A
+        type A = struct { x: B }
+        type B = enum { Stop(unit), ToA(option(A)), ToB(B) }
+    ";
+    let schema = Schema::parse_text(text).unwrap();
+    assert_eq!(schema, schema!(struct {
+        (x: enum {
+            Stop(unit),
+            ToA(option(struct { (x: recurse(3)) })),
+            ToB(recurse(1)),
+        }),
+    }));
+    assert_eq!(Schema::parse_text(&schema.to_text()).unwrap(), schema);
+    let text = "
+        root Expr
+        type Expr = enum { Lit(u32), Neg(Expr), Call(Call) }
+        type Call = struct { name: str, args: seq(varlen)(Expr) }
+    ";
+    let schema = Schema::parse_text(text).unwrap();
+    schema.validate().unwrap();
+    assert_eq!(Schema::parse_text(&schema.to_text()).unwrap(), schema);
+
+    let err = |text: &str| {
+        let e = Schema::parse_text(text).unwrap_err();
+        (e.line, e.col, e.message)
+    };
+    assert_eq!(err("root struct {\n  a u32 }").0, 2);
+    assert_eq!(err("root struct {\n  a u32 }").1, 5);
+    assert_eq!(err("root Foo").2, "undefined type `Foo`");
+    assert_eq!(
+        err("root A\ntype A = B\ntype B = A"),
+        (2, 6, "type `A` refers to itself without nesting".into()),
+    );
+    assert_eq!(
+        err("root A\ntype A = enum { Stop(unit), ToA(A), ToB(B) }\n\
+            type B = enum { Stop(unit), ToB(B), ToA(A) }"),
+        (2, 6, "recursion to type `A` can't be expressed as a schema".into()),
+    );
+    assert_eq!(err("type A = u8").2, "missing `root`");
+    assert_eq!(err("root u8\ntype u8 = u8").2, "cannot define type with reserved name `u8`");
+
+    // deep nesting fails instead of overflowing the stack
+    let deep = |n: usize| format!("root {}u8{}", "option(".repeat(n), ")".repeat(n));
+    Schema::parse_text(&deep(100)).unwrap();
+    assert_eq!(err(&deep(100_000)).2, "types nested deeper than 256");
+}
+
+#[test]
+fn named_schema_test() {
+    let schema = BinaryTree::schema(Default::default());
+    let named = schema.to_named();
+    assert_eq!(named.root, NamedType::Ref("T1".into()));
+    assert_eq!(named.defs.len(), 1);
+    assert_eq!(named.to_schema().unwrap(), schema);
+
+    // mutual recursion, with names kept
+    let named = NamedSchema::parse_text("
+        root Expr
+        type Expr = enum {
+            Lit(i64),
+            Neg(Expr),
+            Call(Call),
+        }
+        type Call = struct {
+            name: str,
+            args: seq(varlen)(Expr),
+        }
+    ").unwrap();
+    assert_eq!(named.defs["Call"], NamedType::Struct(vec![
+        ("name".into(), NamedType::Str),
+        ("args".into(), NamedType::Seq {
+            len: None,
+            inner: Box::new(NamedType::Ref("Expr".into())),
+        }),
+    ]));
+    assert_eq!(NamedSchema::parse_text(&named.to_text()).unwrap(), named);
+    let schema = named.to_schema().unwrap();
+    assert_eq!(schema, schema!(enum {
+        Lit(i64),
+        Neg(recurse(1)),
+        Call(struct {
+            (name: str),
+            (args: seq(varlen)(recurse(3))),
+        }),
+    }));
+    assert_eq!(schema.to_named().to_schema().unwrap(), schema);
+
+    // names which aren't identifiers are quoted
+    let mut named = NamedSchema {
+        defs: Default::default(),
+        root: NamedType::Option(Box::new(NamedType::Ref("u8".into()))),
+    };
+    named.defs.insert("u8".into(), NamedType::Scalar(ScalarType::U16));
+    assert_eq!(named.to_text(), "root option(\"u8\")\n\ntype \"u8\" = u16\n");
+    assert_eq!(NamedSchema::parse_text(&named.to_text()).unwrap(), named);
+
+    let named = NamedSchema {
+        defs: Default::default(),
+        root: NamedType::Ref("A".into()),
+    };
+    assert_eq!(named.to_schema(), Err(NamedSchemaError::UndefinedRef("A".into())));
+    let mut named = named;
+    named.defs.insert("A".into(), NamedType::Ref("A".into()));
+    assert_eq!(named.to_schema(), Err(NamedSchemaError::RefWithoutNesting("A".into())));
+    assert_eq!(NamedSchema::parse_text("root A").unwrap_err().message, "undefined type `A`");
+}
+
+#[test]
+fn named_schema_mutual_recursion_test() {
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct A {
+        x: B,
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    enum B {
+        Stop,
+        ToA(Option<Box<A>>),
+        ToB(Box<B>),
+    }
+
+    // B recurses to itself, so refs to A from within B can't reach back past
+    // it, and A is substituted in again
+    let named = NamedSchema::parse_text(r#"
+        root A
+        type A = struct { x: B }
+        type B = enum { Stop(unit), ToA(option(A)), ToB(B) }
+    "#).unwrap();
+    let schema = named.to_schema().unwrap();
+    assert_eq!(schema, schema!(struct {
+        (x: enum {
+            Stop(unit),
+            ToA(option(struct { (x: recurse(3)) })),
+            ToB(recurse(1)),
+        }),
+    }));
+    schema.validate().unwrap();
+    assert_eq!(schema.to_named().to_schema().unwrap(), schema);
+
+    let val = A {
+        x: B::ToB(Box::new(B::ToA(Some(Box::new(A { x: B::Stop }))))),
+    };
+    let mut buf = Vec::new();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    val.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    coder.is_finished_or_err().unwrap();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let val2 = A::deserialize(&mut Decoder::new(&mut coder, &mut read)).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert_eq!(val2, val);
+    assert!(read.is_empty());
+
+    // whereas if A recurses to itself too, each would need to be substituted
+    // into the other forever
+    let err = NamedSchema::parse_text(r#"
+        root A
+        type A = struct { x: B, y: option(A) }
+        type B = enum { Stop(unit), ToA(option(A)), ToB(B) }
+    "#).unwrap().to_schema().unwrap_err();
+    assert!(matches!(err, NamedSchemaError::Inexpressible(_)), "{:?}", err);
+}
+
+#[test]
+fn fingerprint_test() {
+    // pinned, since peers compare fingerprints computed by other builds
+    assert_eq!(Schema::Unit.fingerprint(), 0xd228cb69111a8caf78912b704e4a15ba);
+    let schema = BinaryTree::schema(Default::default());
+    assert_eq!(schema.fingerprint(), schema.clone().fingerprint());
+    assert_eq!(
+        Schema::parse_text(&schema.to_text()).unwrap().fingerprint(),
+        schema.fingerprint(),
+    );
+
+    let schemas = [
+        schema!(u32),
+        schema!(i32),
+        schema!(str),
+        schema!(bytes),
+        schema!(option(u32)),
+        schema!(seq(varlen)(u32)),
+        schema!(seq(0)(u32)),
+        schema!(tuple { (u32) }),
+        schema!(struct { (a: u32) }),
+        schema!(struct { (b: u32) }),
+        schema!(enum { A(u32) }),
+        schema!(struct { (a: u32), (b: u32) }),
+        schema!(struct { (b: u32), (a: u32) }),
+        schema!(option(recurse(1))),
+        schema!(option(recurse(2))),
+        schema,
+    ];
+    for (i, a) in schemas.iter().enumerate() {
+        for b in &schemas[i + 1..] {
+            assert_ne!(a.fingerprint(), b.fingerprint(), "{:?} {:?}", a, b);
+        }
+    }
+}
+
+#[test]
+fn schema_diff_test() {
+    use binschema::path::{Path, PathSegment};
+
+    let tree = BinaryTree::schema(Default::default());
+    assert!(diff_schemas(&tree, &tree).is_empty());
+
+    let old = schema!(struct {
+        (id: u32),
+        (name: str),
+        (tags: seq(4)(str)),
+        (kind: enum {
+            A(unit),
+            B(u8),
+            C(unit),
+        }),
+        (legacy: bool),
+    });
+    let new = schema!(struct {
+        (id: u64),
+        (display_name: str),
+        (tags: seq(varlen)(str)),
+        (kind: enum {
+            A(unit),
+            C(option(unit)),
+            D(i8),
+        }),
+        (note: option(str)),
+        (count: u8),
+    });
+    let diff = diff_schemas(&old, &new);
+    let field = |name: &str| Path::from(vec![PathSegment::Field(name.into())]);
+    let kind_path = field("kind");
+    assert_eq!(diff.changes, vec![
+        SchemaChange {
+            path: field("id"),
+            kind: SchemaChangeKind::ScalarType {
+                old: ScalarType::U32,
+                new: ScalarType::U64,
+            },
+        },
+        SchemaChange {
+            path: Path::new(),
+            kind: SchemaChangeKind::FieldRenamed {
+                old: "name".into(),
+                new: "display_name".into(),
+                has_default: false,
+            },
+        },
+        SchemaChange {
+            path: field("tags"),
+            kind: SchemaChangeKind::SeqLen { old: Some(4), new: None },
+        },
+        SchemaChange {
+            path: kind_path.join(PathSegment::Variant("C".into())),
+            kind: SchemaChangeKind::OptionAdded,
+        },
+        SchemaChange {
+            path: kind_path.clone(),
+            kind: SchemaChangeKind::VariantRemoved("B".into()),
+        },
+        SchemaChange {
+            path: kind_path.clone(),
+            kind: SchemaChangeKind::VariantAdded("D".into()),
+        },
+        SchemaChange {
+            path: Path::new(),
+            kind: SchemaChangeKind::FieldRemoved("legacy".into()),
+        },
+        SchemaChange {
+            path: Path::new(),
+            kind: SchemaChangeKind::FieldAdded {
+                name: "note".into(),
+                has_default: true,
+            },
+        },
+        SchemaChange {
+            path: Path::new(),
+            kind: SchemaChangeKind::FieldAdded {
+                name: "count".into(),
+                has_default: false,
+            },
+        },
+    ]);
+    assert!(diff.is_breaking());
+    assert_eq!(diff.pretty_fmt(), "\
+.:
+    - field \"name\" renamed to \"display_name\", with no default (breaking)
+    - field \"legacy\" removed
+    - field \"note\" added
+    - field \"count\" added, with no default (breaking)
+    .id:
+        - u32 changed to u64
+    .tags:
+        - seq of len 4 changed to seq of variable len
+    .kind:
+        - variant \"B\" removed (breaking)
+        - variant \"D\" added
+        .C:
+            - wrapped in option");
+
+    // a renamed field is read as a removed one and an added one, so is fine
+    // if the reader can default it, but a renamed variant never is
+    let diff = diff_schemas(
+        &schema!(struct {
+            (a: struct { (note: option(str)) }),
+            (b: enum { X(unit), Y(u8) }),
+        }),
+        &schema!(struct {
+            (a: struct { (comment: option(str)) }),
+            (b: enum { X(unit), Z(u8) }),
+        }),
+    );
+    assert_eq!(diff.pretty_fmt(), "\
+.:
+    .a:
+        - field \"note\" renamed to \"comment\"
+    .b:
+        - variant \"Y\" renamed to \"Z\" (breaking)");
+    let diff = diff_schemas(
+        &schema!(tuple { (struct { (note: option(str)) }) }),
+        &schema!(tuple { (struct { (comment: option(str)) }) }),
+    );
+    assert!(!diff.is_breaking());
+    assert_eq!(diff.to_string(), "\
+.0:
+    - field \"note\" renamed to \"comment\"");
+
+    // reordering, and changes below recursion
+    let new_tree = schema!(enum {
+        Leaf(u64),
+        Branch(struct {
+            (value: u32),
+            (right: recurse(2)),
+            (left: recurse(2)),
+        }),
+    });
+    let diff = diff_schemas(&tree, &new_tree);
+    assert_eq!(diff.changes.len(), 3, "{}", diff);
+    assert!(!diff.is_breaking());
+    assert_eq!(diff.changes[1].path, Path::from(vec![
+        PathSegment::Variant("Leaf".into()),
+    ]));
+    assert_eq!(diff.to_string(), "\
+.:
+    - variants reordered from [\"Branch\", \"Leaf\"] to [\"Leaf\", \"Branch\"]
+    .Leaf:
+        - u32 changed to u64
+    .Branch:
+        - fields reordered from [\"value\", \"left\", \"right\"] to [\"value\", \"right\", \"left\"]");
+}
+
+#[test]
+fn json_test() {
+    use binschema::json::{bytes_to_json, json_to_bytes};
+
+    let schema = schema!(struct {
+        (small: u8),
+        (big: u128),
+        (neg: i128),
+        (f: f32),
+        (nan: f64),
+        (c: char),
+        (s: str),
+        (b: bytes),
+        (u: unit),
+        (none: option(u32)),
+        (some: option(u32)),
+        (some_unit: option(unit)),
+        (fixed: seq(2)(bool)),
+        (var: seq(varlen)(i16)),
+        (t: tuple { (i32), (str) }),
+        (e: enum { A(unit), B(u16) }),
+    });
+    let json = r#"{
+  "small": 7,
+  "big": "340282366920938463463374607431768211455",
+  "neg": "-170141183460469231731687303715884105728",
+  "f": 1.1,
+  "nan": "NaN",
+  "c": "é",
+  "s": "hi",
+  "b": "AAEC/w==",
+  "u": null,
+  "none": null,
+  "some": 5,
+  "some_unit": [
+    null
+  ],
+  "fixed": [
+    true,
+    false
+  ],
+  "var": [
+    -1,
+    2
+  ],
+  "t": [
+    -3,
+    "x"
+  ],
+  "e": {
+    "B": 9
+  }
+}"#;
+    let bytes = json_to_bytes(&schema, json).unwrap();
+    assert_eq!(bytes_to_json(&schema, &bytes).unwrap(), json);
+
+    // recursive schemas
+    let schema = BinaryTree::schema(Default::default());
+    let tree = BinaryTree::Branch {
+        value: 5,
+        left: Box::new(BinaryTree::Leaf(2)),
+        right: Box::new(BinaryTree::Leaf(20)),
+    };
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut bytes = Vec::new();
+    tree.serialize(&mut Encoder::new(&mut coder, &mut bytes)).unwrap();
+    let json = bytes_to_json(&schema, &bytes).unwrap();
+    assert_eq!(
+        serde_json::from_str::<serde_json::Value>(&json).unwrap(),
+        serde_json::json!({
+            "Branch": {
+                "value": 5,
+                "left": { "Leaf": 2 },
+                "right": { "Leaf": 20 },
+            },
+        }),
+    );
+    assert_eq!(json_to_bytes(&schema, &json).unwrap(), bytes);
+
+    // errors
+    let e = json_to_bytes(&schema, r#"{"Branch": {"value": 5, "left": {"Leaf": -2}, "right": {"Leaf": 1}}}"#)
+        .unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::SchemaNonConformance);
+    assert_eq!(e.path().unwrap().to_string(), ".Branch.left.Leaf");
+    let e = json_to_bytes(&schema, r#"{"Branch": {"value": 5}}"#).unwrap_err();
+    assert_eq!(e.path().unwrap().to_string(), ".Branch");
+    assert!(e.to_string().contains("missing field \"left\""), "{}", e);
+    let e = json_to_bytes(&schema, r#"{"Leaf": 1"#).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::MalformedData);
+    let e = bytes_to_json(&schema, &[1, 0, 0]).unwrap_err();
+    assert_eq!(e.kind(), error::ErrorKind::MalformedData);
+}
+
+#[test]
+fn hex_dump_test() {
+    let schema = schema!(struct {
+        (id: u16),
+        (name: str),
+        (tag: option(u8)),
+        (items: seq(varlen)(u32)),
+        (kind: enum { A(unit), B(i8), C(unit) }),
+    });
+    let bytes = json::json_to_bytes(
+        &schema,
+        r#"{"id": 300, "name": "hi", "tag": 7, "items": [1, 1000], "kind": {"B": -1}}"#,
+    ).unwrap();
+    let dump = hex_dump(&schema, &bytes);
+    assert!(dump.error.is_none());
+    assert_eq!(dump.to_string(), "\
+00000000  2c 01 02 68 69 01 07 02 ..  - . struct (13 bytes)
+00000000  2c 01                         - .id u16 = 300
+00000002  02                              len = 2
+00000003  68 69                         - .name str \"hi\"
+00000005  01 07                         - .tag option (2 bytes)
+00000005  01                                some
+00000006  07                              - .tag.Some u8 = 7
+00000007  02 01 e8 07                   - .items seq of 2 (4 bytes)
+00000007  02                                len = 2
+00000008  01                              - .items[0] u32 = 1
+00000009  e8 07                           - .items[1] u32 = 1000
+0000000b  01 ff                         - .kind enum (2 bytes)
+0000000b  01                                variant 1 (name = \"B\")
+0000000c  ff                              - .kind.B i8 = -1
+");
+
+    // enums with one variant have 0-byte ordinals
+    let schema = BinaryTree::schema(Default::default());
+    let bytes = [1, 2];
+    let dump = hex_dump(&schema, &bytes);
+    assert_eq!(dump.entries, vec![
+        DumpEntry {
+            range: 0..2,
+            depth: 0,
+            path: path::Path::new(),
+            kind: DumpEntryKind::Enum,
+        },
+        DumpEntry {
+            range: 0..1,
+            depth: 1,
+            path: path::Path::new(),
+            kind: DumpEntryKind::Ordinal { ord: 1, name: "Leaf".into() },
+        },
+        DumpEntry {
+            range: 1..2,
+            depth: 1,
+            path: vec![path::PathSegment::Variant("Leaf".into())].into(),
+            kind: DumpEntryKind::Scalar(value::ScalarValue::U32(2)),
+        },
+    ]);
+
+    // invalid and trailing data
+    let dump = hex_dump(&schema, &[0, 5, 1, 7, 9]);
+    let e = dump.error.as_ref().unwrap();
+    assert!(e.to_string().contains("enum ordinal 9 out of range"), "{}", e);
+    assert_eq!(dump.end, 5);
+    assert_eq!(dump.entries[0].range, 0..5);
+    assert_eq!(dump.entries.last().unwrap().kind, DumpEntryKind::Enum);
+    let dump = hex_dump(&schema, &[1, 2, 3]);
+    assert!(dump.error.is_none());
+    assert_eq!(dump.end, 2);
+    assert!(dump.to_string().ends_with("00000002  03                          trailing bytes (1 bytes)\n"));
+}
+
+#[test]
+fn value_serde_test() {
+    use serde::de::DeserializeSeed;
+    use value::{Value, ValueSeed};
+
+    let tree = BinaryTree::Branch {
+        value: 5,
+        left: Box::new(BinaryTree::Leaf(2)),
+        right: Box::new(BinaryTree::Branch {
+            value: 6,
+            left: Box::new(BinaryTree::Leaf(7)),
+            right: Box::new(BinaryTree::Leaf(20)),
+        }),
+    };
+    let schema = BinaryTree::schema(Default::default());
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    tree.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let value = Value::decode_from(&mut Decoder::new(&mut coder, &mut buf.as_slice()))
+        .unwrap();
+
+    // value serializes like the type it was decoded from
+    let json = serde_json::to_string(&value).unwrap();
+    assert_eq!(json, serde_json::to_string(&tree).unwrap());
+
+    // and deserializes back given the schema, from any format
+    let seed = ValueSeed::new(&schema);
+    let mut de = serde_json::Deserializer::from_str(&json);
+    assert_eq!(seed.deserialize(&mut de).unwrap(), value);
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut read = buf.as_slice();
+    let mut decoder = Decoder::new(&mut coder, &mut read);
+    assert_eq!(seed.deserialize(&mut decoder).unwrap(), value);
+    coder.is_finished_or_err().unwrap();
+
+    // and serializes with the encoder to the same bytes
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf2 = Vec::new();
+    value.serialize(&mut Encoder::new(&mut coder, &mut buf2)).unwrap();
+    coder.is_finished_or_err().unwrap();
+    assert_eq!(buf2, buf);
+    let schema = schema!(struct { (a: u8), (b: enum { X(unit), Y(str) }) });
+    let value = Value::Struct(vec![
+        value::StructValueField {
+            name: "a".into(),
+            value: Value::Scalar(value::ScalarValue::U8(1)),
+        },
+        value::StructValueField {
+            name: "b".into(),
+            value: Value::Enum(value::EnumValue {
+                variant_ord: 1,
+                variant_name: "Y".into(),
+                value: Box::new(Value::Str("hi".into())),
+            }),
+        },
+    ]);
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf = Vec::new();
+    value.serialize(&mut Encoder::new(&mut coder, &mut buf)).unwrap();
+    coder.is_finished_or_err().unwrap();
+    let mut coder = CoderState::new(&schema, CoderStateAlloc::new(), None);
+    let mut buf2 = Vec::new();
+    value.encode_to(&mut Encoder::new(&mut coder, &mut buf2)).unwrap();
+    assert_eq!(buf, buf2);
+
+    // rust values can be converted through any self-describing format
+    let schema = schema!(struct {
+        (id: u64),
+        (tag: option(char)),
+        (data: bytes),
+        (pair: seq(2)(f32)),
+        (kind: enum { A(unit), B(i8) }),
+    });
+    #[derive(Serialize)]
+    enum Kind { _A, B(i8) }
+    let json = serde_json::json!({
+        "pair": [1.5, 2.0],
+        "data": [1, 2, 3],
+        "tag": "x",
+        "id": 3,
+        "kind": Kind::B(-4),
+    });
+    let value = ValueSeed::new(&schema).deserialize(&json).unwrap();
+    assert_eq!(value.encoded_len(&schema).unwrap(), 1 + 2 + 4 + 8 + 2);
+    let Value::Struct(ref fields) = value else { panic!() };
+    assert_eq!(fields[0].name, "id");
+    assert_eq!(fields[0].value, Value::Scalar(value::ScalarValue::U64(3)));
+    assert_eq!(fields[2].value, Value::Bytes(vec![1, 2, 3]));
+
+    // errors
+    let e = ValueSeed::new(&schema)
+        .deserialize(serde_json::json!({ "id": -1 }))
+        .unwrap_err();
+    assert!(e.to_string().contains("invalid value: integer `-1`, expected u64"), "{}", e);
+    let e = ValueSeed::new(&schema)
+        .deserialize(serde_json::json!({ "id": 1 }))
+        .unwrap_err();
+    assert!(e.to_string().contains("missing field \"tag\""), "{}", e);
+}
+
+#[test]
+fn value_check_test() {
+    use binschema::path::{Path, PathSegment};
+    use value::{
+        Value,
+        ScalarValue,
+        StructValueField,
+        EnumValue,
+        MismatchKind,
+    };
+
+    let schema = schema!(struct {
+        (a: u32),
+        (b: seq(2)(str)),
+        (c: tuple { (u8), (option(bool)) }),
+        (d: enum { X(unit), Y(u16) }),
+    });
+    let field = |name: &str, value| StructValueField { name: name.into(), value };
+    let variant = |ord, name: &str, value| Value::Enum(EnumValue {
+        variant_ord: ord,
+        variant_name: name.into(),
+        value: Box::new(value),
+    });
+    let value = Value::Struct(vec![
+        field("a", Value::Scalar(ScalarValue::U32(1))),
+        field("b", Value::FixedLenSeq(vec![Value::Str("x".into()), Value::Str("y".into())])),
+        field("c", Value::Tuple(vec![
+            Value::Scalar(ScalarValue::U8(2)),
+            Value::Option(Some(Box::new(Value::Scalar(ScalarValue::Bool(true))))),
+        ])),
+        field("d", variant(1, "Y", Value::Scalar(ScalarValue::U16(3)))),
+    ]);
+    value.check(&schema).unwrap();
+    value.encoded_len(&schema).unwrap();
+
+    let value = Value::Struct(vec![
+        field("b", Value::FixedLenSeq(vec![Value::Str("x".into())])),
+        field("a", Value::Scalar(ScalarValue::I32(1))),
+        field("c", Value::Tuple(vec![
+            Value::Scalar(ScalarValue::U8(2)),
+            Value::Option(Some(Box::new(Value::Unit))),
+        ])),
+        field("e", Value::Unit),
+    ]);
+    let mismatches = value.check(&schema).unwrap_err();
+    for mismatch in &mismatches {
+        println!("{}", mismatch);
+    }
+    let mismatches = mismatches.into_iter()
+        .map(|mismatch| (mismatch.path, mismatch.kind))
+        .collect::<Vec<_>>();
+    let field = |name: &str| PathSegment::Field(name.into());
+    assert_eq!(mismatches, vec![
+        (
+            Path::new(),
+            MismatchKind::FieldOrder { name: "b".into(), expected: 1, found: 0 },
+        ),
+        (
+            Path::from(vec![field("b")]),
+            MismatchKind::SeqLen { expected: 2, found: 1 },
+        ),
+        (
+            Path::new(),
+            MismatchKind::FieldOrder { name: "a".into(), expected: 0, found: 1 },
+        ),
+        (
+            Path::from(vec![field("a")]),
+            MismatchKind::ScalarType { expected: ScalarType::U32, found: ScalarType::I32 },
+        ),
+        (
+            Path::from(vec![field("c"), PathSegment::TupleElem(1), PathSegment::Some]),
+            MismatchKind::Kind { expected: "bool", found: "unit" },
+        ),
+        (
+            Path::new(),
+            MismatchKind::UnknownField("e".into()),
+        ),
+        (
+            Path::new(),
+            MismatchKind::MissingField("d".into()),
+        ),
+    ]);
+
+    let schema = schema!(enum { X(unit), Y(seq(varlen)(u8)) });
+    let e = variant(0, "Y", Value::FixedLenSeq(vec![])).check(&schema).unwrap_err();
+    assert_eq!(e.len(), 2);
+    assert_eq!(e[0].kind, MismatchKind::VariantOrd { name: "Y".into(), expected: 1, found: 0 });
+    assert_eq!(e[1].to_string(), ".Y: expected var len seq, found fixed len seq");
+    let e = variant(1, "Z", Value::Unit).check(&schema).unwrap_err();
+    assert_eq!(e[0].kind, MismatchKind::UnknownVariant("Z".into()));
+}
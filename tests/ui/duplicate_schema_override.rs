@@ -0,0 +1,11 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+struct Foo {
+    #[schema(bytes)]
+    #[schema(bytes)]
+    data: Vec<u8>,
+}
+
+fn main() {}
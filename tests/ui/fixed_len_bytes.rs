@@ -0,0 +1,10 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+struct Foo {
+    #[schema(bytes, fixed_len = 4)]
+    data: Vec<u8>,
+}
+
+fn main() {}
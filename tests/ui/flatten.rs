@@ -0,0 +1,16 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+struct Inner {
+    a: u32,
+}
+
+#[derive(Serialize, KnownSchema)]
+struct Outer {
+    #[serde(flatten)]
+    inner: Inner,
+    b: u32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+struct Foo {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    items: Vec<u32>,
+}
+
+fn main() {}
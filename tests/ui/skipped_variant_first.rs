@@ -0,0 +1,11 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+enum Foo {
+    #[serde(skip)]
+    Hidden,
+    A(u32),
+}
+
+fn main() {}
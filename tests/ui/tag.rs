@@ -0,0 +1,11 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+#[serde(tag = "type")]
+enum Foo {
+    A { a: u32 },
+    B { b: String },
+}
+
+fn main() {}
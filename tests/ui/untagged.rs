@@ -0,0 +1,11 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+#[derive(Serialize, KnownSchema)]
+#[serde(untagged)]
+enum Foo {
+    A(u32),
+    B(String),
+}
+
+fn main() {}
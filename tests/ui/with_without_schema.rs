@@ -0,0 +1,16 @@
+use binschema::KnownSchema;
+use serde::Serialize;
+
+mod as_string {
+    pub fn serialize<S: serde::Serializer>(n: &u32, s: S) -> Result<S::Ok, S::Error> {
+        s.collect_str(n)
+    }
+}
+
+#[derive(Serialize, KnownSchema)]
+struct Foo {
+    #[serde(with = "as_string")]
+    n: u32,
+}
+
+fn main() {}